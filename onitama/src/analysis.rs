@@ -0,0 +1,116 @@
+#[cfg(feature = "gui")]
+use crate::bot::Value;
+use crate::bot::{MoveAnalysis, Searcher, MAX_DEPTH};
+use crate::cli::AnalyseArgs;
+use crate::config::EngineConfig;
+use crate::eval::AnyEvaluator;
+use crate::game::{Game, Move};
+use crate::messages::move_to_text;
+
+use std::result::Result;
+use std::time::{Duration, Instant};
+
+// Analysis for reviewing games: every legal move with its value,
+// the nodes searched for it and the line the search expects.
+
+// how long the front ends analyse a position when the player asks for it during a replay
+pub const REVIEW_TIME: Duration = Duration::from_secs(3);
+
+// with the searcher the bot plays with, so that the analysis starts from its table
+pub fn analyse(
+    searcher: &mut Searcher<AnyEvaluator>,
+    g: &Game,
+    time: Duration,
+) -> Vec<MoveAnalysis> {
+    searcher.analyse(g, MAX_DEPTH, Some(time))
+}
+
+// the moves of a line written like litama moves, the cards change as it is played
+pub fn pv_texts(g: &Game, pv: &[Move]) -> Vec<String> {
+    let mut g = g.clone();
+    let mut texts = Vec::new();
+    for m in pv {
+        texts.push(move_to_text(m, &g));
+        g = g.take_turn(m);
+    }
+    texts
+}
+
+// short enough to fit on a square of the board
+#[cfg(feature = "gui")]
+pub fn value_label(value: Value) -> String {
+    match value {
+        Value::Win(n) => format!("W{}", n),
+        Value::Loss(n) => format!("L{}", n),
+        Value::Eval(eval) => format!("{:+}", eval),
+    }
+}
+
+pub fn describe(g: &Game, analysis: &[MoveAnalysis]) -> Vec<String> {
+    analysis
+        .iter()
+        .map(|a| {
+            format!(
+                "{:<16} {:<10} nodes {:<10} pv {}",
+                move_to_text(&a.m, g),
+                a.value.to_string(),
+                a.nodes,
+                pv_texts(g, &a.pv).join(" ")
+            )
+        })
+        .collect()
+}
+
+pub fn run(args: AnalyseArgs) -> Result<(), String> {
+    let config = match &args.config {
+        Some(path) => EngineConfig::load(path)?,
+        None => EngineConfig::default(),
+    };
+    let game = args.position;
+    if !game.in_progress {
+        return Err("The game is already over".to_string());
+    }
+    config.evaluator.check_cards(&game)?;
+    println!("{}", game);
+    let start = Instant::now();
+    let mut searcher = config.searcher();
+    let analysis = searcher.analyse(&game, args.depth, args.time);
+    println!(
+        "depth {}, {} nodes in {} ms",
+        searcher.completed_depth,
+        searcher.nodes,
+        start.elapsed().as_millis()
+    );
+    for line in describe(&game, &analysis) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::Searcher;
+
+    #[test]
+    fn test_describe() {
+        let game = Game::from_notation("bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab").unwrap();
+        let analysis = Searcher::new().analyse(&game, 3, None);
+        let lines = describe(&game, &analysis);
+        assert_eq!(lines.len(), game.gen_moves().len());
+        for (line, a) in lines.iter().zip(analysis.iter()) {
+            // each line starts with its move and its line starts with the same move
+            let text = move_to_text(&a.m, &game);
+            assert!(line.starts_with(&text));
+            assert!(line.contains(&format!("pv {}", text)));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn test_value_label() {
+        assert_eq!(value_label(Value::Win(3)), "W3");
+        assert_eq!(value_label(Value::Eval(5)), "+5");
+        assert_eq!(value_label(Value::Eval(-5)), "-5");
+    }
+}
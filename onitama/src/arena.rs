@@ -0,0 +1,286 @@
+use crate::cards::{draw_cards, Card};
+use crate::cli::ArenaArgs;
+use crate::colour::Colour;
+use crate::config::EngineConfig;
+use crate::game::Game;
+use crate::record::GameRecord;
+
+use std::result::Result;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+
+// Plays two engine configurations (see config.rs) against each other.
+// Every opening is a random set of cards that is played twice, once with each engine as red,
+// and results only count once both games of an opening are over.
+// Results are from the point of view of the first engine.
+
+// games that go on for longer are drawn
+pub const MAX_PLIES: usize = 200;
+// the chance of a wrong conclusion from the SPRT, for both kinds of error
+const SPRT_ERROR: f64 = 0.05;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Score {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+pub fn expected_score(elo: f64) -> f64 {
+    1. / (1. + 10f64.powf(-elo / 400.))
+}
+
+pub fn elo_from_score(score: f64) -> f64 {
+    -400. * (1. / score - 1.).log10()
+}
+
+impl Score {
+    pub fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Loss => self.losses += 1,
+            Outcome::Draw => self.draws += 1,
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    // average points per game and the variance of the points of a single game
+    fn mean_variance(&self) -> (f64, f64) {
+        let n = self.games() as f64;
+        let (w, l, d) = (
+            self.wins as f64 / n,
+            self.losses as f64 / n,
+            self.draws as f64 / n,
+        );
+        let mean = w + d / 2.;
+        let variance = w * (1. - mean).powi(2) + l * mean.powi(2) + d * (0.5 - mean).powi(2);
+        (mean, variance)
+    }
+
+    // the elo difference and the margin of its 95% confidence interval
+    pub fn elo(&self) -> (f64, f64) {
+        if self.games() == 0 {
+            return (0., f64::INFINITY);
+        }
+        let (mean, variance) = self.mean_variance();
+        // one engine won every game
+        if variance == 0. && mean != 0.5 {
+            return (elo_from_score(mean), f64::INFINITY);
+        }
+        let margin = 1.96 * (variance / self.games() as f64).sqrt();
+        let low = elo_from_score((mean - margin).max(0.));
+        let high = elo_from_score((mean + margin).min(1.));
+        (elo_from_score(mean), (high - low) / 2.)
+    }
+
+    // generalised SPRT log likelihood ratio of elo1 against elo0,
+    // using the normal approximation of the score
+    pub fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        if self.games() == 0 {
+            return 0.;
+        }
+        let (mean, variance) = self.mean_variance();
+        if variance == 0. {
+            return 0.;
+        }
+        let (s0, s1) = (expected_score(elo0), expected_score(elo1));
+        self.games() as f64 * (s1 - s0) * (2. * mean - s0 - s1) / (2. * variance)
+    }
+}
+
+// the bounds of the log likelihood ratio at which to stop
+pub fn sprt_bounds() -> (f64, f64) {
+    (
+        (SPRT_ERROR / (1. - SPRT_ERROR)).ln(),
+        ((1. - SPRT_ERROR) / SPRT_ERROR).ln(),
+    )
+}
+
+// engines[0] plays red, an engine that can't start or can't move loses
+pub fn play_game(engines: [&EngineConfig; 2], cards: Vec<Card>) -> GameRecord {
+    let mut game = Game::from_cards(cards);
+    let mut record = GameRecord::new(&game, &engines[0].name, &engines[1].name);
+    let mut players = [engines[0].player(), engines[1].player()];
+    while game.in_progress && record.moves.len() < MAX_PLIES {
+        let i = match game.colour {
+            Colour::Red => 0,
+            Colour::Blue => 1,
+        };
+        let m = match &mut players[i] {
+            Ok(player) => engines[i].best_move(player, &game),
+            Err(err) => Err(err.clone()),
+        };
+        match m {
+            Ok(m) => {
+                record.push(&m, &game);
+                game = game.take_turn(&m);
+            }
+            Err(err) => {
+                println!("{} forfeits: {}", engines[i].name, err);
+                record.forfeit(game.colour);
+                return record;
+            }
+        }
+    }
+    record.finish(&game);
+    record
+}
+
+pub fn run(args: ArenaArgs) -> Result<(), String> {
+    let engines = [
+        EngineConfig::load(&args.configs[0])?,
+        EngineConfig::load(&args.configs[1])?,
+    ];
+    let pairs = args.games.div_ceil(2);
+    println!(
+        "{} vs {}, {} games on {} threads",
+        engines[0].name,
+        engines[1].name,
+        2 * pairs,
+        args.threads
+    );
+
+    let engines = Arc::new(engines);
+    let next = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = channel();
+    let handles: Vec<_> = (0..args.threads)
+        .map(|_| {
+            let engines = Arc::clone(&engines);
+            let next = Arc::clone(&next);
+            let stop = Arc::clone(&stop);
+            let tx = tx.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let pair = next.fetch_add(1, Ordering::Relaxed);
+                    if pair >= pairs {
+                        break;
+                    }
+                    // both games of the opening are reported together, so that
+                    // the arena never stops with only one side of it played
+                    let cards = draw_cards();
+                    let outcomes = [Colour::Red, Colour::Blue].map(|first_colour| {
+                        let (red, blue) = match first_colour {
+                            Colour::Red => (&engines[0], &engines[1]),
+                            Colour::Blue => (&engines[1], &engines[0]),
+                        };
+                        match play_game([red, blue], cards.clone()).winner {
+                            None => Outcome::Draw,
+                            Some(winner) if winner == first_colour => Outcome::Win,
+                            Some(_) => Outcome::Loss,
+                        }
+                    });
+                    if tx.send(outcomes).is_err() {
+                        return;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let (lower, upper) = sprt_bounds();
+    let mut score = Score::default();
+    for outcomes in rx {
+        for &outcome in outcomes.iter() {
+            score.add(outcome);
+        }
+        let (elo, margin) = score.elo();
+        let mut line = format!(
+            "game {}: +{} -{} ={}, elo {:.1} +- {:.1}",
+            score.games(),
+            score.wins,
+            score.losses,
+            score.draws,
+            elo,
+            margin
+        );
+        if let Some((elo0, elo1)) = args.sprt {
+            let llr = score.llr(elo0, elo1);
+            line.push_str(&format!(", llr {:.2} ({:.2}, {:.2})", llr, lower, upper));
+            if llr <= lower || llr >= upper {
+                stop.store(true, Ordering::Relaxed);
+                println!("{}", line);
+                if llr >= upper {
+                    println!("H1 accepted: elo >= {}", elo1);
+                } else {
+                    println!("H0 accepted: elo <= {}", elo0);
+                }
+                break;
+            }
+        }
+        println!("{}", line);
+    }
+    for handle in handles {
+        handle
+            .join()
+            .map_err(|_| "An arena thread panicked".to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elo() {
+        let even = Score {
+            wins: 40,
+            losses: 40,
+            draws: 20,
+        };
+        let (elo, margin) = even.elo();
+        assert!(elo.abs() < 1e-9);
+        assert!(margin > 0. && margin < 100.);
+        let better = Score {
+            wins: 75,
+            losses: 25,
+            draws: 0,
+        };
+        assert!((better.elo().0 - 190.85).abs() < 0.01);
+        // more games make the error bars smaller
+        let more = Score {
+            wins: 750,
+            losses: 250,
+            draws: 0,
+        };
+        assert!(more.elo().1 < better.elo().1);
+    }
+
+    #[test]
+    fn test_sprt() {
+        let (lower, upper) = sprt_bounds();
+        let better = Score {
+            wins: 600,
+            losses: 400,
+            draws: 0,
+        };
+        assert!(better.llr(0., 10.) > upper);
+        let worse = Score {
+            wins: 400,
+            losses: 600,
+            draws: 0,
+        };
+        assert!(worse.llr(0., 10.) < lower);
+        let few = Score {
+            wins: 3,
+            losses: 2,
+            draws: 1,
+        };
+        let llr = few.llr(0., 10.);
+        assert!(lower < llr && llr < upper);
+    }
+}
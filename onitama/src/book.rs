@@ -0,0 +1,291 @@
+use crate::cards::{draw_cards, Card, BASE_CARDS};
+use crate::cli::{BookArgs, BookDeals};
+use crate::config::EngineConfig;
+use crate::game::{Game, Move};
+use crate::messages::{move_to_text, parse_move};
+use crate::transposition::StoredMove;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::result::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+
+// An opening book, saved as JSON:
+//
+// {
+//   "positions": {
+//     "5851027421846331372": {
+//       "position": "bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab",
+//       "move": "boar c5c4",
+//       "value": "eval 3",
+//       "depth": 9
+//     }
+//   }
+// }
+//
+// Positions are keyed by their hash (see zobrist.rs), which includes the cards,
+// so the openings of every card set live side by side in one book. Symmetric positions
+// share the key of canonical_hash (see symmetry.rs) and so one entry.
+// `position` is in the position notation and `move` is written like a litama move,
+// the move is turned into the same move of a symmetric position when probing.
+// `value` and `depth` are what the search found, they are only for reading.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Book {
+    pub positions: BTreeMap<u64, BookEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BookEntry {
+    pub position: String,
+    #[serde(rename = "move")]
+    pub text: String,
+    pub value: String,
+    pub depth: u8,
+}
+
+impl Book {
+    pub fn load(path: &Path) -> Result<Book, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // the book move, checked against the legal moves in case two positions share a hash
+    pub fn probe(&self, g: &Game) -> Option<Move> {
+        let (hash, symmetry) = g.canonical_hash();
+        let entry = self.positions.get(&hash)?;
+        let position = Game::from_notation(&entry.position).ok()?;
+        let m = parse_move(&entry.text, &position).ok()?;
+        // through the position that was hashed to this one
+        let (_, entry_symmetry) = position.canonical_hash();
+        let stored = StoredMove::new(&m, &position)
+            .transform(entry_symmetry)
+            .transform(symmetry);
+        g.gen_moves().into_iter().find(|m| stored.matches(m, g))
+    }
+}
+
+// every deal of five cards of the base game, red's cards and blue's cards in any order are the same deal
+fn all_deals() -> Vec<Vec<Card>> {
+    let mut deals = Vec::new();
+    for set in 0u32..1 << BASE_CARDS {
+        if set.count_ones() != 5 {
+            continue;
+        }
+        let cards: Vec<Card> = (0..BASE_CARDS)
+            .filter(|&i| set & 1 << i != 0)
+            .map(Card::from_num)
+            .collect();
+        for table in 0..5 {
+            let rest: Vec<Card> = (0..5).filter(|&i| i != table).map(|i| cards[i]).collect();
+            for a in 0..4 {
+                for b in a + 1..4 {
+                    let blue: Vec<Card> = (0..4)
+                        .filter(|&i| i != a && i != b)
+                        .map(|i| rest[i])
+                        .collect();
+                    // Game::from_cards takes blue, blue, red, red, table
+                    deals.push(vec![blue[0], blue[1], rest[b], rest[a], cards[table]]);
+                }
+            }
+        }
+    }
+    deals
+}
+
+// the positions less than `plies` moves from the start, with every move played
+fn openings(start: Game, plies: usize) -> Vec<Game> {
+    let mut seen = HashSet::new();
+    let mut positions = Vec::new();
+    let mut layer = vec![start];
+    for _ in 0..plies {
+        let mut next_layer = Vec::new();
+        for game in layer {
+            if !game.in_progress || !seen.insert(game.canonical_hash().0) {
+                continue;
+            }
+            for m in game.gen_moves() {
+                next_layer.push(game.take_turn(&m));
+            }
+            positions.push(game);
+        }
+        layer = next_layer;
+    }
+    positions
+}
+
+pub fn run(args: BookArgs) -> Result<(), String> {
+    let config = match &args.config {
+        Some(path) => EngineConfig::load(path)?,
+        None => EngineConfig::default(),
+    };
+    // add to an existing book
+    let mut book = if args.path.exists() {
+        Book::load(&args.path)?
+    } else {
+        Book::default()
+    };
+    let deals = match args.deals {
+        BookDeals::All => all_deals(),
+        BookDeals::Random(n) => (0..n).map(|_| draw_cards()).collect(),
+    };
+    println!(
+        "searching {} deals {} plies deep on {} threads",
+        deals.len(),
+        args.plies,
+        args.threads
+    );
+
+    let known: Arc<HashSet<u64>> = Arc::new(book.positions.keys().copied().collect());
+    let deals = Arc::new(deals);
+    let config = Arc::new(config);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = channel();
+    let handles: Vec<_> = (0..args.threads)
+        .map(|_| {
+            let (known, deals, config, next) = (
+                Arc::clone(&known),
+                Arc::clone(&deals),
+                Arc::clone(&config),
+                Arc::clone(&next),
+            );
+            let tx = tx.clone();
+            let plies = args.plies;
+            thread::spawn(move || {
+                let mut searcher = config.searcher();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= deals.len() {
+                        break;
+                    }
+                    let start = Game::from_cards(deals[i].clone());
+                    let mut entries = Vec::new();
+                    for game in openings(start, plies) {
+                        let (hash, _) = game.canonical_hash();
+                        if known.contains(&hash) {
+                            continue;
+                        }
+                        let (value, m) = config.search(&mut searcher, &game);
+                        entries.push((
+                            hash,
+                            BookEntry {
+                                position: game.to_notation(),
+                                text: move_to_text(&m, &game),
+                                value: value.to_string(),
+                                depth: searcher.completed_depth,
+                            },
+                        ));
+                    }
+                    if tx.send(entries).is_err() {
+                        return;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    for (i, entries) in rx.iter().enumerate() {
+        let added = entries.len();
+        book.positions.extend(entries);
+        println!(
+            "deal {}/{}: {} positions, {} in the book",
+            i + 1,
+            deals.len(),
+            added,
+            book.positions.len()
+        );
+    }
+    for handle in handles {
+        handle
+            .join()
+            .map_err(|_| "A book thread panicked".to_string())?;
+    }
+    book.save(&args.path)?;
+    println!("saved to {}", args.path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::Searcher;
+    use crate::symmetry::Symmetry;
+
+    #[test]
+    fn test_all_deals() {
+        let deals = all_deals();
+        // 4368 sets of cards, 5 table cards and 6 ways to split the rest
+        assert_eq!(deals.len(), 4368 * 30);
+        let starts: HashSet<u64> = deals
+            .iter()
+            .map(|cards| Game::from_cards(cards.clone()).hash())
+            .collect();
+        assert_eq!(starts.len(), deals.len());
+    }
+
+    #[test]
+    fn test_probe() {
+        let cards = vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ];
+        let start = Game::from_cards(cards.clone());
+        let positions = openings(start.clone(), 2);
+        // the start and every position after one move
+        assert_eq!(positions.len(), 1 + start.gen_moves().len());
+
+        let mut book = Book::default();
+        for game in positions.iter() {
+            let (value, m) = Searcher::new().search(game, 2, None);
+            book.positions.insert(
+                game.canonical_hash().0,
+                BookEntry {
+                    position: game.to_notation(),
+                    text: move_to_text(&m, game),
+                    value: value.to_string(),
+                    depth: 2,
+                },
+            );
+        }
+        let json = serde_json::to_string(&book).unwrap();
+        let book: Book = serde_json::from_str(&json).unwrap();
+        for game in positions.iter() {
+            let m = book.probe(game).unwrap();
+            assert!(game.gen_moves().contains(&m));
+        }
+        // the same position two moves in isn't in the book
+        let m = start.gen_moves()[0];
+        let later = start.take_turn(&m);
+        let later = later.take_turn(&later.gen_moves()[0]);
+        assert_eq!(book.probe(&later), None);
+        // the hands are sets, so swapping the cards in a hand finds the same entry
+        let mut swapped = start.clone();
+        swapped.my.cards.swap(0, 1);
+        assert_eq!(
+            move_to_text(&book.probe(&swapped).unwrap(), &swapped),
+            move_to_text(&book.probe(&start).unwrap(), &start)
+        );
+        // the deal with the mirrored cards plays the mirrored move
+        let mirror = Symmetry {
+            rotate: false,
+            mirror: true,
+        };
+        let mirrored = start.transform(mirror);
+        assert_ne!(mirrored.hash(), start.hash());
+        let m = book.probe(&start).unwrap();
+        let expected = StoredMove::new(&m, &start).transform(mirror);
+        assert!(expected.matches(&book.probe(&mirrored).unwrap(), &mirrored));
+    }
+}
@@ -1,60 +1,45 @@
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use bitwise::TestBit;
 
-use crate::cards::{shift_bitmap, BitIter};
-use crate::game::{Game, Move};
-
-const PIECE_WEIGHT: i64 = 10;
-const SQUARE_WEIGHT: i64 = 1;
-const CHECK_WEIGHT: i64 = 10;
-
-// positive is good for me, negative is good for other
-pub fn game_eval(g: &Game) -> i64 {
-    let mut my_control = 0u32;
-    let my_card = g.my.cards[0].get_move(g.colour) | g.my.cards[1].get_move(g.colour);
-    for pos in BitIter(g.my.pieces) {
-        my_control |= shift_bitmap(my_card, pos);
-    }
-    let mut other_control = 0u32;
-    let other_card =
-        g.other.cards[0].get_move(g.colour.next()) | g.other.cards[1].get_move(g.colour.next());
-    for pos in BitIter(g.other.pieces) {
-        other_control |= shift_bitmap(other_card, pos);
-    }
-    let mut checks = 0i64;
-    if my_control.test_bit(g.other.king) {
-        checks += 1
-    }
-    if other_control.test_bit(g.my.king) {
-        checks -= 1
-    }
-    if shift_bitmap(my_card, g.my.king as u32).test_bit(g.goal()) {
-        checks += 1
-    }
-    if shift_bitmap(other_card, g.other.king as u32).test_bit(24 - g.goal()) {
-        checks -= 1
-    }
-    let square_diff = my_control.count_ones() as i64 - other_control.count_ones() as i64;
-    let piece_diff = g.my.pieces.count_ones() as i64 - g.other.pieces.count_ones() as i64;
-    PIECE_WEIGHT * piece_diff + SQUARE_WEIGHT * square_diff + checks * CHECK_WEIGHT
-}
+use crate::book::Book;
+use crate::eval::{Evaluator, Heuristic};
+use crate::game::{Game, Move, Moves};
+use crate::tablebase::Tablebase;
+use crate::transposition::{Bound, Entry, StoredMove, TranspositionTable};
+use crate::zobrist::SymmetricHashes;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-enum Value {
+pub enum Value {
     Win(u8),
     Loss(u8),
     Eval(i64),
 }
 
 impl Value {
-    fn next(self) -> Self {
+    pub fn next(self) -> Self {
         match self {
             Value::Win(x) => Value::Loss(x + 1),
             Value::Loss(x) => Value::Win(x + 1),
             Value::Eval(y) => Value::Eval(-y),
         }
     }
+
+    // inverse of next, used to pass the search window down to a child
+    // Loss(0) and Win(0) act as the infinite bounds
+    fn prev(self) -> Self {
+        match self {
+            Value::Win(x) => Value::Loss(x.saturating_sub(1)),
+            Value::Loss(x) => Value::Win(x.saturating_sub(1)),
+            Value::Eval(y) => Value::Eval(-y),
+        }
+    }
 }
 
 impl Ord for Value {
@@ -85,47 +70,559 @@ impl PartialOrd for Value {
     }
 }
 
-const EVAL_FOR_DEEPENING: f64 = 15.; // decrease this for more aggressive pruning
-const EVAL_OFFSET: i64 = 6; // increase this to make high depth faster
-fn get_value(g: &Game, nodes: f64, eval: i64) -> Value {
-    if !g.in_progress {
-        Value::Loss(0)
-    } else {
-        let new_eval = game_eval(&g);
-        let nodes = nodes / ((new_eval + eval + EVAL_OFFSET) as f64 / EVAL_FOR_DEEPENING).exp();
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Win(x) => write!(f, "win {}", x),
+            Value::Loss(x) => write!(f, "loss {}", x),
+            Value::Eval(y) => write!(f, "eval {}", y),
+        }
+    }
+}
 
-        if nodes <= 1. {
-            Value::Eval(new_eval)
+// try the move from the table first, then wins, then captures
+fn order_moves(g: &Game, tt_move: Option<StoredMove>) -> Moves {
+    let mut moves = g.gen_moves();
+    moves.sort_by_key(|m| {
+        if tt_move.is_some_and(|stored| stored.matches(m, g)) {
+            0
+        } else if m.to == g.other.king || (m.from == g.my.king && m.to == g.goal()) {
+            1
+        } else if g.other.pieces.test_bit(m.to) && !g.spirit.test_bit(m.from) {
+            2
         } else {
-            let moves = g.gen_moves();
-            let nodes = nodes / moves.len() as f64;
-            moves
-                .iter()
-                .map(|m| get_value(&g.take_turn(m), nodes, new_eval))
-                .min()
-                .unwrap()
-                .next()
+            3
+        }
+    });
+    moves
+}
+
+#[cfg(not(test))]
+const TABLE_SIZE: usize = 1 << 20;
+// the tests make a searcher for almost every position they search
+#[cfg(test)]
+const TABLE_SIZE: usize = 1 << 16;
+
+const NODES_PER_CHECK: u64 = 1024;
+
+// sent after every completed iteration
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub depth: u8,
+    pub value: Value,
+    pub nodes: u64,
+    pub time: Duration,
+    pub pv: Vec<Move>,
+}
+
+// one root move of an analysis
+#[derive(Clone, Debug)]
+pub struct MoveAnalysis {
+    pub m: Move,
+    pub value: Value,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+}
+
+// the root moves of one iteration, shared by the threads searching them
+struct RootSplit {
+    game: Game,
+    hashes: SymmetricHashes,
+    depth: u8,
+    moves: Moves,
+    next: AtomicUsize,
+    // the best value so far and the index of its move
+    best: Mutex<(Value, usize)>,
+}
+
+// threads that live as long as their searcher and help it with the root moves
+struct Workers<E: Evaluator> {
+    jobs: Vec<Sender<(Searcher<E>, Arc<RootSplit>)>>,
+    // the nodes a worker searched and whether it was aborted
+    done: Receiver<(u64, bool)>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl<E: Evaluator> Workers<E> {
+    fn start(count: usize) -> Workers<E> {
+        let (tx, done) = channel();
+        let (jobs, handles) = (0..count)
+            .map(|_| {
+                let (jobs, rx) = channel::<(Searcher<E>, Arc<RootSplit>)>();
+                let tx = tx.clone();
+                let handle = thread::spawn(move || {
+                    for (mut helper, split) in rx {
+                        helper.search_split(&split);
+                        if tx.send((helper.nodes, helper.aborted)).is_err() {
+                            break;
+                        }
+                    }
+                });
+                (jobs, handle)
+            })
+            .unzip();
+        Workers {
+            jobs,
+            done,
+            handles,
+        }
+    }
+}
+
+impl<E: Evaluator> Drop for Workers<E> {
+    fn drop(&mut self) {
+        // the workers finish once there are no more jobs to wait for
+        self.jobs.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+// generic over how positions at the end of the search are scored
+pub struct Searcher<E: Evaluator = Heuristic> {
+    pub evaluator: E,
+    // shared with the workers
+    table: Arc<TranspositionTable>,
+    pub nodes: u64,
+    pub completed_depth: u8,
+    // set from another thread to cancel the search
+    pub stop: Arc<AtomicBool>,
+    pub threads: usize,
+    pub node_limit: Option<u64>,
+    // exact values for endgames
+    pub tablebase: Option<Arc<Tablebase>>,
+    workers: Option<Workers<E>>,
+    deadline: Option<Instant>,
+    aborted: bool,
+}
+
+// the program searches with an engine config (see config.rs), the tests with the default heuristic
+#[cfg(test)]
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher::with_evaluator(Heuristic::default())
+    }
+}
+
+impl<E: Evaluator> Searcher<E> {
+    pub fn with_evaluator(evaluator: E) -> Searcher<E> {
+        Searcher {
+            evaluator,
+            table: Arc::new(TranspositionTable::new(TABLE_SIZE)),
+            nodes: 0,
+            completed_depth: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: 1,
+            node_limit: None,
+            tablebase: None,
+            workers: None,
+            deadline: None,
+            aborted: false,
+        }
+    }
+
+    // searches with the same table, limits and evaluator on another thread
+    fn helper(&self) -> Searcher<E> {
+        Searcher {
+            evaluator: self.evaluator.clone(),
+            table: Arc::clone(&self.table),
+            nodes: 0,
+            completed_depth: self.completed_depth,
+            stop: Arc::clone(&self.stop),
+            threads: 1,
+            node_limit: self
+                .node_limit
+                .map(|n| n.saturating_sub(self.nodes) / self.threads as u64),
+            tablebase: self.tablebase.clone(),
+            workers: None,
+            deadline: self.deadline,
+            aborted: false,
+        }
+    }
+
+    fn should_stop(&self) -> bool {
+        let out_of_time = self.deadline.is_some_and(|d| Instant::now() >= d);
+        let out_of_nodes = self.node_limit.is_some_and(|n| self.nodes >= n);
+        out_of_time || out_of_nodes || self.stop.load(AtomicOrdering::Relaxed)
+    }
+
+    // the first iteration always finishes so that there is a move to play
+    fn check_abort(&mut self) {
+        if self.completed_depth > 0 && self.nodes.is_multiple_of(NODES_PER_CHECK) {
+            self.aborted = self.should_stop();
+        }
+    }
+
+    // the table shares the entries of the positions the evaluator can't tell apart
+    fn root_hashes(&self, g: &Game) -> SymmetricHashes {
+        SymmetricHashes::new(g, self.evaluator.mirror_symmetric())
+    }
+
+    // negamax with alpha-beta pruning, values are from the perspective of the player to move
+    fn get_value(
+        &mut self,
+        g: &Game,
+        hashes: SymmetricHashes,
+        depth: u8,
+        mut alpha: Value,
+        beta: Value,
+    ) -> Value {
+        self.nodes += 1;
+        self.check_abort();
+        if self.aborted {
+            return Value::Eval(0);
+        }
+        if !g.in_progress {
+            return Value::Loss(0);
+        }
+        if let Some(value) = self.tablebase.as_ref().and_then(|t| t.probe(g)) {
+            return value;
+        }
+        if depth == 0 {
+            return Value::Eval(self.evaluator.evaluate_hashed(g, hashes.hash()));
+        }
+
+        // symmetric positions share an entry, its best move is for the position that was hashed
+        let (hash, symmetry) = hashes.canonical();
+        let entry = self.table.probe(hash);
+        if let Some(entry) = entry {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.value,
+                    Bound::Lower if entry.value >= beta => return entry.value,
+                    Bound::Upper if entry.value <= alpha => return entry.value,
+                    _ => {}
+                }
+            }
+        }
+
+        let original_alpha = alpha;
+        let mut best = Value::Loss(0);
+        let mut best_move = None;
+        let tt_move = entry
+            .and_then(|e| e.best_move)
+            .map(|m| m.transform(symmetry));
+        for m in order_moves(g, tt_move).iter() {
+            let value = self
+                .get_child_value(g, hashes, m, depth, beta.prev(), alpha.prev())
+                .next();
+            if self.aborted {
+                // the result is incomplete, so don't store it
+                return best;
+            }
+            if value > best {
+                best = value;
+                best_move = Some(StoredMove::new(m, g));
+            }
+            if value > alpha {
+                alpha = value;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.store(Entry {
+            hash,
+            depth,
+            bound,
+            value: best,
+            best_move: best_move.map(|m: StoredMove| m.transform(symmetry)),
+        });
+        best
+    }
+
+    // the value of the position after the move, from the perspective of the other player
+    fn get_child_value(
+        &mut self,
+        g: &Game,
+        hashes: SymmetricHashes,
+        m: &Move,
+        depth: u8,
+        alpha: Value,
+        beta: Value,
+    ) -> Value {
+        let next = g.take_turn(m);
+        let next_hashes = hashes.after(g, &next);
+        self.evaluator
+            .make_move(g, hashes.hash(), &next, next_hashes.hash());
+        let value = self.get_value(&next, next_hashes, depth - 1, alpha, beta);
+        self.evaluator.unmake_move();
+        value
+    }
+
+    fn search_root(&mut self, g: &Game, depth: u8) -> Option<(Value, Move)> {
+        let hashes = self.root_hashes(g);
+        let (hash, symmetry) = hashes.canonical();
+        let tt_move = self
+            .table
+            .probe(hash)
+            .and_then(|e| e.best_move)
+            .map(|m| m.transform(symmetry));
+        let mut alpha = Value::Loss(0);
+        let mut best_move = None;
+        for m in order_moves(g, tt_move) {
+            let value = self
+                .get_child_value(g, hashes, &m, depth, Value::Loss(0), alpha.prev())
+                .next();
+            if self.aborted {
+                return None;
+            }
+            if value > alpha {
+                alpha = value;
+                best_move = Some(m);
+            }
+        }
+        let best_move = best_move.unwrap();
+        self.table.store(Entry {
+            hash,
+            depth,
+            bound: Bound::Exact,
+            value: alpha,
+            best_move: Some(StoredMove::new(&best_move, g).transform(symmetry)),
+        });
+        Some((alpha, best_move))
+    }
+
+    // take the next unsearched root move until there are none left,
+    // and search it against the best value found so far
+    fn search_split(&mut self, split: &RootSplit) {
+        loop {
+            let i = split.next.fetch_add(1, AtomicOrdering::Relaxed);
+            if i >= split.moves.len() {
+                break;
+            }
+            let alpha = split.best.lock().unwrap().0;
+            let value = self
+                .get_child_value(
+                    &split.game,
+                    split.hashes,
+                    &split.moves[i],
+                    split.depth,
+                    Value::Loss(0),
+                    alpha.prev(),
+                )
+                .next();
+            if self.aborted {
+                break;
+            }
+            let mut best = split.best.lock().unwrap();
+            if value > best.0 {
+                *best = (value, i);
+            }
+        }
+    }
+
+    // root splitting over the shared table, the first move is searched alone to get
+    // a good bound, then this thread and the workers share out the other root moves
+    fn search_root_parallel(&mut self, g: &Game, depth: u8) -> Option<(Value, Move)> {
+        let hashes = self.root_hashes(g);
+        let (hash, symmetry) = hashes.canonical();
+        let tt_move = self
+            .table
+            .probe(hash)
+            .and_then(|e| e.best_move)
+            .map(|m| m.transform(symmetry));
+        let moves = order_moves(g, tt_move);
+        let first = self
+            .get_child_value(g, hashes, &moves[0], depth, Value::Loss(0), Value::Win(0))
+            .next();
+        if self.aborted {
+            return None;
+        }
+        let split = Arc::new(RootSplit {
+            game: g.clone(),
+            hashes,
+            depth,
+            moves,
+            next: AtomicUsize::new(1),
+            best: Mutex::new((first, 0)),
+        });
+
+        let count = self.threads - 1;
+        if self.workers.as_ref().map(|w| w.jobs.len()) != Some(count) {
+            self.workers = Some(Workers::start(count));
+        }
+        let helpers: Vec<_> = (0..count).map(|_| self.helper()).collect();
+        let workers = self.workers.as_ref().unwrap();
+        for (jobs, helper) in workers.jobs.iter().zip(helpers) {
+            jobs.send((helper, Arc::clone(&split))).unwrap();
+        }
+        self.search_split(&split);
+        let workers = self.workers.as_ref().unwrap();
+        for _ in 0..count {
+            let (nodes, aborted) = workers.done.recv().unwrap();
+            self.nodes += nodes;
+            self.aborted |= aborted;
+        }
+        if self.aborted {
+            return None;
+        }
+
+        let (value, index) = *split.best.lock().unwrap();
+        let best_move = split.moves[index];
+        self.table.store(Entry {
+            hash,
+            depth,
+            bound: Bound::Exact,
+            value,
+            best_move: Some(StoredMove::new(&best_move, g).transform(symmetry)),
+        });
+        Some((value, best_move))
+    }
+
+    // follow the best moves stored in the table
+    pub fn principal_variation(&self, g: &Game, max_len: u8) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut g = g.clone();
+        while pv.len() < max_len as usize && g.in_progress {
+            let (hash, symmetry) = self.root_hashes(&g).canonical();
+            let stored = self
+                .table
+                .probe(hash)
+                .and_then(|e| e.best_move)
+                .map(|m| m.transform(symmetry));
+            let m = match stored.and_then(|s| g.gen_moves().into_iter().find(|m| s.matches(m, &g)))
+            {
+                Some(m) => m,
+                None => break,
+            };
+            g = g.take_turn(&m);
+            pv.push(m);
+        }
+        pv
+    }
+
+    // like search, but every root move gets a full window so that its value is exact
+    // rather than a bound, returns the moves of the last completed iteration from best to worst
+    pub fn analyse(
+        &mut self,
+        g: &Game,
+        max_depth: u8,
+        time: Option<Duration>,
+    ) -> Vec<MoveAnalysis> {
+        self.nodes = 0;
+        self.completed_depth = 0;
+        self.deadline = time.map(|t| Instant::now() + t);
+        self.aborted = false;
+
+        let hashes = self.root_hashes(g);
+        let mut result = Vec::new();
+        for depth in 1..=max_depth {
+            if depth > 1 && self.should_stop() {
+                break;
+            }
+            let mut analysis = Vec::new();
+            for m in order_moves(g, None) {
+                let nodes = self.nodes;
+                let value = self
+                    .get_child_value(g, hashes, &m, depth, Value::Loss(0), Value::Win(0))
+                    .next();
+                if self.aborted {
+                    break;
+                }
+                let mut pv = vec![m];
+                pv.extend(self.principal_variation(&g.take_turn(&m), depth - 1));
+                analysis.push(MoveAnalysis {
+                    m,
+                    value,
+                    nodes: self.nodes - nodes,
+                    pv,
+                });
+            }
+            if self.aborted {
+                break;
+            }
+            analysis.sort_by_key(|a| Reverse(a.value));
+            result = analysis;
+            self.completed_depth = depth;
+            // searching deeper can't change proven values
+            if result.iter().all(|a| !matches!(a.value, Value::Eval(_))) {
+                break;
+            }
+        }
+        result
+    }
+
+    pub fn search(&mut self, g: &Game, max_depth: u8, time: Option<Duration>) -> (Value, Move) {
+        self.search_reporting(g, max_depth, time, |_| {})
+    }
+
+    // iterative deepening until the depth, time or nodes run out, or the search is stopped
+    // returns the result of the last completed iteration
+    pub fn search_reporting(
+        &mut self,
+        g: &Game,
+        max_depth: u8,
+        time: Option<Duration>,
+        mut report: impl FnMut(&Report),
+    ) -> (Value, Move) {
+        let start = Instant::now();
+        self.nodes = 0;
+        self.completed_depth = 0;
+        self.deadline = time.map(|t| Instant::now() + t);
+        self.aborted = false;
+
+        let mut result = None;
+        for depth in 1..=max_depth {
+            if depth > 1 && self.should_stop() {
+                break;
+            }
+            let result_at_depth = if self.threads > 1 {
+                self.search_root_parallel(g, depth)
+            } else {
+                self.search_root(g, depth)
+            };
+            match result_at_depth {
+                Some(r) => result = Some(r),
+                None => break,
+            }
+            self.completed_depth = depth;
+            report(&Report {
+                depth,
+                value: result.as_ref().unwrap().0,
+                nodes: self.nodes,
+                time: start.elapsed(),
+                pv: self.principal_variation(g, depth),
+            });
+            // stop early once the result is proven
+            if !matches!(result, Some((Value::Eval(_), _))) {
+                break;
+            }
         }
+        result.unwrap()
     }
 }
 
-const SEARCH_NODES: f64 = 100000000.;
-pub fn get_move(g: &Game) -> Move {
-    let moves = g.gen_moves();
-    let nodes = SEARCH_NODES / moves.len() as f64;
-    let eval = game_eval(g);
-    let (eval, m) = moves
-        .into_iter()
-        .map(|m| (get_value(&g.take_turn(&m), nodes, eval), m))
-        .min_by_key(|x| x.0)
-        .unwrap();
-    println!("{:?}", eval.next());
-    m
+pub const MAX_DEPTH: u8 = 64;
+// with the report of the last completed iteration, or none for a book move
+// the searcher is kept for the whole game so its table and workers carry over between moves
+pub fn get_move<E: Evaluator>(
+    g: &Game,
+    searcher: &mut Searcher<E>,
+    time: Duration,
+    book: Option<&Book>,
+) -> (Move, Option<Report>) {
+    if let Some(m) = book.and_then(|book| book.probe(g)) {
+        return (m, None);
+    }
+    let mut last = None;
+    let (_, m) = searcher.search_reporting(g, MAX_DEPTH, Some(time), |r| last = Some(r.clone()));
+    (m, last)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cards::Card;
 
     #[test]
     fn test_value() {
@@ -138,4 +635,193 @@ mod tests {
         assert!(Win(10) > Eval(20));
         assert!(Eval(10) > Eval(-10));
     }
+
+    #[test]
+    fn test_value_prev() {
+        use Value::*;
+
+        for &value in [Win(3), Loss(4), Eval(-7), Eval(0)].iter() {
+            assert_eq!(value.next().prev(), value);
+        }
+        assert_eq!(Win(0).prev(), Loss(0));
+        assert_eq!(Loss(0).prev(), Win(0));
+    }
+
+    fn minimax(g: &Game, depth: u8) -> Value {
+        if !g.in_progress {
+            Value::Loss(0)
+        } else if depth == 0 {
+            Value::Eval(Heuristic::default().evaluate(g))
+        } else {
+            g.gen_moves()
+                .iter()
+                .map(|m| minimax(&g.take_turn(m), depth - 1).next())
+                .max()
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn test_search_matches_minimax() {
+        let cards = vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ];
+        let mut game = Game::from_cards(cards);
+        // play into the middlegame to get some captures
+        for _ in 0..6 {
+            let m = Searcher::new().search(&game, 3, None).1;
+            game = game.take_turn(&m);
+        }
+        for depth in 1..=4 {
+            let mut searcher = Searcher::new();
+            assert_eq!(searcher.search(&game, depth, None).0, minimax(&game, depth));
+        }
+    }
+
+    #[test]
+    fn test_analyse_matches_minimax() {
+        let cards = vec![
+            Card::Tiger,
+            Card::Dragon,
+            Card::Frog,
+            Card::Rabbit,
+            Card::Cobra,
+        ];
+        let mut game = Game::from_cards(cards);
+        for _ in 0..4 {
+            let m = Searcher::new().search(&game, 3, None).1;
+            game = game.take_turn(&m);
+        }
+        let analysis = Searcher::new().analyse(&game, 3, None);
+        assert_eq!(analysis.len(), game.gen_moves().len());
+        for a in analysis.iter() {
+            assert_eq!(a.value, minimax(&game.take_turn(&a.m), 2).next());
+            assert_eq!(a.pv[0], a.m);
+            assert!(a.nodes > 0);
+        }
+        assert!(analysis
+            .windows(2)
+            .all(|pair| pair[0].value >= pair[1].value));
+        assert_eq!(analysis[0].value, Searcher::new().search(&game, 3, None).0);
+    }
+
+    #[test]
+    fn test_search_finds_win() {
+        let cards = vec![
+            Card::Crab,
+            Card::Elephant,
+            Card::Horse,
+            Card::Tiger,
+            Card::Boar,
+        ];
+        let mut game = Game::from_cards(cards);
+        // red tiger can jump straight onto the blue king
+        game.my.pieces = board!(
+            0 0 0 0 0
+            0 0 0 0 0
+            0 0 1 0 0
+            0 0 0 0 0
+            0 0 1 0 0
+        );
+        game.my.king = 22;
+        let (value, m) = Searcher::new().search(&game, 5, None);
+        assert_eq!(value, Value::Win(1));
+        assert_eq!(m.to, game.other.king);
+    }
+
+    #[test]
+    fn test_search_time_limit() {
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        let mut searcher = Searcher::new();
+        let start = Instant::now();
+        searcher.search(&game, MAX_DEPTH, Some(Duration::from_millis(100)));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(searcher.completed_depth >= 1);
+
+        // a stopped search still completes the first iteration
+        searcher.stop.store(true, AtomicOrdering::Relaxed);
+        let (_, m) = searcher.search(&game, MAX_DEPTH, None);
+        assert!(game.gen_moves().contains(&m));
+        assert_eq!(searcher.completed_depth, 1);
+    }
+
+    #[test]
+    fn test_parallel_search_matches() {
+        let mut game = Game::from_cards(vec![
+            Card::Dragon,
+            Card::Monkey,
+            Card::Rabbit,
+            Card::Goose,
+            Card::Tiger,
+        ]);
+        for _ in 0..4 {
+            for depth in 1..=4 {
+                // the workers are kept from one iteration to the next
+                let mut searcher = Searcher::new();
+                searcher.threads = 4;
+                let (value, m) = searcher.search(&game, depth, None);
+                assert_eq!(value, Searcher::new().search(&game, depth, None).0);
+                assert_eq!(value, minimax(&game, depth));
+                assert_eq!(minimax(&game.take_turn(&m), depth - 1).next(), value);
+            }
+            let m = Searcher::new().search(&game, 3, None).1;
+            game = game.take_turn(&m);
+        }
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        let mut searcher = Searcher::new();
+        let mut reports = Vec::new();
+        let (value, m) = searcher.search_reporting(&game, 5, None, |r| reports.push(r.depth));
+        assert_eq!(reports, vec![1, 2, 3, 4, 5]);
+        let pv = searcher.principal_variation(&game, 5);
+        assert_eq!(pv[0], m);
+        // the end of the variation should have the same value
+        let mut end = game.clone();
+        for m in pv.iter() {
+            end = end.take_turn(m);
+        }
+        if pv.len() == 5 {
+            let leaf = Value::Eval(Heuristic::default().evaluate(&end));
+            let leaf = if pv.len().is_multiple_of(2) {
+                leaf
+            } else {
+                leaf.next()
+            };
+            assert_eq!(leaf, value);
+        }
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        let mut searcher = Searcher::new();
+        searcher.node_limit = Some(10_000);
+        searcher.search(&game, MAX_DEPTH, None);
+        assert!(searcher.nodes < 10_000 + NODES_PER_CHECK);
+    }
 }
@@ -3,35 +3,102 @@ use array_const_fn_init::array_const_fn_init;
 use bitwise::TestBit;
 use rand::{
     distributions::{Distribution, Standard},
+    seq::SliceRandom,
     Rng,
 };
 use std::fmt;
+use std::fs;
+use std::path::Path;
 use std::result::Result;
+use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use std::sync::RwLock;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum Card {
-    Boar,
-    Cobra,
-    Crab,
-    Crane,
-    Dragon,
-    Eel,
-    Elephant,
-    Frog,
-    Goose,
-    Horse,
-    Mantis,
-    Monkey,
-    Ox,
-    Rabbit,
-    Rooster,
-    Tiger,
+// Cards are numbered, first the base game, then the expansions and then the cards loaded
+// at runtime with load_cards. The built in cards keep their numbers, tablebases save them.
+// The loaded cards are global and can't be removed, they are meant to be loaded once
+// at startup before any game is dealt.
+//
+// A card file is a JSON list of cards. The moves are drawn like on the card,
+// from the view of the player holding it with the piece in the middle:
+//
+// [
+//   {
+//     "name": "heron",
+//     "colour": "blue",
+//     "moves": ["0 0 0 0 0",
+//               "0 0 1 0 0",
+//               "1 0 0 0 1",
+//               "0 0 1 0 0",
+//               "0 0 0 0 0"]
+//   }
+// ]
+//
+// Names are single lowercase words so that they fit in moves and the notation.
+// A card has at most MAX_CARD_MOVES moves like the printed cards, so that the moves
+// of a position fit in gen_moves. Cards like those of the Way of the Wind also have
+// "spirit" moves in the same format, the wind spirit moves with them after the piece.
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Card(u8);
+
+#[allow(non_upper_case_globals)]
+impl Card {
+    // the base game
+    pub const Boar: Card = Card(0);
+    pub const Cobra: Card = Card(1);
+    pub const Crab: Card = Card(2);
+    pub const Crane: Card = Card(3);
+    pub const Dragon: Card = Card(4);
+    pub const Eel: Card = Card(5);
+    pub const Elephant: Card = Card(6);
+    pub const Frog: Card = Card(7);
+    pub const Goose: Card = Card(8);
+    pub const Horse: Card = Card(9);
+    pub const Mantis: Card = Card(10);
+    pub const Monkey: Card = Card(11);
+    pub const Ox: Card = Card(12);
+    pub const Rabbit: Card = Card(13);
+    pub const Rooster: Card = Card(14);
+    pub const Tiger: Card = Card(15);
+
+    // Sensei's Path
+    pub const Bear: Card = Card(16);
+    pub const Dog: Card = Card(17);
+    pub const Fox: Card = Card(18);
+    pub const Giraffe: Card = Card(19);
+    pub const Iguana: Card = Card(20);
+    pub const Kirin: Card = Card(21);
+    pub const Mouse: Card = Card(22);
+    pub const Otter: Card = Card(23);
+    pub const Panda: Card = Card(24);
+    pub const Phoenix: Card = Card(25);
+    pub const Rat: Card = Card(26);
+    pub const Sable: Card = Card(27);
+    pub const SeaSnake: Card = Card(28);
+    pub const Tanuki: Card = Card(29);
+    pub const Turtle: Card = Card(30);
+    pub const Viper: Card = Card(31);
+
+    // Way of the Wind
+    pub const Bat: Card = Card(32);
+    pub const Eagle: Card = Card(33);
+    pub const Lizard: Card = Card(34);
+    pub const Octopus: Card = Card(35);
+    pub const Scorpion: Card = Card(36);
+    pub const Sparrow: Card = Card(37);
+    pub const Swan: Card = Card(38);
+    pub const Wolf: Card = Card(39);
 }
 
+pub const BASE_CARDS: usize = 16;
+const BUILT_IN_CARDS: usize = 40;
+pub const MAX_CARDS: usize = 64;
+pub const MAX_CARD_MOVES: u32 = 4;
+
 // these are for red
 #[rustfmt::skip]
 const fn const_card(num: usize) -> u32 {
-    match Card::from_num(num) {
+    match Card(num as u8) {
         Card::Boar =>
             board!(0 0 0 0 0
                    0 0 1 0 0
@@ -128,6 +195,207 @@ const fn const_card(num: usize) -> u32 {
                    0 0 0 0 0
                    0 0 1 0 0
                    0 0 0 0 0),
+        Card::Bear =>
+            board!(0 0 0 0 0
+                   0 1 1 0 0
+                   0 0 0 0 0
+                   0 0 0 1 0
+                   0 0 0 0 0),
+        Card::Dog =>
+            board!(0 0 0 0 0
+                   0 1 0 0 0
+                   0 1 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 0),
+        Card::Fox =>
+            board!(0 0 0 0 0
+                   0 0 0 1 0
+                   0 0 0 1 0
+                   0 0 0 1 0
+                   0 0 0 0 0),
+        Card::Giraffe =>
+            board!(0 0 0 0 0
+                   1 0 0 0 1
+                   0 0 0 0 0
+                   0 0 1 0 0
+                   0 0 0 0 0),
+        Card::Iguana =>
+            board!(0 0 0 0 0
+                   1 0 1 0 0
+                   0 0 0 0 0
+                   0 0 0 1 0
+                   0 0 0 0 0),
+        Card::Kirin =>
+            board!(0 1 0 1 0
+                   0 0 0 0 0
+                   0 0 0 0 0
+                   0 0 0 0 0
+                   0 0 1 0 0),
+        Card::Mouse =>
+            board!(0 0 0 0 0
+                   0 0 1 0 0
+                   0 0 0 1 0
+                   0 1 0 0 0
+                   0 0 0 0 0),
+        Card::Otter =>
+            board!(0 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 1
+                   0 0 0 1 0
+                   0 0 0 0 0),
+        Card::Panda =>
+            board!(0 0 0 0 0
+                   0 0 1 1 0
+                   0 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 0),
+        Card::Phoenix =>
+            board!(0 0 0 0 0
+                   0 1 0 1 0
+                   1 0 0 0 1
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        Card::Rat =>
+            board!(0 0 0 0 0
+                   0 0 1 0 0
+                   0 1 0 0 0
+                   0 0 0 1 0
+                   0 0 0 0 0),
+        Card::Sable =>
+            board!(0 0 0 0 0
+                   0 0 0 1 0
+                   1 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 0),
+        Card::SeaSnake =>
+            board!(0 0 0 0 0
+                   0 0 1 0 0
+                   0 0 0 0 1
+                   0 1 0 0 0
+                   0 0 0 0 0),
+        Card::Tanuki =>
+            board!(0 0 0 0 0
+                   0 0 1 0 1
+                   0 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 0),
+        Card::Turtle =>
+            board!(0 0 0 0 0
+                   0 0 0 0 0
+                   1 0 0 0 1
+                   0 1 0 1 0
+                   0 0 0 0 0),
+        Card::Viper =>
+            board!(0 0 0 0 0
+                   0 0 1 0 0
+                   1 0 0 0 0
+                   0 0 0 1 0
+                   0 0 0 0 0),
+        Card::Bat =>
+            board!(0 0 0 0 0
+                   0 1 0 1 0
+                   0 0 0 0 0
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        Card::Eagle =>
+            board!(0 0 1 0 0
+                   0 0 0 0 0
+                   0 0 0 0 0
+                   0 1 0 1 0
+                   0 0 0 0 0),
+        Card::Lizard =>
+            board!(0 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 1
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        Card::Octopus =>
+            board!(0 0 0 0 0
+                   0 0 0 0 0
+                   0 1 0 1 0
+                   0 0 1 0 0
+                   0 0 0 0 0),
+        Card::Scorpion =>
+            board!(0 0 0 0 0
+                   0 0 1 0 0
+                   0 0 0 0 0
+                   0 1 0 1 0
+                   0 0 0 0 0),
+        Card::Sparrow =>
+            board!(0 0 0 0 0
+                   0 0 0 1 0
+                   1 0 0 0 0
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        Card::Swan =>
+            board!(0 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 0
+                   0 0 1 0 0
+                   0 0 0 0 0),
+        Card::Wolf =>
+            board!(0 0 0 0 0
+                   0 0 0 1 0
+                   0 0 0 0 0
+                   0 0 1 0 0
+                   0 0 0 0 0),
+        _ => 0,
+    }
+}
+
+// how the wind spirit moves after the piece, only the Way of the Wind cards have these
+#[rustfmt::skip]
+const fn const_spirit_card(num: usize) -> u32 {
+    match Card(num as u8) {
+        Card::Bat =>
+            board!(0 0 0 0 0
+                   0 0 1 0 0
+                   0 0 0 0 0
+                   0 0 1 0 0
+                   0 0 0 0 0),
+        Card::Eagle =>
+            board!(0 0 0 0 0
+                   0 0 0 0 0
+                   0 1 0 1 0
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        Card::Lizard =>
+            board!(0 0 0 0 0
+                   0 0 0 0 0
+                   0 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 0),
+        Card::Octopus =>
+            board!(0 0 0 0 0
+                   0 1 0 1 0
+                   0 0 0 0 0
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        Card::Scorpion =>
+            board!(0 0 0 0 0
+                   0 0 0 0 0
+                   1 0 0 0 1
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        Card::Sparrow =>
+            board!(0 0 0 0 0
+                   0 0 0 0 0
+                   0 0 0 0 0
+                   0 0 0 1 0
+                   0 0 0 0 0),
+        Card::Swan =>
+            board!(0 0 0 0 0
+                   0 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        Card::Wolf =>
+            board!(0 0 0 0 0
+                   0 0 0 0 0
+                   0 0 0 1 0
+                   0 0 0 0 0
+                   0 0 0 0 0),
+        _ => 0,
     }
 }
 
@@ -135,17 +403,135 @@ const fn const_reversed_card(card: usize) -> u32 {
     const_card(card).reverse_bits() >> (32 - 25)
 }
 
-const RED_CARDS: [u32; 16] = array_const_fn_init![const_card; 16];
-const BLUE_CARDS: [u32; 16] = array_const_fn_init![const_reversed_card; 16];
+const fn const_reversed_spirit_card(card: usize) -> u32 {
+    const_spirit_card(card).reverse_bits() >> (32 - 25)
+}
+
+// the macro needs a literal length, the tables below are all as long as BUILT_IN_CARDS
+const _: () = assert!(BUILT_IN_CARDS == 40);
+const RED_CARDS: [u32; BUILT_IN_CARDS] = array_const_fn_init![const_card; 40];
+const BLUE_CARDS: [u32; BUILT_IN_CARDS] = array_const_fn_init![const_reversed_card; 40];
+const RED_SPIRIT_CARDS: [u32; BUILT_IN_CARDS] = array_const_fn_init![const_spirit_card; 40];
+const BLUE_SPIRIT_CARDS: [u32; BUILT_IN_CARDS] =
+    array_const_fn_init![const_reversed_spirit_card; 40];
+
+// the moves mirrored left to right
+pub const fn mirror_bitmap(board: u32) -> u32 {
+    // turning the board around mirrors it both ways, putting the rows back in order undoes one
+    let turned = board.reverse_bits() >> (32 - 25);
+    let mut mirrored = 0;
+    let mut row = 0;
+    while row < 5 {
+        mirrored |= (turned >> (5 * (4 - row)) & 0b11111) << (5 * row);
+        row += 1;
+    }
+    mirrored
+}
+
+const NO_MIRROR: u8 = u8::MAX;
+
+// the built in card with the mirrored moves, the card itself when it is symmetric
+const fn const_mirror(num: usize) -> u8 {
+    let moves = mirror_bitmap(RED_CARDS[num]);
+    let spirit = mirror_bitmap(RED_SPIRIT_CARDS[num]);
+    if moves == RED_CARDS[num] && spirit == RED_SPIRIT_CARDS[num] {
+        return num as u8;
+    }
+    let mut other = 0;
+    while other < BUILT_IN_CARDS {
+        if RED_CARDS[other] == moves && RED_SPIRIT_CARDS[other] == spirit {
+            return other as u8;
+        }
+        other += 1;
+    }
+    NO_MIRROR
+}
+
+const MIRRORS: [u8; BUILT_IN_CARDS] = array_const_fn_init![const_mirror; 40];
+
+#[derive(Clone, Copy)]
+struct CustomCard {
+    name: &'static str,
+    colour: Colour,
+}
+
+// the cards loaded at runtime, numbered from BUILT_IN_CARDS
+static CUSTOM_CARDS: RwLock<Vec<CustomCard>> = RwLock::new(Vec::new());
+// their moves for red and blue and then their spirit moves for red and blue,
+// read without the lock since every move generation needs them
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_MOVES: [AtomicU32; 4] = [
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+];
+static CUSTOM_MOVES: [[AtomicU32; 4]; MAX_CARDS - BUILT_IN_CARDS] =
+    [NO_MOVES; MAX_CARDS - BUILT_IN_CARDS];
+#[allow(clippy::declare_interior_mutable_const)]
+const UNMIRRORED: AtomicU8 = AtomicU8::new(NO_MIRROR);
+static CUSTOM_MIRRORS: [AtomicU8; MAX_CARDS - BUILT_IN_CARDS] =
+    [UNMIRRORED; MAX_CARDS - BUILT_IN_CARDS];
 
 impl Card {
     pub fn get_move(self, colour: Colour) -> u32 {
-        match colour {
-            Colour::Blue => BLUE_CARDS[self as usize],
-            Colour::Red => RED_CARDS[self as usize],
+        let index = self.index();
+        if index < BUILT_IN_CARDS {
+            match colour {
+                Colour::Blue => BLUE_CARDS[index],
+                Colour::Red => RED_CARDS[index],
+            }
+        } else {
+            let moves = &CUSTOM_MOVES[index - BUILT_IN_CARDS];
+            match colour {
+                Colour::Blue => moves[1].load(Ordering::Relaxed),
+                Colour::Red => moves[0].load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    // 0 for the cards without spirit moves
+    pub fn get_spirit_move(self, colour: Colour) -> u32 {
+        let index = self.index();
+        if index < BUILT_IN_CARDS {
+            match colour {
+                Colour::Blue => BLUE_SPIRIT_CARDS[index],
+                Colour::Red => RED_SPIRIT_CARDS[index],
+            }
+        } else {
+            let moves = &CUSTOM_MOVES[index - BUILT_IN_CARDS];
+            match colour {
+                Colour::Blue => moves[3].load(Ordering::Relaxed),
+                Colour::Red => moves[2].load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    // whether the card moves a piece and then the wind spirit
+    pub fn is_dual(self) -> bool {
+        self.get_spirit_move(Colour::Red) != 0
+    }
+
+    // the card with the moves mirrored left to right, the card itself when it is symmetric
+    // and None when there is no such card, then the mirror isn't a symmetry of the game
+    pub fn mirror(self) -> Option<Card> {
+        let index = self.index();
+        let mirror = if index < BUILT_IN_CARDS {
+            MIRRORS[index]
+        } else {
+            CUSTOM_MIRRORS[index - BUILT_IN_CARDS].load(Ordering::Relaxed)
+        };
+        if mirror == NO_MIRROR {
+            None
+        } else {
+            Some(Card(mirror))
         }
     }
 
+    pub fn is_symmetric(self) -> bool {
+        self.mirror() == Some(self)
+    }
+
     pub fn get_colour(self) -> Colour {
         match self {
             Card::Boar => Colour::Red,
@@ -164,6 +550,31 @@ impl Card {
             Card::Rabbit => Colour::Blue,
             Card::Rooster => Colour::Red,
             Card::Tiger => Colour::Blue,
+            Card::Bear => Colour::Blue,
+            Card::Dog => Colour::Blue,
+            Card::Fox => Colour::Red,
+            Card::Giraffe => Colour::Blue,
+            Card::Iguana => Colour::Red,
+            Card::Kirin => Colour::Red,
+            Card::Mouse => Colour::Blue,
+            Card::Otter => Colour::Red,
+            Card::Panda => Colour::Red,
+            Card::Phoenix => Colour::Blue,
+            Card::Rat => Colour::Red,
+            Card::Sable => Colour::Blue,
+            Card::SeaSnake => Colour::Blue,
+            Card::Tanuki => Colour::Blue,
+            Card::Turtle => Colour::Red,
+            Card::Viper => Colour::Red,
+            Card::Bat => Colour::Red,
+            Card::Eagle => Colour::Blue,
+            Card::Lizard => Colour::Red,
+            Card::Octopus => Colour::Blue,
+            Card::Scorpion => Colour::Red,
+            Card::Sparrow => Colour::Blue,
+            Card::Swan => Colour::Blue,
+            Card::Wolf => Colour::Red,
+            _ => self.custom().colour,
         }
     }
 
@@ -185,52 +596,228 @@ impl Card {
             Card::Rabbit => "rabbit",
             Card::Rooster => "rooster",
             Card::Tiger => "tiger",
+            Card::Bear => "bear",
+            Card::Dog => "dog",
+            Card::Fox => "fox",
+            Card::Giraffe => "giraffe",
+            Card::Iguana => "iguana",
+            Card::Kirin => "kirin",
+            Card::Mouse => "mouse",
+            Card::Otter => "otter",
+            Card::Panda => "panda",
+            Card::Phoenix => "phoenix",
+            Card::Rat => "rat",
+            Card::Sable => "sable",
+            Card::SeaSnake => "seasnake",
+            Card::Tanuki => "tanuki",
+            Card::Turtle => "turtle",
+            Card::Viper => "viper",
+            Card::Bat => "bat",
+            Card::Eagle => "eagle",
+            Card::Lizard => "lizard",
+            Card::Octopus => "octopus",
+            Card::Scorpion => "scorpion",
+            Card::Sparrow => "sparrow",
+            Card::Swan => "swan",
+            Card::Wolf => "wolf",
+            _ => self.custom().name,
         }
     }
 
+    fn custom(self) -> CustomCard {
+        CUSTOM_CARDS.read().unwrap()[self.index() - BUILT_IN_CARDS]
+    }
+
     pub fn from_text(text: &str) -> Result<Card, String> {
-        match text {
-            "boar" => Ok(Card::Boar),
-            "cobra" => Ok(Card::Cobra),
-            "crab" => Ok(Card::Crab),
-            "crane" => Ok(Card::Crane),
-            "dragon" => Ok(Card::Dragon),
-            "eel" => Ok(Card::Eel),
-            "elephant" => Ok(Card::Elephant),
-            "frog" => Ok(Card::Frog),
-            "goose" => Ok(Card::Goose),
-            "horse" => Ok(Card::Horse),
-            "mantis" => Ok(Card::Mantis),
-            "monkey" => Ok(Card::Monkey),
-            "ox" => Ok(Card::Ox),
-            "rabbit" => Ok(Card::Rabbit),
-            "rooster" => Ok(Card::Rooster),
-            "tiger" => Ok(Card::Tiger),
-            _ => Err(format!("Unknown card {}", text)),
+        Card::all()
+            .into_iter()
+            .find(|card| card.get_name() == text)
+            .ok_or_else(|| format!("Unknown card {}", text))
+    }
+
+    // every number below Card::count is a card, the others would panic later on
+    pub fn from_num(num: usize) -> Self {
+        assert!(num < Card::count(), "There is no card number {}", num);
+        Card(num as u8)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    // how many cards there are, including the ones loaded so far
+    pub fn count() -> usize {
+        BUILT_IN_CARDS + CUSTOM_CARDS.read().unwrap().len()
+    }
+
+    pub fn all() -> Vec<Card> {
+        (0..Card::count()).map(Card::from_num).collect()
+    }
+}
+
+fn check_moves(name: &str, moves: u32) -> Result<(), String> {
+    if moves & 1 << 12 != 0 {
+        return Err(format!("{} can't move a piece onto itself", name));
+    }
+    if moves >> 25 != 0 || moves.count_ones() > MAX_CARD_MOVES {
+        return Err(format!("{} has more than {} moves", name, MAX_CARD_MOVES));
+    }
+    Ok(())
+}
+
+// add a card with its moves for red drawn like in const_card, spirit is 0 for most cards
+pub fn add_card(name: &str, moves: u32, spirit: u32, colour: Colour) -> Result<Card, String> {
+    if name.is_empty() || name.contains(char::is_whitespace) || name != name.to_lowercase() {
+        return Err(format!(
+            "Card names must be one lowercase word, not `{}`",
+            name
+        ));
+    }
+    if moves == 0 {
+        return Err(format!("{} has no moves", name));
+    }
+    check_moves(name, moves)?;
+    check_moves(name, spirit)?;
+    let mut custom = CUSTOM_CARDS.write().unwrap();
+    // the cards are numbered directly while the lock is held
+    let built_in = (0..BUILT_IN_CARDS).map(|num| Card(num as u8).get_name());
+    if built_in
+        .chain(custom.iter().map(|c| c.name))
+        .any(|n| n == name)
+    {
+        return Err(format!("There already is a card called {}", name));
+    }
+    let index = BUILT_IN_CARDS + custom.len();
+    if index >= MAX_CARDS {
+        return Err(format!("There can't be more than {} cards", MAX_CARDS));
+    }
+    let slot = &CUSTOM_MOVES[index - BUILT_IN_CARDS];
+    slot[0].store(moves, Ordering::Relaxed);
+    slot[1].store(moves.reverse_bits() >> (32 - 25), Ordering::Relaxed);
+    slot[2].store(spirit, Ordering::Relaxed);
+    slot[3].store(spirit.reverse_bits() >> (32 - 25), Ordering::Relaxed);
+    // the mirror of an earlier card, which is then mirrored by this one if it had no mirror yet
+    let (mirror_moves, mirror_spirit) = (mirror_bitmap(moves), mirror_bitmap(spirit));
+    let mirror = if mirror_moves == moves && mirror_spirit == spirit {
+        Some(index)
+    } else {
+        (0..index).find(|&other| {
+            let card = Card(other as u8);
+            card.get_move(Colour::Red) == mirror_moves
+                && card.get_spirit_move(Colour::Red) == mirror_spirit
+        })
+    };
+    if let Some(mirror) = mirror {
+        CUSTOM_MIRRORS[index - BUILT_IN_CARDS].store(mirror as u8, Ordering::Relaxed);
+        if mirror >= BUILT_IN_CARDS && mirror != index {
+            let _ = CUSTOM_MIRRORS[mirror - BUILT_IN_CARDS].compare_exchange(
+                NO_MIRROR,
+                index as u8,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+    }
+    custom.push(CustomCard {
+        // cards are never removed, so the name can live as long as the program
+        name: Box::leak(name.to_string().into_boxed_str()),
+        colour,
+    });
+    Ok(Card(index as u8))
+}
+
+#[derive(Deserialize)]
+struct CardDefinition {
+    name: String,
+    colour: Colour,
+    moves: Vec<String>,
+    #[serde(default)]
+    spirit: Vec<String>,
+}
+
+fn parse_moves(rows: &[String]) -> Result<u32, String> {
+    if rows.len() != 5 {
+        return Err(format!("Expected 5 rows of moves, got {}", rows.len()));
+    }
+    let mut moves = 0;
+    for (row, text) in rows.iter().enumerate() {
+        let squares: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        if squares.len() != 5 {
+            return Err(format!("Expected 5 squares in `{}`", text));
+        }
+        for (col, square) in squares.into_iter().enumerate() {
+            match square {
+                '0' => {}
+                '1' => moves |= 1 << (5 * row + col),
+                _ => return Err(format!("Squares are 0 or 1, not {}", square)),
+            }
         }
     }
+    Ok(moves)
+}
 
-    pub const fn from_num(num: usize) -> Self {
-        match num {
-            x if x == Card::Boar as usize => Card::Boar,
-            x if x == Card::Cobra as usize => Card::Cobra,
-            x if x == Card::Crab as usize => Card::Crab,
-            x if x == Card::Crane as usize => Card::Crane,
-            x if x == Card::Dragon as usize => Card::Dragon,
-            x if x == Card::Eel as usize => Card::Eel,
-            x if x == Card::Elephant as usize => Card::Elephant,
-            x if x == Card::Frog as usize => Card::Frog,
-            x if x == Card::Goose as usize => Card::Goose,
-            x if x == Card::Horse as usize => Card::Horse,
-            x if x == Card::Mantis as usize => Card::Mantis,
-            x if x == Card::Monkey as usize => Card::Monkey,
-            x if x == Card::Ox as usize => Card::Ox,
-            x if x == Card::Rabbit as usize => Card::Rabbit,
-            x if x == Card::Rooster as usize => Card::Rooster,
-            x if x == Card::Tiger as usize => Card::Tiger,
-            _ => Card::Boar,
+// add the cards in a card file, see the top of this file for the format
+// either every card in the file is added or none of them
+pub fn load_cards(path: &Path) -> Result<Vec<Card>, String> {
+    let error = |e: String| format!("{}: {}", path.display(), e);
+    let text = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    let definitions: Vec<CardDefinition> =
+        serde_json::from_str(&text).map_err(|e| error(e.to_string()))?;
+    let parsed = definitions
+        .iter()
+        .map(|d| {
+            let parse = |rows: &[String]| {
+                parse_moves(rows).map_err(|e| error(format!("{}: {}", d.name, e)))
+            };
+            let moves = parse(&d.moves)?;
+            let spirit = if d.spirit.is_empty() {
+                0
+            } else {
+                parse(&d.spirit)?
+            };
+            Ok((moves, spirit))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    // a card that is already there or twice in the file would leave the cards before it loaded
+    for (i, d) in definitions.iter().enumerate() {
+        if Card::from_text(&d.name).is_ok() || definitions[..i].iter().any(|e| e.name == d.name) {
+            return Err(error(format!("There already is a card called {}", d.name)));
         }
     }
+    if Card::count() + definitions.len() > MAX_CARDS {
+        return Err(error(format!(
+            "There can't be more than {} cards",
+            MAX_CARDS
+        )));
+    }
+    definitions
+        .iter()
+        .zip(parsed)
+        .map(|(d, (moves, spirit))| add_card(&d.name, moves, spirit, d.colour).map_err(error))
+        .collect()
+}
+
+// tests that add cards hold this while they run and start without any loaded cards,
+// so that the cards of one test don't turn up in another
+#[cfg(test)]
+pub fn fresh_cards() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut custom = CUSTOM_CARDS.write().unwrap();
+    for index in 0..custom.len() {
+        for moves in CUSTOM_MOVES[index].iter() {
+            moves.store(0, Ordering::Relaxed);
+        }
+        CUSTOM_MIRRORS[index].store(NO_MIRROR, Ordering::Relaxed);
+    }
+    custom.clear();
+    guard
+}
+
+impl fmt::Debug for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_name())
+    }
 }
 
 impl fmt::Display for Card {
@@ -254,16 +841,29 @@ impl fmt::Display for Card {
     }
 }
 
+// random cards are from the base game
 impl Distribution<Card> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Card {
-        Card::from_num(rng.gen_range(0, 16))
+        Card::from_num(rng.gen_range(0, BASE_CARDS))
     }
 }
 
+// five different cards from the deck
+pub fn draw_cards_from(deck: &[Card]) -> Vec<Card> {
+    deck.choose_multiple(&mut rand::thread_rng(), 5)
+        .copied()
+        .collect()
+}
+
 pub fn draw_cards() -> Vec<Card> {
+    draw_cards_with(&mut rand::thread_rng())
+}
+
+// the same rng state draws the same cards, e.g. the openings of a seeded tournament
+pub fn draw_cards_with<R: Rng>(rng: &mut R) -> Vec<Card> {
     let mut drawn = vec![];
     while drawn.len() < 5 {
-        let card: Card = rand::random();
+        let card: Card = rng.gen();
         if !drawn.contains(&card) {
             drawn.push(card);
         }
@@ -321,6 +921,129 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_built_in_cards() {
+        for num in 0..BUILT_IN_CARDS {
+            let card = Card::from_num(num);
+            assert_eq!(Card::from_text(card.get_name()), Ok(card));
+            assert_ne!(card.get_move(Colour::Red), 0);
+            assert!(!card.get_move(Colour::Red).test_bit(12));
+            assert!(card.get_move(Colour::Red).count_ones() <= MAX_CARD_MOVES);
+            assert!(!card.get_spirit_move(Colour::Red).test_bit(12));
+            // only the Way of the Wind cards move the wind spirit
+            assert_eq!(card.is_dual(), num >= Card::Bat.index());
+        }
+        assert_eq!(Card::from_text("seasnake"), Ok(Card::SeaSnake));
+        // a pair of cards that are each other turned around have different colours
+        assert_eq!(
+            Card::Fox.get_move(Colour::Red),
+            Card::Dog.get_move(Colour::Blue)
+        );
+        assert_ne!(Card::Fox.get_colour(), Card::Dog.get_colour());
+    }
+
+    #[test]
+    #[should_panic(expected = "There is no card number 63")]
+    fn test_from_num_out_of_range() {
+        Card::from_num(MAX_CARDS - 1);
+    }
+
+    #[test]
+    fn test_mirror_cards() {
+        assert_eq!(
+            mirror_bitmap(board!(1 1 0 0 0
+                                 0 0 0 0 0
+                                 0 0 0 1 0
+                                 0 0 0 0 0
+                                 0 0 0 0 1)),
+            board!(0 0 0 1 1
+                   0 0 0 0 0
+                   0 1 0 0 0
+                   0 0 0 0 0
+                   1 0 0 0 0)
+        );
+        // every built in card has a mirror, and the mirror of the mirror is the card
+        for num in 0..BUILT_IN_CARDS {
+            let card = Card::from_num(num);
+            let mirror = card.mirror().unwrap();
+            assert_eq!(mirror.mirror(), Some(card));
+            for &colour in [Colour::Red, Colour::Blue].iter() {
+                assert_eq!(
+                    mirror_bitmap(card.get_move(colour)),
+                    mirror.get_move(colour)
+                );
+                assert_eq!(
+                    mirror_bitmap(card.get_spirit_move(colour)),
+                    mirror.get_spirit_move(colour)
+                );
+            }
+        }
+        assert!(Card::Tiger.is_symmetric() && Card::Bat.is_symmetric());
+        assert_eq!(Card::Frog.mirror(), Some(Card::Rabbit));
+        assert_eq!(Card::Swan.mirror(), Some(Card::Wolf));
+    }
+
+    #[test]
+    fn test_load_cards() {
+        let _cards = fresh_cards();
+        let path = std::env::temp_dir().join("onitama_test_cards.json");
+        fs::write(
+            &path,
+            r#"[{"name": "heron", "colour": "blue",
+                 "moves": ["0 0 0 0 0", "0 0 1 0 0", "1 0 0 0 1", "0 0 1 0 0", "0 0 0 0 0"]},
+                {"name": "gale", "colour": "red",
+                 "moves": ["0 0 0 0 0", "0 0 1 0 0", "0 0 0 0 0", "0 0 0 0 0", "0 0 0 0 0"],
+                 "spirit": ["0 0 0 0 0", "0 0 0 0 0", "0 1 0 0 0", "0 0 0 0 0", "0 0 0 0 0"]}]"#,
+        )
+        .unwrap();
+        let cards = load_cards(&path).unwrap();
+        assert_eq!(cards.len(), 2);
+        let heron = Card::from_text("heron").unwrap();
+        assert_eq!(cards[0], heron);
+        assert!(heron.index() >= BUILT_IN_CARDS);
+        assert_eq!(heron.get_colour(), Colour::Blue);
+        assert_eq!(
+            heron.get_move(Colour::Red),
+            board!(0 0 0 0 0
+                   0 0 1 0 0
+                   1 0 0 0 1
+                   0 0 1 0 0
+                   0 0 0 0 0)
+        );
+        assert!(!heron.is_dual() && cards[1].is_dual());
+        // gale moves the spirit to one side and no card moves it to the other
+        assert!(heron.is_symmetric());
+        assert_eq!(cards[1].mirror(), None);
+        assert_eq!(cards[1].get_spirit_move(Colour::Blue), 1 << 13);
+        // loading it again would give two cards the same name
+        let count = Card::count();
+        assert!(load_cards(&path).is_err());
+        assert_eq!(Card::count(), count);
+        fs::remove_file(&path).unwrap();
+
+        // a card loaded later mirrors one loaded earlier
+        let left = add_card("leftward", 1 | 1 << 11, 0, Colour::Red).unwrap();
+        assert_eq!(left.mirror(), None);
+        let right = add_card("rightward", 1 << 4 | 1 << 13, 0, Colour::Blue).unwrap();
+        assert_eq!(right.mirror(), Some(left));
+        assert_eq!(left.mirror(), Some(right));
+
+        assert!(add_card("Big Ox", 1, 0, Colour::Red).is_err());
+        assert!(add_card("nothing", 0, 0, Colour::Red).is_err());
+        assert!(add_card("stay", 1 << 12, 0, Colour::Red).is_err());
+        assert!(add_card("tiger", 1, 0, Colour::Red).is_err());
+        assert!(add_card("many", 0b11111, 0, Colour::Red).is_err());
+        assert!(add_card("windy", 1, 1 << 12, Colour::Red).is_err());
+        let rows =
+            |rows: &[&str]| parse_moves(&rows.iter().map(|r| r.to_string()).collect::<Vec<_>>());
+        assert!(rows(&["00000"; 4]).is_err());
+        assert!(rows(&["00000", "00000", "00200", "00000", "00000"]).is_err());
+        assert_eq!(
+            rows(&["00000", "00000", "00001", "00000", "00000"]),
+            Ok(1 << 14)
+        );
+    }
+
     #[test]
     fn test_shift_bitmap() {
         assert_eq!(
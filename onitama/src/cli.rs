@@ -1,9 +1,114 @@
-use crate::cards::Card;
-use crate::game::Game;
+use crate::bot::MAX_DEPTH;
+use crate::cards::{load_cards, Card};
+use crate::game::{Game, Ruleset};
+use crate::tablebase::{DEFAULT_PIECES, MAX_PIECES};
 use std::env;
+use std::path::{Path, PathBuf};
 use std::result::Result;
+use std::time::Duration;
 
-pub type Args = (Playing, GameHost);
+pub enum Command {
+    Play(Args),
+    // the game and the gui flag, the config analyses the positions
+    Replay(PathBuf, bool, Option<PathBuf>),
+    Arena(ArenaArgs),
+    Export(ExportArgs),
+    Tablebase(TablebaseArgs),
+    Book(BookArgs),
+    Analyse(AnalyseArgs),
+    Perft(PerftArgs),
+    Tournament(TournamentArgs),
+    Server(u16),
+    Engine,
+}
+
+pub struct Args {
+    pub playing: Playing,
+    pub host: GameHost,
+    pub time_limit: Duration,
+    // overrides the threads of the config
+    pub threads: Option<usize>,
+    pub config: Option<PathBuf>,
+    pub gui: bool,
+    pub save_dir: Option<PathBuf>,
+    pub book: Option<PathBuf>,
+    // the litama server for online games
+    pub server: String,
+}
+
+pub struct ArenaArgs {
+    pub configs: [PathBuf; 2],
+    pub games: usize,
+    pub threads: usize,
+    pub sprt: Option<(f64, f64)>,
+}
+
+pub struct TournamentArgs {
+    pub configs: Vec<PathBuf>,
+    pub openings: usize,
+    pub threads: usize,
+    pub gauntlet: bool,
+    pub pgn: Option<PathBuf>,
+    // the openings are random without one
+    pub seed: Option<u64>,
+}
+
+pub struct ExportArgs {
+    pub path: PathBuf,
+    pub games: usize,
+    pub threads: usize,
+    pub config: Option<PathBuf>,
+}
+
+pub struct TablebaseArgs {
+    pub path: PathBuf,
+    pub cards: [Card; 5],
+    pub max_pieces: usize,
+    pub threads: usize,
+}
+
+pub struct BookArgs {
+    pub path: PathBuf,
+    pub deals: BookDeals,
+    pub plies: usize,
+    pub threads: usize,
+    pub config: Option<PathBuf>,
+}
+
+pub struct AnalyseArgs {
+    pub position: Game,
+    pub depth: u8,
+    pub time: Option<Duration>,
+    pub config: Option<PathBuf>,
+}
+
+pub struct PerftArgs {
+    pub positions: PerftPositions,
+    pub divide: bool,
+    pub bulk: bool,
+    pub threads: usize,
+}
+
+pub enum PerftPositions {
+    // a position and the depth to count to
+    One(Game, u8),
+    // a suite file, only checked up to the depth if there is one
+    Suite(PathBuf, Option<u8>),
+}
+
+pub enum BookDeals {
+    All,
+    Random(usize),
+}
+
+const DEFAULT_TIME_LIMIT: f64 = 5.;
+const DEFAULT_ARENA_GAMES: usize = 100;
+const DEFAULT_TOURNAMENT_OPENINGS: usize = 10;
+const DEFAULT_EXPORT_GAMES: usize = 1000;
+const DEFAULT_BOOK_DEALS: usize = 100;
+const DEFAULT_BOOK_PLIES: usize = 2;
+const DEFAULT_SERVER: &str = "wss://litama.herokuapp.com";
+const DEFAULT_PORT: u16 = 8080;
 
 pub enum Playing {
     Human,
@@ -19,22 +124,39 @@ pub enum GameHost {
     Online(Option<MatchId>, Username),
 }
 
-pub fn parse_args() -> Result<Args, String> {
-    let mut args = env::args().map(|s| s.to_lowercase());
+pub fn parse_args() -> Result<Command, String> {
+    // paths keep their case, everything else is lowercased
+    let mut args = load_card_files(env::args().collect())?.into_iter();
     // ignore first argument
     let _exe = args
         .next()
         .ok_or_else(|| "How did you launch this without any arguments?".to_string())?;
 
-    let online = match args.next().as_deref() {
+    let online = match args.next().map(|s| s.to_lowercase()).as_deref() {
         Some("online") => Ok(true),
         Some("local") => Ok(false),
+        Some("engine") => return Ok(Command::Engine),
+        Some("replay") => {
+            let path = args
+                .next()
+                .ok_or_else(|| "You need to give a file to replay".to_string())?;
+            let Flags { gui, config, .. } = parse_flags(args)?;
+            return Ok(Command::Replay(PathBuf::from(path), gui, config));
+        }
+        Some("arena") => return parse_arena_args(args).map(Command::Arena),
+        Some("tournament") => return parse_tournament_args(args).map(Command::Tournament),
+        Some("export") => return parse_export_args(args).map(Command::Export),
+        Some("tablebase") => return parse_tablebase_args(args).map(Command::Tablebase),
+        Some("book") => return parse_book_args(args).map(Command::Book),
+        Some("analyse") | Some("analyze") => return parse_analyse_args(args).map(Command::Analyse),
+        Some("server") => return parse_server_args(args).map(Command::Server),
+        Some("perft") => return parse_perft_args(args).map(Command::Perft),
         Some("help") => Err("".to_string()),
         Some(word) => Err(format!("Unrecognised word: {}", word)),
         None => Err("No command found".to_string()),
     }?;
 
-    let second = args.next();
+    let second = args.next().map(|s| s.to_lowercase());
     if online {
         // find out what to do online
         let (need_match_id, spectate) = match second.as_deref() {
@@ -46,7 +168,7 @@ pub fn parse_args() -> Result<Args, String> {
         }?;
         // potentially get match id
         let match_id = if need_match_id {
-            let a = args.next();
+            let a = args.next().map(|s| s.to_lowercase());
             if a.is_none() {
                 return Err("You need give a match id".to_string());
             }
@@ -54,12 +176,24 @@ pub fn parse_args() -> Result<Args, String> {
         } else {
             None
         };
-        let username = match args.next() {
+        let username = match args.next().map(|s| s.to_lowercase()) {
             Some(name) => Ok(name),
             None => Err("You must enter a username when playing online".to_string()),
         }?;
-        // find out if human is playing
-        let human = is_human(args.next())?;
+        let Flags {
+            human,
+            time_limit,
+            threads,
+            config,
+            gui,
+            save_dir,
+            book,
+            server,
+            rules,
+        } = parse_flags(args)?;
+        if rules != Ruleset::Base {
+            return Err("Online games are played with the base rules".to_string());
+        }
         let playing = if human {
             Playing::Human
         } else if spectate {
@@ -68,7 +202,17 @@ pub fn parse_args() -> Result<Args, String> {
             Playing::Bot
         };
 
-        Ok((playing, GameHost::Online(match_id, username)))
+        Ok(Command::Play(Args {
+            playing,
+            host: GameHost::Online(match_id, username),
+            time_limit,
+            threads,
+            config,
+            gui,
+            save_dir,
+            book,
+            server,
+        }))
     } else {
         // find out if I want to use preset cards or random
         let preset = match second.as_deref() {
@@ -77,36 +221,523 @@ pub fn parse_args() -> Result<Args, String> {
             Some(word) => Err(format!("Unrecognised word: {}", word)),
             None => Err("You must specify whether to use random or preset cards".to_string()),
         }?;
-        // make a game struct for use later
-        let game = if preset {
-            let mut cards = Vec::new();
+        let mut cards = Vec::new();
+        if preset {
             for i in 0..5 {
                 cards.push(Card::from_text(
                     &args
                         .next()
-                        .ok_or_else(|| format!("Expected 5 cards, got {}", i))?,
+                        .ok_or_else(|| format!("Expected 5 cards, got {}", i))?
+                        .to_lowercase(),
                 )?)
             }
-            Game::from_cards(cards)
+        }
+        let Flags {
+            human,
+            time_limit,
+            threads,
+            config,
+            gui,
+            save_dir,
+            book,
+            server,
+            rules,
+        } = parse_flags(args)?;
+        // make a game struct for use later
+        let game = if preset {
+            Game::from_cards_with(cards, rules)
         } else {
             // random cards
-            Game::new()
+            Game::new_with(rules)
         };
-        // find out if human is playing
-        let playing = if is_human(args.next())? {
-            Playing::Human
+        let playing = if human { Playing::Human } else { Playing::Bot };
+
+        Ok(Command::Play(Args {
+            playing,
+            host: GameHost::Local(game),
+            time_limit,
+            threads,
+            config,
+            gui,
+            save_dir,
+            book,
+            server,
+        }))
+    }
+}
+
+// `--cards [file]` works with every command, the cards are loaded before the rest is parsed
+// so that they can be used in preset cards and positions
+fn load_card_files(args: Vec<String>) -> Result<Vec<String>, String> {
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg.to_lowercase() == "--cards" {
+            let path = args
+                .next()
+                .ok_or_else(|| "Expected a file after --cards".to_string())?;
+            load_cards(Path::new(&path))?;
         } else {
-            Playing::Bot
+            rest.push(arg);
+        }
+    }
+    Ok(rest)
+}
+
+struct Flags {
+    human: bool,
+    time_limit: Duration,
+    threads: Option<usize>,
+    config: Option<PathBuf>,
+    gui: bool,
+    save_dir: Option<PathBuf>,
+    book: Option<PathBuf>,
+    server: String,
+    rules: Ruleset,
+}
+
+// find out if human is playing and how the bot should search
+fn parse_flags(mut args: impl Iterator<Item = String>) -> Result<Flags, String> {
+    let mut human = false;
+    let mut seconds = DEFAULT_TIME_LIMIT;
+    let mut threads = None;
+    let mut config = None;
+    // fall back to the terminal when built without the gui
+    let mut gui = cfg!(feature = "gui");
+    let mut save_dir = None;
+    let mut book = None;
+    let mut server = DEFAULT_SERVER.to_string();
+    let mut rules = Ruleset::default();
+    while let Some(flag) = args.next() {
+        match flag.to_lowercase().as_ref() {
+            "-h" => human = true,
+            "--no-gui" => gui = false,
+            "--save" => {
+                let dir = args
+                    .next()
+                    .ok_or_else(|| "Expected a directory after --save".to_string())?;
+                save_dir = Some(PathBuf::from(dir));
+            }
+            "--book" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| "Expected a file after --book".to_string())?;
+                book = Some(PathBuf::from(path));
+            }
+            "--rules" => {
+                let text = args
+                    .next()
+                    .ok_or_else(|| "Expected base or wind after --rules".to_string())?;
+                rules = Ruleset::from_text(&text.to_lowercase())?;
+            }
+            "--server" => {
+                server = args
+                    .next()
+                    .ok_or_else(|| "Expected a url after --server".to_string())?;
+            }
+            "-t" => {
+                let text = args
+                    .next()
+                    .ok_or_else(|| "Expected a number of seconds after -t".to_string())?;
+                seconds = text
+                    .parse()
+                    .ok()
+                    // too long a time can't be a Duration
+                    .filter(|s: &f64| *s > 0. && Duration::try_from_secs_f64(*s).is_ok())
+                    .ok_or_else(|| format!("Invalid time limit: {}", text))?;
+            }
+            "-j" => threads = Some(parse_threads(&mut args)?),
+            "-c" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| "Expected a config file after -c".to_string())?;
+                config = Some(PathBuf::from(path));
+            }
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
+    }
+    Ok(Flags {
+        human,
+        time_limit: Duration::from_secs_f64(seconds),
+        threads,
+        config,
+        gui,
+        save_dir,
+        book,
+        server,
+        rules,
+    })
+}
+
+// arena [config a] [config b] [-n games] [-j threads] [--sprt elo0 elo1]
+fn parse_arena_args(mut args: impl Iterator<Item = String>) -> Result<ArenaArgs, String> {
+    let mut config = || {
+        args.next()
+            .map(PathBuf::from)
+            .ok_or_else(|| "The arena needs two engine config files".to_string())
+    };
+    let configs = [config()?, config()?];
+    let mut games = DEFAULT_ARENA_GAMES;
+    let mut threads = 1;
+    let mut sprt = None;
+    while let Some(flag) = args.next() {
+        let mut number = |name: &str| -> Result<String, String> {
+            args.next()
+                .ok_or_else(|| format!("Expected {} after {}", name, flag))
+        };
+        match flag.to_lowercase().as_ref() {
+            "-n" => {
+                let text = number("a number of games")?;
+                games = text
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Invalid number of games: {}", text))?;
+            }
+            "-j" => threads = parse_threads(&mut args)?,
+            "--sprt" => {
+                let mut elo = || -> Result<f64, String> {
+                    let text = number("two elo differences")?;
+                    text.parse()
+                        .ok()
+                        .filter(|e: &f64| e.is_finite())
+                        .ok_or_else(|| format!("Invalid elo difference: {}", text))
+                };
+                let (elo0, elo1) = (elo()?, elo()?);
+                if elo0 >= elo1 {
+                    return Err("The first SPRT bound must be below the second".to_string());
+                }
+                sprt = Some((elo0, elo1));
+            }
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
+    }
+    Ok(ArenaArgs {
+        configs,
+        games,
+        threads,
+        sprt,
+    })
+}
+
+// tournament [configs ...] [-n openings] [-j threads] [--gauntlet] [-o file] [--seed seed]
+fn parse_tournament_args(args: impl Iterator<Item = String>) -> Result<TournamentArgs, String> {
+    let mut args = args.peekable();
+    let mut configs = Vec::new();
+    while let Some(path) = args.next_if(|arg| !arg.starts_with('-')) {
+        configs.push(PathBuf::from(path));
+    }
+    if configs.len() < 2 {
+        return Err("A tournament needs at least two engine config files".to_string());
+    }
+    let mut openings = DEFAULT_TOURNAMENT_OPENINGS;
+    let mut threads = 1;
+    let mut gauntlet = false;
+    let mut pgn = None;
+    let mut seed = None;
+    while let Some(flag) = args.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            args.next()
+                .ok_or_else(|| format!("Expected {} after {}", name, flag))
+        };
+        match flag.to_lowercase().as_ref() {
+            "-n" => {
+                let text = value("a number of openings")?;
+                openings = text
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Invalid number of openings: {}", text))?;
+            }
+            "-j" => threads = parse_threads(&mut args)?,
+            "--gauntlet" => gauntlet = true,
+            "-o" => pgn = Some(PathBuf::from(value("a file")?)),
+            "--seed" => {
+                let text = value("a seed")?;
+                seed = Some(
+                    text.parse()
+                        .map_err(|_| format!("Invalid seed: {}", text))?,
+                );
+            }
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
+    }
+    Ok(TournamentArgs {
+        configs,
+        openings,
+        threads,
+        gauntlet,
+        pgn,
+        seed,
+    })
+}
+
+// export [file] [-n games] [-j threads] [-c config]
+fn parse_export_args(mut args: impl Iterator<Item = String>) -> Result<ExportArgs, String> {
+    let path = args
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| "You need to give a file to export to".to_string())?;
+    let mut games = DEFAULT_EXPORT_GAMES;
+    let mut threads = 1;
+    let mut config = None;
+    while let Some(flag) = args.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            args.next()
+                .ok_or_else(|| format!("Expected {} after {}", name, flag))
         };
+        match flag.to_lowercase().as_ref() {
+            "-n" => {
+                let text = value("a number of games")?;
+                games = text
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Invalid number of games: {}", text))?;
+            }
+            "-j" => threads = parse_threads(&mut args)?,
+            "-c" => config = Some(PathBuf::from(value("a config file")?)),
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
+    }
+    Ok(ExportArgs {
+        path,
+        games,
+        threads,
+        config,
+    })
+}
 
-        Ok((playing, GameHost::Local(game)))
+// tablebase [file] [5 cards] [-k pieces] [-j threads]
+fn parse_tablebase_args(mut args: impl Iterator<Item = String>) -> Result<TablebaseArgs, String> {
+    let path = args
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| "You need to give a file to write the tablebase to".to_string())?;
+    let mut cards = [Card::Boar; 5];
+    for (i, card) in cards.iter_mut().enumerate() {
+        let text = args
+            .next()
+            .ok_or_else(|| format!("Expected 5 cards, got {}", i))?;
+        *card = Card::from_text(&text.to_lowercase())?;
+    }
+    let mut max_pieces = DEFAULT_PIECES;
+    let mut threads = 1;
+    while let Some(flag) = args.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            args.next()
+                .ok_or_else(|| format!("Expected {} after {}", name, flag))
+        };
+        match flag.to_lowercase().as_ref() {
+            "-k" => {
+                let text = value("a number of pieces")?;
+                max_pieces = text
+                    .parse()
+                    .ok()
+                    .filter(|&k| k > 0 && k <= MAX_PIECES)
+                    .ok_or_else(|| format!("Invalid number of pieces: {}", text))?;
+            }
+            "-j" => threads = parse_threads(&mut args)?,
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
     }
+    Ok(TablebaseArgs {
+        path,
+        cards,
+        max_pieces,
+        threads,
+    })
 }
 
-fn is_human(hb: Option<String>) -> Result<bool, String> {
-    match hb.as_deref() {
-        Some("-h") => Ok(true),
-        Some(word) => Err(format!("Unrecognised word: {}", word)),
-        None => Ok(false),
+// book [file] [-n deals|all] [-p plies] [-j threads] [-c config]
+fn parse_book_args(mut args: impl Iterator<Item = String>) -> Result<BookArgs, String> {
+    let path = args
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| "You need to give a file for the book".to_string())?;
+    let mut deals = BookDeals::Random(DEFAULT_BOOK_DEALS);
+    let mut plies = DEFAULT_BOOK_PLIES;
+    let mut threads = 1;
+    let mut config = None;
+    while let Some(flag) = args.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            args.next()
+                .ok_or_else(|| format!("Expected {} after {}", name, flag))
+        };
+        match flag.to_lowercase().as_ref() {
+            "-n" => {
+                let text = value("a number of deals")?;
+                deals = if text.to_lowercase() == "all" {
+                    BookDeals::All
+                } else {
+                    text.parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .map(BookDeals::Random)
+                        .ok_or_else(|| format!("Invalid number of deals: {}", text))?
+                };
+            }
+            "-p" => {
+                let text = value("a number of plies")?;
+                plies = text
+                    .parse()
+                    .ok()
+                    .filter(|&p| p > 0)
+                    .ok_or_else(|| format!("Invalid number of plies: {}", text))?;
+            }
+            "-j" => threads = parse_threads(&mut args)?,
+            "-c" => config = Some(PathBuf::from(value("a config file")?)),
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
+    }
+    Ok(BookArgs {
+        path,
+        deals,
+        plies,
+        threads,
+        config,
+    })
+}
+
+// server [-p port]
+fn parse_server_args(mut args: impl Iterator<Item = String>) -> Result<u16, String> {
+    let mut port = DEFAULT_PORT;
+    while let Some(flag) = args.next() {
+        match flag.to_lowercase().as_ref() {
+            "-p" => {
+                let text = args
+                    .next()
+                    .ok_or_else(|| "Expected a port after -p".to_string())?;
+                port = text
+                    .parse()
+                    .map_err(|_| format!("Invalid port: {}", text))?;
+            }
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
+    }
+    Ok(port)
+}
+
+// analyse [board] [side] [red cards] [blue cards] [table card] [-d depth] [-t seconds] [-c config]
+// searches for DEFAULT_TIME_LIMIT seconds unless there is a limit
+fn parse_analyse_args(mut args: impl Iterator<Item = String>) -> Result<AnalyseArgs, String> {
+    let notation: Vec<String> = args.by_ref().take(5).collect();
+    let position = Game::from_notation(&notation.join(" "))?;
+    let mut depth = None;
+    let mut time = None;
+    let mut config = None;
+    while let Some(flag) = args.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            args.next()
+                .ok_or_else(|| format!("Expected {} after {}", name, flag))
+        };
+        match flag.to_lowercase().as_ref() {
+            "-d" => {
+                let text = value("a depth")?;
+                depth = Some(
+                    text.parse()
+                        .ok()
+                        .filter(|&d| d > 0 && d <= MAX_DEPTH)
+                        .ok_or_else(|| format!("Invalid depth: {}", text))?,
+                );
+            }
+            "-t" => {
+                let text = value("a number of seconds")?;
+                time = Some(
+                    text.parse()
+                        .ok()
+                        .filter(|s: &f64| *s > 0.)
+                        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                        .ok_or_else(|| format!("Invalid time limit: {}", text))?,
+                );
+            }
+            "-c" => config = Some(PathBuf::from(value("a config file")?)),
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
+    }
+    if depth.is_none() && time.is_none() {
+        time = Some(Duration::from_secs_f64(DEFAULT_TIME_LIMIT));
+    }
+    Ok(AnalyseArgs {
+        position,
+        depth: depth.unwrap_or(MAX_DEPTH),
+        time,
+        config,
+    })
+}
+
+// the number after -j, the same for every command
+fn parse_threads(args: &mut impl Iterator<Item = String>) -> Result<usize, String> {
+    let text = args
+        .next()
+        .ok_or_else(|| "Expected a number of threads after -j".to_string())?;
+    text.parse()
+        .ok()
+        .filter(|&t| t > 0)
+        .ok_or_else(|| format!("Invalid number of threads: {}", text))
+}
+
+fn parse_depth(text: &str) -> Result<u8, String> {
+    text.parse()
+        .ok()
+        .filter(|&d| d > 0)
+        .ok_or_else(|| format!("Invalid depth: {}", text))
+}
+
+// perft [board] [side] [red cards] [blue cards] [table card] [depth] [--divide] [--bulk] [-j threads]
+// perft --suite [file] [-d depth] [--bulk] [-j threads]
+fn parse_perft_args(mut args: impl Iterator<Item = String>) -> Result<PerftArgs, String> {
+    let first = args
+        .next()
+        .ok_or_else(|| "Expected a position or --suite".to_string())?;
+    let mut positions = if first.to_lowercase() == "--suite" {
+        let path = args
+            .next()
+            .ok_or_else(|| "Expected a file after --suite".to_string())?;
+        PerftPositions::Suite(PathBuf::from(path), None)
+    } else {
+        let notation: Vec<String> = Some(first)
+            .into_iter()
+            .chain(args.by_ref().take(4))
+            .collect();
+        let position = Game::from_notation(&notation.join(" "))?;
+        let text = args
+            .next()
+            .ok_or_else(|| "Expected a depth after the position".to_string())?;
+        PerftPositions::One(position, parse_depth(&text)?)
+    };
+    let mut divide = false;
+    let mut bulk = false;
+    let mut threads = 1;
+    while let Some(flag) = args.next() {
+        let mut value = |name: &str| -> Result<String, String> {
+            args.next()
+                .ok_or_else(|| format!("Expected {} after {}", name, flag))
+        };
+        match flag.to_lowercase().as_ref() {
+            "--divide" => divide = true,
+            "--bulk" => bulk = true,
+            "-d" => match &mut positions {
+                PerftPositions::Suite(_, max_depth) => {
+                    *max_depth = Some(parse_depth(&value("a depth")?)?)
+                }
+                PerftPositions::One(..) => {
+                    return Err("-d only limits the depth of a suite".to_string())
+                }
+            },
+            "-j" => threads = parse_threads(&mut args)?,
+            _ => return Err(format!("Unrecognised word: {}", flag)),
+        }
+    }
+    if divide {
+        if let PerftPositions::Suite(..) = positions {
+            return Err("--divide needs a single position".to_string());
+        }
     }
+    Ok(PerftArgs {
+        positions,
+        divide,
+        bulk,
+        threads,
+    })
 }
@@ -1,6 +1,7 @@
 use std::result::Result;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Colour {
     Red,
     Blue,
@@ -14,6 +15,13 @@ impl Colour {
         }
     }
 
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Colour::Red => "red",
+            Colour::Blue => "blue",
+        }
+    }
+
     pub fn from(colour: String) -> Result<Colour, String> {
         match colour.as_ref() {
             "red" => Ok(Colour::Red),
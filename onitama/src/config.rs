@@ -0,0 +1,216 @@
+use crate::bot::{Searcher, Value, MAX_DEPTH};
+use crate::eval::{AnyEvaluator, Heuristic, PieceSquareTables};
+use crate::external::External;
+use crate::game::{Game, Move};
+use crate::nnue::Nnue;
+use crate::tablebase::Tablebase;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+// An engine configuration, saved as JSON. Every field is optional:
+//
+// {
+//   "name": "more squares",
+//   "weights": { "piece": 10, "square": 2, "check": 10 },
+//   "tables": "tables.json",
+//   "network": "network.json",
+//   "tablebase": "tiger-crab-boar-ox-mantis.tb",
+//   "depth": 8,
+//   "nodes": 50000,
+//   "time": 0.1,
+//   "threads": 1,
+//   "command": ["./onitama", "engine"],
+//   "timeout": 60
+// }
+//
+// `time` is in seconds per move. The search stops at whichever limit is hit first,
+// without any limit it searches DEFAULT_NODES nodes per move.
+// `weights` are for the default heuristic evaluator. When `tables` or `network` is given
+// the engine evaluates with piece square tables (see eval.rs) or a neural network
+// (see nnue.rs) instead. With a `tablebase` the search plays the endgames of its cards
+// perfectly (see tablebase.rs). Paths are relative to the config file.
+// The name defaults to the name of the file.
+// With a `command` the engine is another program speaking the protocol in engine.rs,
+// started in the directory of the config file and told the depth, nodes and time.
+// It loses when it takes longer than `timeout` seconds to answer (default 60).
+// Only the arena and tournaments play external engines.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    pub name: String,
+    pub weights: Heuristic,
+    pub tables: Option<PathBuf>,
+    pub network: Option<PathBuf>,
+    pub tablebase: Option<PathBuf>,
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub time: Option<f64>,
+    pub threads: usize,
+    pub command: Option<Vec<String>>,
+    pub timeout: f64,
+    #[serde(skip)]
+    pub evaluator: AnyEvaluator,
+    #[serde(skip)]
+    pub endgames: Option<Arc<Tablebase>>,
+    // where the config was loaded from
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+// a config ready to play: searching in this process or asking another program
+pub enum Player {
+    Internal(Box<Searcher<AnyEvaluator>>),
+    External(External),
+}
+
+const DEFAULT_NODES: u64 = 50_000;
+
+impl Default for EngineConfig {
+    fn default() -> EngineConfig {
+        EngineConfig {
+            name: "default".to_string(),
+            weights: Heuristic::default(),
+            tables: None,
+            network: None,
+            tablebase: None,
+            depth: None,
+            nodes: None,
+            time: None,
+            threads: 1,
+            command: None,
+            timeout: 60.,
+            evaluator: AnyEvaluator::default(),
+            endgames: None,
+            dir: PathBuf::new(),
+        }
+    }
+}
+
+impl EngineConfig {
+    // `default` is the built in configuration
+    pub fn load(path: &Path) -> Result<EngineConfig, String> {
+        if path == Path::new("default") {
+            return Ok(EngineConfig::default());
+        }
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut config: EngineConfig =
+            serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if config.name == EngineConfig::default().name {
+            if let Some(stem) = path.file_stem() {
+                config.name = stem.to_string_lossy().into_owned();
+            }
+        }
+        if config.threads == 0 {
+            return Err(format!("{}: threads must be at least 1", path.display()));
+        }
+        if let Some(time) = config.time {
+            if !time.is_finite() || time <= 0. {
+                return Err(format!("{}: invalid time {}", path.display(), time));
+            }
+        }
+        if !config.timeout.is_finite() || config.timeout <= 0. {
+            return Err(format!(
+                "{}: invalid timeout {}",
+                path.display(),
+                config.timeout
+            ));
+        }
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        config.dir = dir.to_path_buf();
+        config.evaluator = match (&config.network, &config.tables) {
+            (Some(network), _) => AnyEvaluator::Network(Nnue::load(&dir.join(network))?),
+            (None, Some(tables)) => {
+                AnyEvaluator::Tables(Box::new(PieceSquareTables::load(&dir.join(tables))?))
+            }
+            (None, None) => AnyEvaluator::Heuristic(config.weights),
+        };
+        if let Some(tablebase) = &config.tablebase {
+            config.endgames = Some(Arc::new(Tablebase::load(&dir.join(tablebase))?));
+        }
+        Ok(config)
+    }
+
+    pub fn searcher(&self) -> Searcher<AnyEvaluator> {
+        let mut searcher = Searcher::with_evaluator(self.evaluator.clone());
+        searcher.threads = self.threads;
+        searcher.tablebase = self.endgames.clone();
+        searcher
+    }
+
+    pub fn player(&self) -> Result<Player, String> {
+        match &self.command {
+            Some(command) => {
+                let timeout = Duration::from_secs_f64(self.timeout);
+                External::start(command, &self.dir, timeout).map(Player::External)
+            }
+            None => Ok(Player::Internal(Box::new(self.searcher()))),
+        }
+    }
+
+    // the same limits as search, for an external engine
+    pub fn go_command(&self) -> String {
+        let unlimited = self.depth.is_none() && self.nodes.is_none() && self.time.is_none();
+        let mut go = "go".to_string();
+        if let Some(depth) = self.depth {
+            go.push_str(&format!(" depth {}", depth));
+        }
+        if let Some(nodes) = if unlimited {
+            Some(DEFAULT_NODES)
+        } else {
+            self.nodes
+        } {
+            go.push_str(&format!(" nodes {}", nodes));
+        }
+        if let Some(time) = self.time {
+            go.push_str(&format!(" movetime {}", (time * 1000.).ceil() as u64));
+        }
+        go
+    }
+
+    pub fn best_move(&self, player: &mut Player, game: &Game) -> Result<Move, String> {
+        match player {
+            Player::Internal(searcher) => {
+                self.evaluator.check_cards(game)?;
+                Ok(self.search(searcher, game).1)
+            }
+            Player::External(external) => external.best_move(game, &self.go_command()),
+        }
+    }
+
+    pub fn search(&self, searcher: &mut Searcher<AnyEvaluator>, game: &Game) -> (Value, Move) {
+        let unlimited = self.depth.is_none() && self.nodes.is_none() && self.time.is_none();
+        searcher.node_limit = if unlimited {
+            Some(DEFAULT_NODES)
+        } else {
+            self.nodes
+        };
+        let depth = self.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+        let time = self.time.map(Duration::from_secs_f64);
+        searcher.search(game, depth, time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config() {
+        let config: EngineConfig =
+            serde_json::from_str(r#"{ "weights": { "square": 3 }, "depth": 4 }"#).unwrap();
+        assert_eq!(config.weights.piece, Heuristic::default().piece);
+        assert_eq!(config.weights.square, 3);
+        assert_eq!(config.depth, Some(4));
+        assert_eq!(config.nodes, None);
+        assert_eq!(config.threads, 1);
+        assert_eq!(config.go_command(), "go depth 4");
+        assert_eq!(EngineConfig::default().go_command(), "go nodes 50000");
+        let config: EngineConfig =
+            serde_json::from_str(r#"{ "nodes": 1000, "time": 0.25 }"#).unwrap();
+        assert_eq!(config.go_command(), "go nodes 1000 movetime 250");
+    }
+}
@@ -1,76 +1,93 @@
+use crate::error::OnitamaError;
 use crate::game::{Game, Move};
 use crate::messages::*;
 use std::marker::Send;
 use std::result::Result;
-use websocket::result::WebSocketResult;
+use std::thread;
+use std::time::Duration;
 use websocket::sync::{client::Client, stream::NetworkStream};
 use websocket::{ClientBuilder, Message, OwnedMessage};
 
+// wait for a message matching the pattern, skipping other types of messages
+// and reporting messages that can't be read until the connection itself fails,
+// an error reply from the server means the message being waited for will never come
 macro_rules! recv_loop {
     ($self:ident, $p:pat => $e:expr) => {
         loop {
             match $self.recv() {
-                $p => break $e,
-                Ok(LitamaMessage::Error(msg)) => println!("Received error message: {}", msg.error),
-                Ok(msg) => println!("Received wrong message type {:#?}", msg),
-                Err(err) => println!("Error while receiving: {}", err),
+                $p => break Ok($e),
+                Ok(LitamaMessage::Error(msg)) => break Err(OnitamaError::Protocol(msg.error)),
+                Ok(_) => {}
+                Err(err) if err.is_fatal() => break Err(err),
+                Err(err) => eprintln!("Skipping a message: {}", err),
             }
         }
     };
 }
 
+// after the connection drops, wait this long before the first attempt to reconnect
+// and twice as long before each next one, up to the maximum
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(16);
+const RECONNECT_ATTEMPTS: u32 = 6;
+
 pub struct Participant {
     pub token: String,
     pub index: u8,
 }
 
 pub struct Connection {
+    address: String,
     client: Client<Box<dyn NetworkStream + Send>>,
+    // the match being watched, resumed when the connection drops
+    match_id: Option<String>,
+}
+
+fn connect(address: &str) -> Result<Client<Box<dyn NetworkStream + Send>>, OnitamaError> {
+    Ok(ClientBuilder::new(address)
+        .map_err(|e| OnitamaError::Network(e.to_string()))?
+        .connect(None)?)
 }
 
 impl Connection {
-    pub fn new(address: &str) -> Result<Connection, String> {
-        let client = ClientBuilder::new(address)
-            .map_err(|e| e.to_string())?
-            .connect(None)
-            .map_err(|e| e.to_string())?;
-        Ok(Connection { client })
+    pub fn new(address: &str) -> Result<Connection, OnitamaError> {
+        Ok(Connection {
+            address: address.to_string(),
+            client: connect(address)?,
+            match_id: None,
+        })
     }
 
-    fn send(&mut self, text: &str) -> WebSocketResult<()> {
+    fn send(&mut self, text: &str) -> Result<(), OnitamaError> {
         // println!("-> {}", text);
-        self.client.send_message(&Message::text(text))
+        Ok(self.client.send_message(&Message::text(text))?)
     }
 
-    fn recv(&mut self) -> WebSocketResult<LitamaMessage> {
-        let message = loop {
+    fn recv(&mut self) -> Result<LitamaMessage, OnitamaError> {
+        loop {
             match self.client.recv_message()? {
-                // if it's a parse error then the server protocol has probably changed and retrying won't help
                 OwnedMessage::Text(text) => {
                     // println!("<- {}", text);
-                    break serde_json::from_str::<LitamaMessage>(&text).unwrap();
+                    break Ok(serde_json::from_str::<LitamaMessage>(&text)?);
                 }
                 OwnedMessage::Binary(bytes) => {
-                    break serde_json::from_slice::<LitamaMessage>(&bytes).unwrap()
+                    break Ok(serde_json::from_slice::<LitamaMessage>(&bytes)?);
                 }
-                // another message that is not handled
-                OwnedMessage::Ping(data) => {
-                    self.client.send_message(&OwnedMessage::Pong(data)).unwrap()
+                OwnedMessage::Ping(data) => self.client.send_message(&OwnedMessage::Pong(data))?,
+                OwnedMessage::Pong(_) => {}
+                OwnedMessage::Close(data) => {
+                    // answer the close so the server can drop the connection cleanly
+                    let _ = self.client.send_message(&OwnedMessage::Close(None));
+                    let reason = data.map(|data| data.reason).unwrap_or_default();
+                    break Err(OnitamaError::Closed(reason));
                 }
-                // OwnedMessage::Pong(_) => panic!("todo"),
-                // OwnedMessage::Close(_) => panic!("todo"),
-                msg => panic!("Received unexpected message: {:#?}", msg),
             }
-        };
-        Ok(message)
+        }
     }
 
-    pub fn create_match(&mut self, username: &str) -> (String, Participant) {
-        // send create message
-        while let Err(err) = self.send(&format!("create {}", username)) {
-            println!("Error while sending: {}", err);
-        }
-        return recv_loop!(
+    pub fn create_match(&mut self, username: &str) -> Result<(String, Participant), OnitamaError> {
+        self.send(&format!("create {}", username))?;
+        recv_loop!(
             self,
             Ok(LitamaMessage::Create(msg)) => (
                 msg.match_id,
@@ -79,49 +96,205 @@ impl Connection {
                     index: msg.index,
                 },
             )
-        );
+        )
     }
 
-    pub fn join_match(&mut self, match_id: &str, username: &str) -> Participant {
-        // send join message
-        while let Err(err) = self.send(&format!("join {} {}", match_id, username)) {
-            println!("Error while sending: {}", err)
-        }
-        return recv_loop!(
+    pub fn join_match(
+        &mut self,
+        match_id: &str,
+        username: &str,
+    ) -> Result<Participant, OnitamaError> {
+        self.send(&format!("join {} {}", match_id, username))?;
+        recv_loop!(
             self,
             Ok(LitamaMessage::Join(msg)) => Participant {
                 token: msg.token,
                 index: msg.index,
             }
-        );
+        )
     }
 
-    pub fn spectate(&mut self, match_id: &str) -> StateMsg {
-        // send spectate message
-        while let Err(err) = self.send(&format!("spectate {}", match_id)) {
-            println!("Error while sending: {}", err)
-        }
+    // spectate the match and resume it from here on if the connection drops,
+    // the token of a participant stays valid on the new connection
+    pub fn spectate(&mut self, match_id: &str) -> Result<StateMsg, OnitamaError> {
+        self.match_id = Some(match_id.to_string());
+        self.watch(match_id)
+    }
+
+    fn watch(&mut self, match_id: &str) -> Result<StateMsg, OnitamaError> {
+        self.send(&format!("spectate {}", match_id))?;
         // confirm spectate
-        recv_loop!(self, Ok(LitamaMessage::Spectate(_)) => ());
+        recv_loop!(self, Ok(LitamaMessage::Spectate(_)) => ())?;
         loop {
-            let state_msg = self.recv_state();
+            let state_msg = recv_loop!(self, Ok(LitamaMessage::State(msg)) => *msg)?;
             if state_msg.game_state != "waiting for player" {
-                break state_msg;
+                break Ok(state_msg);
             }
         }
     }
 
-    pub fn recv_state(&mut self) -> StateMsg {
-        return recv_loop!(self, Ok(LitamaMessage::State(msg)) => *msg);
+    // the next state of the match, after a reconnect it is the state the match is in
+    // which may be the same state as before
+    pub fn recv_state(&mut self) -> Result<StateMsg, OnitamaError> {
+        let res = recv_loop!(self, Ok(LitamaMessage::State(msg)) => *msg);
+        self.resume_if_dropped(res)
     }
 
-    pub fn make_move(&mut self, m: &Move, match_id: &str, token: &str, game: &Game) -> StateMsg {
-        // send move
-        while let Err(err) = self.send(&move_to_command(m, match_id, token, game)) {
-            println!("Error while sending: {}", err)
+    pub fn make_move(
+        &mut self,
+        m: &Move,
+        match_id: &str,
+        token: &str,
+        game: &Game,
+    ) -> Result<StateMsg, OnitamaError> {
+        let res = self
+            .send(&move_to_command(m, match_id, token, game))
+            // confirm move
+            .and_then(|()| recv_loop!(self, Ok(LitamaMessage::Move(_)) => ()))
+            .and_then(|()| recv_loop!(self, Ok(LitamaMessage::State(msg)) => *msg));
+        // if the move was lost on the way the resumed state is still waiting for it
+        self.resume_if_dropped(res)
+    }
+
+    fn resume_if_dropped(
+        &mut self,
+        res: Result<StateMsg, OnitamaError>,
+    ) -> Result<StateMsg, OnitamaError> {
+        match (res, self.match_id.clone()) {
+            (Err(err), Some(match_id)) if err.is_fatal() => {
+                eprintln!("{}", err);
+                self.resume(&match_id)
+            }
+            (res, _) => res,
         }
-        // confirm move
-        recv_loop!(self, Ok(LitamaMessage::Move(_)) => ());
-        self.recv_state()
+    }
+
+    // reconnect with exponential backoff and spectate the match again
+    fn resume(&mut self, match_id: &str) -> Result<StateMsg, OnitamaError> {
+        let mut backoff = FIRST_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            thread::sleep(backoff);
+            eprintln!("reconnecting, attempt {}/{}", attempt, RECONNECT_ATTEMPTS);
+            let res = connect(&self.address).and_then(|client| {
+                self.client = client;
+                self.watch(match_id)
+            });
+            // the error of the last attempt is returned
+            match res {
+                Err(err) if err.is_fatal() && attempt < RECONNECT_ATTEMPTS => eprintln!("{}", err),
+                res => return res,
+            }
+            attempt += 1;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use websocket::sync::Server;
+
+    fn state(board: &str) -> String {
+        format!(
+            r#"{{"messageType": "state", "usernames": {{"red": "a", "blue": "b"}},
+            "indices": {{"red": 0, "blue": 1}}, "matchId": "m", "currentTurn": "blue",
+            "cards": {{"red": ["ox", "boar"], "blue": ["horse", "elephant"], "side": "crab"}},
+            "board": "{}", "gameState": "in progress", "winner": "none"}}"#,
+            board
+        )
+    }
+
+    #[test]
+    fn test_resume() {
+        let boards = [
+            "1121100000000000000033433",
+            "1120100001000000000033433",
+            "1120100001000000003033403",
+        ];
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let address = format!("ws://{}", server.local_addr().unwrap());
+        // a mock litama server that drops the connection after every state,
+        // first with a close frame and then without one
+        let mock = thread::spawn(move || {
+            for (i, board) in boards.iter().enumerate() {
+                let mut client = server.accept().ok().unwrap().accept().unwrap();
+                let request = client.recv_message().unwrap();
+                assert_eq!(request, OwnedMessage::Text("spectate m".to_string()));
+                let spectate = r#"{"messageType": "spectate", "matchId": "m"}"#;
+                client.send_message(&Message::text(spectate)).unwrap();
+                client.send_message(&Message::text(state(board))).unwrap();
+                match i {
+                    0 => client.send_message(&Message::close()).unwrap(),
+                    1 => client.shutdown().unwrap(),
+                    // keep the last connection open until the test is done with it
+                    _ => while client.recv_message().is_ok() {},
+                }
+            }
+        });
+
+        let mut conn = Connection::new(&address).unwrap();
+        for board in boards.iter() {
+            let state_msg = if conn.match_id.is_none() {
+                conn.spectate("m").unwrap()
+            } else {
+                conn.recv_state().unwrap()
+            };
+            assert_eq!(&state_msg.board, board);
+        }
+        drop(conn);
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn test_skip_unreadable() {
+        let board = "1121100000000000000033433";
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let address = format!("ws://{}", server.local_addr().unwrap());
+        let mock = thread::spawn(move || {
+            let mut client = server.accept().ok().unwrap().accept().unwrap();
+            client.recv_message().unwrap();
+            let spectate = r#"{"messageType": "spectate", "matchId": "m"}"#;
+            client.send_message(&Message::text(spectate)).unwrap();
+            client.send_message(&Message::text("not json")).unwrap();
+            client.send_message(&Message::text(state(board))).unwrap();
+            while client.recv_message().is_ok() {}
+        });
+
+        let mut conn = Connection::new(&address).unwrap();
+        assert_eq!(conn.spectate("m").unwrap().board, board);
+        drop(conn);
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn test_move_refused() {
+        let board = "1121100000000000000033433";
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let address = format!("ws://{}", server.local_addr().unwrap());
+        let mock = thread::spawn(move || {
+            let mut client = server.accept().ok().unwrap().accept().unwrap();
+            client.recv_message().unwrap();
+            let spectate = r#"{"messageType": "spectate", "matchId": "m"}"#;
+            client.send_message(&Message::text(spectate)).unwrap();
+            client.send_message(&Message::text(state(board))).unwrap();
+            // refuse the move
+            client.recv_message().unwrap();
+            let error = r#"{"messageType": "error", "matchId": "m", "error": "invalid move",
+                "query": "move m t"}"#;
+            client.send_message(&Message::text(error)).unwrap();
+            while client.recv_message().is_ok() {}
+        });
+
+        let mut conn = Connection::new(&address).unwrap();
+        let game = Game::from_state_msg(&conn.spectate("m").unwrap()).unwrap();
+        let m = game.gen_moves()[0];
+        assert_eq!(
+            conn.make_move(&m, "m", "t", &game).map(|_| ()),
+            Err(OnitamaError::Protocol("invalid move".to_string()))
+        );
+        drop(conn);
+        mock.join().unwrap();
     }
 }
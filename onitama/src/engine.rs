@@ -0,0 +1,387 @@
+use crate::book::Book;
+use crate::bot::{Report, Searcher, MAX_DEPTH};
+use crate::cards::Card;
+use crate::colour::Colour;
+use crate::eval::{AnyEvaluator, PieceSquareTables};
+use crate::game::Game;
+use crate::messages::{move_to_text, parse_move};
+use crate::nnue::Nnue;
+use crate::tablebase::Tablebase;
+
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::result::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// A text protocol in the spirit of UCI, served over stdin and stdout.
+// Moves are written like litama moves, e.g. `tiger c1c3`.
+// Commands that change the position or the engine end a running search first.
+//
+// onitama                          -> id name, id author, onitamaok
+// isready                          -> readyok
+// setoption name threads value [n]
+// setoption name tables value [file|default]
+//                                  evaluate with piece square tables, see eval.rs
+// setoption name network value [file|default]
+//                                  evaluate with a neural network, see nnue.rs
+// setoption name tablebase value [file|none]
+//                                  look up exact endgame values, see tablebase.rs
+// setoption name book value [file|none]
+//                                  play book moves without searching, see book.rs
+// newgame                          forget everything learned from earlier searches
+// position startpos cards [red1] [red2] [blue1] [blue2] [side] [moves ...]
+// position board [25 digits] turn [red|blue] cards [...] [moves ...]
+//                                  the board is in the same format as litama
+// position notation [board] [turn] [red cards] [blue cards] [side] [moves ...]
+//                                  see notation.rs
+// go [depth n] [nodes n] [movetime ms] [infinite]
+//                                  without any limits it searches until stop, like infinite,
+//                                  and only then answers with bestmove
+//                                  -> info depth [d] score [value] nodes [n] nps [n] time [ms] pv [moves]
+//                                  -> bestmove [card] [pos]
+// stop                             end the search early
+// d                                print the current position
+// quit
+
+struct Engine {
+    game: Game,
+    threads: usize,
+    evaluator: AnyEvaluator,
+    tablebase: Option<Arc<Tablebase>>,
+    book: Option<Book>,
+    searcher: Option<Searcher<AnyEvaluator>>,
+    // an infinite search hands back its bestmove line to be printed on stop
+    search: Option<JoinHandle<(Searcher<AnyEvaluator>, Option<String>)>>,
+    stop: Arc<AtomicBool>,
+}
+
+pub fn run() -> Result<(), String> {
+    let mut engine = Engine {
+        game: Game::new(),
+        threads: 1,
+        evaluator: AnyEvaluator::default(),
+        tablebase: None,
+        book: None,
+        searcher: Some(Searcher::with_evaluator(AnyEvaluator::default())),
+        search: None,
+        stop: Arc::new(AtomicBool::new(false)),
+    };
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        // keep the case of the arguments, the position notation needs it
+        let mut words = line.split_whitespace().map(String::from);
+        let command = match words.next() {
+            Some(command) => command.to_lowercase(),
+            None => continue,
+        };
+        let words: Vec<String> = words.collect();
+        let res = match command.as_ref() {
+            "onitama" => {
+                println!("id name Onitama");
+                println!("id author Viliam Vadocz");
+                println!("onitamaok");
+                Ok(())
+            }
+            "isready" => {
+                println!("readyok");
+                Ok(())
+            }
+            "setoption" => engine.set_option(&words),
+            "newgame" => {
+                engine.stop_search();
+                engine.searcher = Some(engine.new_searcher());
+                Ok(())
+            }
+            "position" => engine.set_position(&words),
+            "go" => engine.go(&words),
+            "stop" => {
+                engine.stop_search();
+                Ok(())
+            }
+            "d" => {
+                println!("{}", engine.game);
+                println!("{}", engine.game.to_notation());
+                Ok(())
+            }
+            "quit" => break,
+            _ => Err(format!("Unknown command: {}", command)),
+        };
+        if let Err(err) = res {
+            println!("info string {}", err);
+        }
+    }
+    engine.stop_search();
+    Ok(())
+}
+
+impl Engine {
+    // end the current search, it still answers with its best move,
+    // so that commands sent during an infinite search don't wait for it forever
+    // a search that panicked didn't answer, so it answers with any legal move instead
+    fn stop_search(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.search.take() {
+            match handle.join() {
+                Ok((searcher, bestmove)) => {
+                    if let Some(line) = bestmove {
+                        println!("{}", line);
+                    }
+                    self.searcher = Some(searcher);
+                }
+                Err(panic) => {
+                    let reason = panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    println!("info string the search failed: {}", reason);
+                    if let Some(m) = self.game.gen_moves().first() {
+                        println!("bestmove {}", move_to_text(m, &self.game));
+                    }
+                    self.searcher = Some(self.new_searcher());
+                }
+            }
+        }
+    }
+
+    fn new_searcher(&self) -> Searcher<AnyEvaluator> {
+        let mut searcher = Searcher::with_evaluator(self.evaluator.clone());
+        searcher.tablebase = self.tablebase.clone();
+        searcher
+    }
+
+    fn set_option(&mut self, words: &[String]) -> Result<(), String> {
+        // the value is everything after `value`, so that a path can have spaces
+        let (option, value) = match words {
+            [name, option, value, rest @ ..]
+                if name == "name" && value == "value" && !rest.is_empty() =>
+            {
+                (option, rest.join(" "))
+            }
+            _ => return Err("Expected `setoption name [option] value [value]`".to_string()),
+        };
+        let value = value.as_str();
+        match option.to_lowercase().as_ref() {
+            "threads" => {
+                self.threads = value
+                    .parse()
+                    .ok()
+                    .filter(|&t| t > 0)
+                    .ok_or_else(|| format!("Invalid number of threads: {}", value))?;
+                Ok(())
+            }
+            "tables" | "network" => {
+                self.evaluator = if value.to_lowercase() == "default" {
+                    AnyEvaluator::default()
+                } else if option.to_lowercase() == "tables" {
+                    AnyEvaluator::Tables(Box::new(PieceSquareTables::load(Path::new(value))?))
+                } else {
+                    AnyEvaluator::Network(Nnue::load(Path::new(value))?)
+                };
+                // the old table entries were scored differently
+                self.stop_search();
+                self.searcher = Some(self.new_searcher());
+                Ok(())
+            }
+            "tablebase" => {
+                self.tablebase = if value.to_lowercase() == "none" {
+                    None
+                } else {
+                    Some(Arc::new(Tablebase::load(Path::new(value))?))
+                };
+                self.stop_search();
+                if let Some(searcher) = &mut self.searcher {
+                    searcher.tablebase = self.tablebase.clone();
+                }
+                Ok(())
+            }
+            "book" => {
+                self.book = if value.to_lowercase() == "none" {
+                    None
+                } else {
+                    Some(Book::load(Path::new(value))?)
+                };
+                Ok(())
+            }
+            _ => Err(format!("Unknown option: {}", option)),
+        }
+    }
+
+    fn set_position(&mut self, words: &[String]) -> Result<(), String> {
+        self.stop_search();
+        let mut words = words.iter().map(String::as_str);
+        let board = match words.next() {
+            Some("notation") => {
+                let notation: Vec<_> = words.by_ref().take(5).collect();
+                let game = Game::from_notation(&notation.join(" "))?;
+                return self.play_moves(game, words);
+            }
+            Some("startpos") => None,
+            Some("board") => {
+                let board = words.next().ok_or("Expected a board")?;
+                if board.len() != 25 || !board.chars().all(|c| ('0'..='4').contains(&c)) {
+                    return Err(format!("Invalid board: {}", board));
+                }
+                // a missing king would be taken as captured
+                if board.matches('2').count() != 1 || board.matches('4').count() != 1 {
+                    return Err(format!("The board needs one king per side: {}", board));
+                }
+                if words.next() != Some("turn") {
+                    return Err("Expected `turn` after the board".to_string());
+                }
+                let colour = Colour::from(words.next().ok_or("Expected a colour")?.to_lowercase())?;
+                Some((board, colour))
+            }
+            _ => return Err("Expected `startpos` or `board`".to_string()),
+        };
+
+        if words.next() != Some("cards") {
+            return Err("Expected `cards`".to_string());
+        }
+        let mut cards = Vec::new();
+        for i in 0..5 {
+            let text = words
+                .next()
+                .ok_or_else(|| format!("Expected 5 cards, got {}", i))?;
+            cards.push(Card::from_text(&text.to_lowercase())?);
+        }
+        let game = match board {
+            None => Game::from_cards(cards),
+            Some((board, colour)) => {
+                let mut game = Game::from_board(
+                    board,
+                    colour,
+                    [cards[0], cards[1]],
+                    [cards[2], cards[3]],
+                    cards[4],
+                    true,
+                );
+                // a king already on the other side's temple has won
+                let (red, blue) = game.get_red_blue();
+                game.in_progress = red.king != 2 && blue.king != 22;
+                game
+            }
+        };
+
+        self.play_moves(game, words)
+    }
+
+    // play out the optional list of moves and set the position
+    fn play_moves<'a>(
+        &mut self,
+        mut game: Game,
+        mut words: impl Iterator<Item = &'a str>,
+    ) -> Result<(), String> {
+        match words.next() {
+            None => {}
+            Some("moves") => {
+                let words: Vec<_> = words.collect();
+                for pair in words.chunks(2) {
+                    let text = pair.join(" ");
+                    let m = parse_move(&text, &game)?;
+                    game.validate_move(&m)
+                        .map_err(|e| format!("Illegal move {}: {}", text, e))?;
+                    game = game.take_turn(&m);
+                }
+            }
+            Some(word) => return Err(format!("Unrecognised word: {}", word)),
+        }
+        self.game = game;
+        Ok(())
+    }
+
+    fn go(&mut self, words: &[String]) -> Result<(), String> {
+        self.stop_search();
+        if !self.game.in_progress {
+            return Err("The game is over".to_string());
+        }
+        self.evaluator.check_cards(&self.game)?;
+        let mut depth = MAX_DEPTH;
+        let mut nodes = None;
+        let mut time = None;
+        let mut limited = false;
+        let mut infinite = false;
+        let mut words = words.iter();
+        while let Some(word) = words.next() {
+            let mut number = || -> Result<u64, String> {
+                let text = words
+                    .next()
+                    .ok_or_else(|| format!("Expected a number after {}", word))?;
+                text.parse()
+                    .map_err(|_| format!("Invalid number: {}", text))
+            };
+            match word.as_ref() {
+                "depth" => {
+                    depth = number()?.clamp(1, MAX_DEPTH as u64) as u8;
+                    limited = true;
+                }
+                "nodes" => {
+                    nodes = Some(number()?);
+                    limited = true;
+                }
+                "movetime" => {
+                    time = Some(Duration::from_millis(number()?));
+                    limited = true;
+                }
+                "infinite" => infinite = true,
+                _ => return Err(format!("Unrecognised word: {}", word)),
+            }
+        }
+        let infinite = infinite || !limited;
+        // an infinite search is analysis, the book only answers a search with limits
+        if let Some(m) = self
+            .book
+            .as_ref()
+            .filter(|_| !infinite)
+            .and_then(|book| book.probe(&self.game))
+        {
+            println!("info string book move");
+            println!("bestmove {}", move_to_text(&m, &self.game));
+            return Ok(());
+        }
+
+        let mut searcher = self.searcher.take().unwrap();
+        self.stop = Arc::new(AtomicBool::new(false));
+        searcher.stop = Arc::clone(&self.stop);
+        searcher.threads = self.threads;
+        searcher.node_limit = nodes;
+        let game = self.game.clone();
+        self.search = Some(thread::spawn(move || {
+            let (_, m) = searcher.search_reporting(&game, depth, time, |report| {
+                println!("{}", info_line(&game, report))
+            });
+            let bestmove = format!("bestmove {}", move_to_text(&m, &game));
+            // a search that ends early on its own still waits for stop
+            if infinite {
+                (searcher, Some(bestmove))
+            } else {
+                println!("{}", bestmove);
+                (searcher, None)
+            }
+        }));
+        Ok(())
+    }
+}
+
+fn info_line(game: &Game, report: &Report) -> String {
+    let mut pv = Vec::new();
+    let mut game = game.clone();
+    for m in report.pv.iter() {
+        pv.push(move_to_text(m, &game));
+        game = game.take_turn(m);
+    }
+    let nps = (report.nodes as f64 / report.time.as_secs_f64().max(1e-3)) as u64;
+    format!(
+        "info depth {} score {} nodes {} nps {} time {} pv {}",
+        report.depth,
+        report.value,
+        report.nodes,
+        nps,
+        report.time.as_millis(),
+        pv.join(" ")
+    )
+}
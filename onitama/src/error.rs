@@ -0,0 +1,61 @@
+use std::fmt;
+use websocket::WebSocketError;
+
+// Everything that can go wrong while playing, most of it online.
+// Network and Closed end the connection, the others are about a single message
+// and the game can usually carry on with the next one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OnitamaError {
+    // the connection to the server failed
+    Network(String),
+    // the server closed the connection, with its reason if it gave one
+    Closed(String),
+    // the server sent something that doesn't fit the protocol
+    Protocol(String),
+    // a message or a part of it couldn't be read
+    Parse(String),
+    // errors from the rest of the program, e.g. the front end
+    Other(String),
+}
+
+impl OnitamaError {
+    // whether the connection is gone and waiting for more messages won't help
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, OnitamaError::Network(_) | OnitamaError::Closed(_))
+    }
+}
+
+impl fmt::Display for OnitamaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OnitamaError::Network(err) => write!(f, "Network error: {}", err),
+            OnitamaError::Closed(reason) if reason.is_empty() => {
+                write!(f, "The server closed the connection")
+            }
+            OnitamaError::Closed(reason) => {
+                write!(f, "The server closed the connection: {}", reason)
+            }
+            OnitamaError::Protocol(err) => write!(f, "Protocol error: {}", err),
+            OnitamaError::Parse(err) => write!(f, "Parse error: {}", err),
+            OnitamaError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<WebSocketError> for OnitamaError {
+    fn from(err: WebSocketError) -> Self {
+        OnitamaError::Network(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for OnitamaError {
+    fn from(err: serde_json::Error) -> Self {
+        OnitamaError::Parse(err.to_string())
+    }
+}
+
+impl From<String> for OnitamaError {
+    fn from(err: String) -> Self {
+        OnitamaError::Other(err)
+    }
+}
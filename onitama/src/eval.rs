@@ -0,0 +1,259 @@
+use bitwise::TestBit;
+
+use crate::cards::{shift_bitmap, BitIter};
+use crate::colour::Colour;
+use crate::game::Game;
+use crate::nnue::Nnue;
+
+use std::fs;
+use std::path::Path;
+use std::result::Result;
+
+// the search only needs a score for positions it doesn't search any deeper
+// positive is good for the player to move, negative is good for the other
+pub trait Evaluator: Clone + Send + 'static {
+    fn evaluate(&self, g: &Game) -> i64;
+
+    // the search passes the hash it keeps of the position (see zobrist.rs),
+    // so that an evaluator with incremental state doesn't have to compute it
+    fn evaluate_hashed(&self, g: &Game, _hash: u64) -> i64 {
+        self.evaluate(g)
+    }
+
+    // called around every move the search makes with the hashes of the positions
+    // before and after it, so that an evaluator can update its state incrementally
+    fn make_move(&mut self, _g: &Game, _hash: u64, _next: &Game, _next_hash: u64) {}
+    fn unmake_move(&mut self) {}
+
+    // whether mirrored positions (see symmetry.rs) get the same score, so that
+    // the search can share their table entries, every evaluator must give
+    // the same score when the board is rotated and the colours swapped
+    fn mirror_symmetric(&self) -> bool {
+        false
+    }
+}
+
+const PIECE_WEIGHT: i64 = 10;
+const SQUARE_WEIGHT: i64 = 1;
+const CHECK_WEIGHT: i64 = 10;
+
+// counts pieces, squares under control and threats on the kings,
+// missing weights in a config file fall back to the defaults
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Heuristic {
+    pub piece: i64,
+    pub square: i64,
+    pub check: i64,
+}
+
+impl Default for Heuristic {
+    fn default() -> Heuristic {
+        Heuristic {
+            piece: PIECE_WEIGHT,
+            square: SQUARE_WEIGHT,
+            check: CHECK_WEIGHT,
+        }
+    }
+}
+
+impl Evaluator for Heuristic {
+    // only counts, which don't change in a mirror
+    fn mirror_symmetric(&self) -> bool {
+        true
+    }
+
+    fn evaluate(&self, g: &Game) -> i64 {
+        let mut my_control = 0u32;
+        let my_card = g.my.cards[0].get_move(g.colour) | g.my.cards[1].get_move(g.colour);
+        for pos in BitIter(g.my.pieces) {
+            my_control |= shift_bitmap(my_card, pos);
+        }
+        let mut other_control = 0u32;
+        let other_card =
+            g.other.cards[0].get_move(g.colour.next()) | g.other.cards[1].get_move(g.colour.next());
+        for pos in BitIter(g.other.pieces) {
+            other_control |= shift_bitmap(other_card, pos);
+        }
+        let mut checks = 0i64;
+        if my_control.test_bit(g.other.king) {
+            checks += 1
+        }
+        if other_control.test_bit(g.my.king) {
+            checks -= 1
+        }
+        if shift_bitmap(my_card, g.my.king as u32).test_bit(g.goal()) {
+            checks += 1
+        }
+        if shift_bitmap(other_card, g.other.king as u32).test_bit(24 - g.goal()) {
+            checks -= 1
+        }
+        let square_diff = my_control.count_ones() as i64 - other_control.count_ones() as i64;
+        let piece_diff = g.my.pieces.count_ones() as i64 - g.other.pieces.count_ones() as i64;
+        self.piece * piece_diff + self.square * square_diff + self.check * checks
+    }
+}
+
+// Piece square tables, saved as JSON:
+//
+// {
+//   "pawn": [[12, 12, 12, 12, 12],
+//            [11, 12, 13, 12, 11],
+//            [10, 11, 12, 11, 10],
+//            [10, 10, 11, 10, 10],
+//            [10, 10, 10, 10, 10]],
+//   "king": [[ 0,  0,  0,  0,  0],
+//            [ 0,  1,  2,  1,  0],
+//            [ 0,  1,  1,  1,  0],
+//            [ 0,  0,  0,  0,  0],
+//            [ 0,  0,  0,  0,  0]]
+// }
+//
+// Each table is a value per square, seen from red's side of the board,
+// so the first row is blue's home row. Blue uses the tables rotated.
+// The pawn values also count as material.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PieceSquareTables {
+    pub pawn: [[i64; 5]; 5],
+    pub king: [[i64; 5]; 5],
+}
+
+impl PieceSquareTables {
+    pub fn load(path: &Path) -> Result<PieceSquareTables, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn side(&self, pieces: u32, king: u8, colour: Colour) -> i64 {
+        let lookup = |table: &[[i64; 5]; 5], pos: u8| {
+            let pos = match colour {
+                Colour::Red => pos,
+                Colour::Blue => 24 - pos,
+            } as usize;
+            table[pos / 5][pos % 5]
+        };
+        let mut total = 0;
+        for pos in BitIter(pieces) {
+            let pos = pos as u8;
+            total += if pos == king {
+                lookup(&self.king, pos)
+            } else {
+                lookup(&self.pawn, pos)
+            };
+        }
+        total
+    }
+}
+
+impl Evaluator for PieceSquareTables {
+    fn evaluate(&self, g: &Game) -> i64 {
+        self.side(g.my.pieces, g.my.king, g.colour)
+            - self.side(g.other.pieces, g.other.king, g.colour.next())
+    }
+}
+
+// for choosing the evaluator at runtime, e.g. from a config file
+#[derive(Clone, Debug)]
+pub enum AnyEvaluator {
+    Heuristic(Heuristic),
+    // boxed so that the other evaluators don't carry the size of the tables
+    Tables(Box<PieceSquareTables>),
+    Network(Nnue),
+}
+
+impl Default for AnyEvaluator {
+    fn default() -> AnyEvaluator {
+        AnyEvaluator::Heuristic(Heuristic::default())
+    }
+}
+
+impl AnyEvaluator {
+    // whether it can evaluate the positions of a game with these cards
+    pub fn check_cards(&self, g: &Game) -> Result<(), String> {
+        match self {
+            AnyEvaluator::Network(nnue) => nnue.check_cards(g),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Evaluator for AnyEvaluator {
+    fn evaluate(&self, g: &Game) -> i64 {
+        match self {
+            AnyEvaluator::Heuristic(heuristic) => heuristic.evaluate(g),
+            AnyEvaluator::Tables(tables) => tables.evaluate(g),
+            AnyEvaluator::Network(nnue) => nnue.evaluate(g),
+        }
+    }
+
+    fn evaluate_hashed(&self, g: &Game, hash: u64) -> i64 {
+        match self {
+            AnyEvaluator::Network(nnue) => nnue.evaluate_hashed(g, hash),
+            _ => self.evaluate(g),
+        }
+    }
+
+    fn make_move(&mut self, g: &Game, hash: u64, next: &Game, next_hash: u64) {
+        if let AnyEvaluator::Network(nnue) = self {
+            nnue.make_move(g, hash, next, next_hash);
+        }
+    }
+
+    fn unmake_move(&mut self) {
+        if let AnyEvaluator::Network(nnue) = self {
+            nnue.unmake_move();
+        }
+    }
+
+    fn mirror_symmetric(&self) -> bool {
+        match self {
+            AnyEvaluator::Heuristic(heuristic) => heuristic.mirror_symmetric(),
+            AnyEvaluator::Tables(tables) => tables.mirror_symmetric(),
+            AnyEvaluator::Network(nnue) => nnue.mirror_symmetric(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cards::Card;
+
+    const TABLES: &str = r#"{
+        "pawn": [[20, 20, 20, 20, 20],
+                 [15, 15, 15, 15, 15],
+                 [12, 12, 12, 12, 12],
+                 [11, 11, 11, 11, 11],
+                 [10, 10, 10, 10, 10]],
+        "king": [[0, 0, 0, 0, 0],
+                 [0, 0, 0, 0, 0],
+                 [0, 0, 0, 0, 0],
+                 [0, 0, 0, 0, 0],
+                 [0, 0, 0, 0, 0]]
+    }"#;
+
+    #[test]
+    fn test_piece_square_tables() {
+        let tables: PieceSquareTables = serde_json::from_str(TABLES).unwrap();
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        // the start is symmetric
+        assert_eq!(tables.evaluate(&game), 0);
+        for m in game.gen_moves() {
+            let next = game.take_turn(&m);
+            // nothing can move backwards from the home row,
+            // so any pawn that changes row moved forward
+            let value = -tables.evaluate(&next);
+            if m.from != game.my.king && m.from / 5 != m.to / 5 {
+                assert!(value > 0, "{:?}", m);
+            } else {
+                assert_eq!(value, 0, "{:?}", m);
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::game::{Game, Move};
+use crate::messages::parse_move;
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::result::Result;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// An engine in another process, spoken to over the protocol in engine.rs.
+// It is asked for one move at a time with the whole position, so it needs no history.
+// An engine that doesn't answer within its timeout loses, its output is read on another thread
+// so that the wait can end.
+
+pub struct External {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    timeout: Duration,
+}
+
+impl External {
+    // start the program in `dir` and wait until it is ready
+    pub fn start(command: &[String], dir: &Path, timeout: Duration) -> Result<External, String> {
+        let (program, args) = command.split_first().ok_or("The engine command is empty")?;
+        let mut process = Command::new(program);
+        process
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        if !dir.as_os_str().is_empty() {
+            process.current_dir(dir);
+        }
+        let mut child = process.spawn().map_err(|e| format!("{}: {}", program, e))?;
+        let stdin = child.stdin.take().ok_or("The engine has no stdin")?;
+        let stdout = child.stdout.take().ok_or("The engine has no stdout")?;
+        let (tx, lines) = channel();
+        // ends when the engine closes its stdout
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut external = External {
+            child,
+            stdin,
+            lines,
+            timeout,
+        };
+        external.send("onitama")?;
+        external.wait_for("onitamaok")?;
+        Ok(external)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", line)
+            .and_then(|()| self.stdin.flush())
+            .map_err(|e| format!("Could not talk to the engine: {}", e))
+    }
+
+    // the first line that starts with the word, info lines are skipped
+    fn wait_for(&mut self, word: &str) -> Result<String, String> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let line = match self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!(
+                        "The engine took longer than {} seconds to answer with {}",
+                        self.timeout.as_secs_f64(),
+                        word
+                    ))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(format!("The engine quit while waiting for {}", word))
+                }
+            };
+            let line = line.trim_start();
+            if line.split_whitespace().next() == Some(word) {
+                return Ok(line[word.len()..].trim().to_string());
+            }
+        }
+    }
+
+    // `go` is the go command with the engine's limits
+    pub fn best_move(&mut self, game: &Game, go: &str) -> Result<Move, String> {
+        self.send(&format!("position notation {}", game.to_notation()))?;
+        self.send(go)?;
+        let text = self.wait_for("bestmove")?;
+        let m = parse_move(&text, game)?;
+        game.validate_move(&m)
+            .map_err(|e| format!("Illegal move {}: {}", text, e))?;
+        Ok(m)
+    }
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        // an engine that already quit can't be told to, one that is still thinking won't listen
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -1,16 +1,122 @@
 use arrayvec::ArrayVec;
 use bitwise::{ClearBit, SetBit, TestBit};
 
-use crate::cards::{draw_cards, shift_bitmap, BitIter, Card};
+use crate::cards::{draw_cards, draw_cards_from, shift_bitmap, BitIter, Card, BASE_CARDS};
 use crate::colour::Colour;
+use crate::error::OnitamaError;
 use crate::messages::*;
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+// The Way of the Wind adds a neutral wind spirit, which starts in the middle of the board.
+// A player can move it with one of their cards instead of moving a piece, except with
+// the cards that have spirit moves: those move a piece and then the spirit.
+// The spirit can't capture and can't move onto a king, when it moves onto a pawn
+// of either colour they swap places. Nothing else can move onto the spirit.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Ruleset {
+    #[default]
+    Base,
+    WayOfTheWind,
+}
+
+impl Ruleset {
+    pub fn from_text(text: &str) -> Result<Ruleset, String> {
+        match text {
+            "base" => Ok(Ruleset::Base),
+            "wind" => Ok(Ruleset::WayOfTheWind),
+            _ => Err(format!("Unknown rules {}", text)),
+        }
+    }
+
+    // the cards random games are dealt from
+    pub fn deck(self) -> Vec<Card> {
+        Card::all()
+            .into_iter()
+            .filter(|card| {
+                card.index() < BASE_CARDS || (self == Ruleset::WayOfTheWind && card.is_dual())
+            })
+            .collect()
+    }
+}
+
+// a move of the wind spirit with an ordinary card goes from the square of the spirit
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Move {
     pub from: u8,
     pub to: u8,
     pub used_left_card: bool,
+    // where the wind spirit goes after the piece, with the cards that have spirit moves
+    pub spirit: Option<u8>,
+}
+
+// two cards with spirit moves, each moving any of 5 pieces to 4 squares and then the spirit
+// to 4 squares, have the most moves
+pub const MAX_MOVES: usize = 160;
+pub type Moves = ArrayVec<[Move; MAX_MOVES]>;
+
+// why a move from outside the engine can't be played
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IllegalMove {
+    GameOver,
+    OffBoard(u8),
+    NoPiece(u8),
+    OwnPiece(u8),
+    NotOnCard { card: Card, from: u8, to: u8 },
+    PassWithMoves,
+    PassWithoutKing,
+    NoSpirit,
+    OntoSpirit(u8),
+    SpiritOntoKing(u8),
+    // the card moves a piece and the spirit, or only one of them
+    SpiritMoveMissing(Card),
+    NoSpiritMove(Card),
+    PieceMoveMissing(Card),
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pos = |pos: &u8| translate_pos(*pos as usize);
+        match self {
+            IllegalMove::GameOver => write!(f, "The game is already over"),
+            IllegalMove::OffBoard(p) => write!(f, "Square {} is off the board", p),
+            IllegalMove::NoPiece(p) => write!(f, "You have no piece on {}", pos(p)),
+            IllegalMove::OwnPiece(p) => write!(f, "You can't take your own piece on {}", pos(p)),
+            IllegalMove::NotOnCard { card, from, to } => write!(
+                f,
+                "The {} card can't move from {} to {}",
+                card.get_name(),
+                pos(from),
+                pos(to)
+            ),
+            IllegalMove::PassWithMoves => write!(f, "You can only pass when no piece can move"),
+            IllegalMove::PassWithoutKing => {
+                write!(
+                    f,
+                    "A pass is written as the king moving onto its own square"
+                )
+            }
+            IllegalMove::NoSpirit => write!(f, "There is no wind spirit in this game"),
+            IllegalMove::OntoSpirit(p) => {
+                write!(f, "Only the wind spirit can move onto {}", pos(p))
+            }
+            IllegalMove::SpiritOntoKing(p) => {
+                write!(f, "The wind spirit can't move onto the king on {}", pos(p))
+            }
+            IllegalMove::SpiritMoveMissing(card) => {
+                write!(f, "The {} card also moves the wind spirit", card.get_name())
+            }
+            IllegalMove::NoSpiritMove(card) => write!(
+                f,
+                "The {} card doesn't move the wind spirit after a piece",
+                card.get_name()
+            ),
+            IllegalMove::PieceMoveMissing(card) => write!(
+                f,
+                "The {} card moves a piece before the wind spirit",
+                card.get_name()
+            ),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -27,6 +133,8 @@ pub struct Game {
     pub table_card: Card,
     pub colour: Colour,
     pub in_progress: bool,
+    // the wind spirit of the Way of the Wind, 0 without it
+    pub spirit: u32,
 }
 
 impl Game {
@@ -35,6 +143,26 @@ impl Game {
         Game::from_cards(cards)
     }
 
+    pub fn new_with(ruleset: Ruleset) -> Game {
+        Game::from_cards_with(draw_cards_from(&ruleset.deck()), ruleset)
+    }
+
+    pub fn from_cards_with(cards: Vec<Card>, ruleset: Ruleset) -> Game {
+        let mut game = Game::from_cards(cards);
+        if ruleset == Ruleset::WayOfTheWind {
+            game.spirit = 1 << 12;
+        }
+        game
+    }
+
+    pub fn ruleset(&self) -> Ruleset {
+        if self.spirit == 0 {
+            Ruleset::Base
+        } else {
+            Ruleset::WayOfTheWind
+        }
+    }
+
     pub fn goal(&self) -> u8 {
         match self.colour {
             Colour::Blue => 22,
@@ -84,10 +212,13 @@ impl Game {
             table_card,
             colour,
             in_progress: true,
+            spirit: 0,
         }
     }
 
     pub fn take_turn(&self, my_move: &Move) -> Game {
+        // only the wind spirit moves
+        let spirit_move = self.spirit.test_bit(my_move.from);
         // move king
         let king_move = self.my.king == my_move.from;
         // card management
@@ -96,18 +227,43 @@ impl Game {
         // king capture
         let king_capture = my_move.to == self.other.king;
 
-        let my = Player {
+        let mut my = Player {
             cards: [self.my.cards[card_index], self.table_card],
-            pieces: self.my.pieces.clear_bit(my_move.from).set_bit(my_move.to),
+            pieces: if spirit_move {
+                self.my.pieces
+            } else {
+                self.my.pieces.clear_bit(my_move.from).set_bit(my_move.to)
+            },
             king: if king_move { my_move.to } else { self.my.king },
         };
 
-        let other = Player {
+        let mut other = Player {
             cards: self.other.cards,
-            pieces: self.other.pieces.clear_bit(my_move.to),
+            pieces: if spirit_move {
+                self.other.pieces
+            } else {
+                self.other.pieces.clear_bit(my_move.to)
+            },
             king: if king_capture { 25 } else { self.other.king }, // move out of board, doesn't get displayed
         };
 
+        let mut spirit = self.spirit;
+        let spirit_to = if spirit_move {
+            Some(my_move.to)
+        } else {
+            my_move.spirit
+        };
+        if let Some(to) = spirit_to {
+            // a pawn where the spirit lands goes to where the spirit was
+            let from = spirit.trailing_zeros() as u8;
+            for player in [&mut my, &mut other].iter_mut() {
+                if player.pieces.test_bit(to) {
+                    player.pieces = player.pieces.clear_bit(to).set_bit(from);
+                }
+            }
+            spirit = 1 << to;
+        }
+
         // check for king capture or reaching end
         let in_progress = !king_capture && my.king != self.goal();
 
@@ -117,10 +273,41 @@ impl Game {
             table_card,
             colour: self.colour.next(),
             in_progress,
+            spirit,
+        }
+    }
+
+    // where the wind spirit can go after a piece moved from one square to another
+    fn spirit_targets(&self, pattern: u32, from: u8, to: u8) -> u32 {
+        let my_king = if from == self.my.king {
+            to
+        } else {
+            self.my.king
+        };
+        let kings = if to == self.other.king {
+            1 << my_king
+        } else {
+            1 << my_king | 1 << self.other.king
+        };
+        shift_bitmap(pattern, self.spirit.trailing_zeros()) & !kings
+    }
+
+    // if no available moves, you can skip, but you still need to use a card
+    fn push_passes(&self, moves: &mut Moves) {
+        for &used_left_card in [true, false].iter() {
+            moves.push(Move {
+                from: self.my.king,
+                to: self.my.king,
+                used_left_card,
+                spirit: None,
+            });
         }
     }
 
-    pub fn gen_moves(&self) -> ArrayVec<[Move; 40]> {
+    pub fn gen_moves(&self) -> Moves {
+        if self.spirit != 0 {
+            return self.gen_wind_moves();
+        }
         let left = self.my.cards[0].get_move(self.colour);
         let right = self.my.cards[1].get_move(self.colour);
         let mut moves = ArrayVec::new();
@@ -132,6 +319,7 @@ impl Game {
                     from: from_pos as u8,
                     to: to_pos as u8,
                     used_left_card: true,
+                    spirit: None,
                 });
             }
             let right_shifted = shift_bitmap(right, from_pos) & !self.my.pieces;
@@ -140,27 +328,163 @@ impl Game {
                     from: from_pos as u8,
                     to: to_pos as u8,
                     used_left_card: false,
+                    spirit: None,
                 });
             }
         }
-        // if no available moves, you can skip, but you still need to use a card
         if moves.is_empty() {
-            moves.push(Move {
-                from: self.my.king,
-                to: self.my.king,
-                used_left_card: true,
+            self.push_passes(&mut moves);
+        }
+
+        moves
+    }
+
+    // the moves with the wind spirit on the board, see Ruleset
+    fn gen_wind_moves(&self) -> Moves {
+        let spirit = self.spirit.trailing_zeros();
+        let kings = 1 << self.my.king | 1 << self.other.king;
+        let mut moves = Moves::new();
+        for (&card, &used_left_card) in self.my.cards.iter().zip([true, false].iter()) {
+            let pattern = card.get_move(self.colour);
+            let spirit_pattern = card.get_spirit_move(self.colour);
+            for from_pos in BitIter(self.my.pieces) {
+                let shifted = shift_bitmap(pattern, from_pos) & !self.my.pieces & !self.spirit;
+                for to_pos in BitIter(shifted) {
+                    let m = Move {
+                        from: from_pos as u8,
+                        to: to_pos as u8,
+                        used_left_card,
+                        spirit: None,
+                    };
+                    if spirit_pattern == 0 {
+                        moves.push(m);
+                        continue;
+                    }
+                    // the spirit stays where it is when it has nowhere to go
+                    let targets = self.spirit_targets(spirit_pattern, m.from, m.to);
+                    if targets == 0 {
+                        moves.push(m);
+                    }
+                    for spirit_to in BitIter(targets) {
+                        moves.push(Move {
+                            spirit: Some(spirit_to as u8),
+                            ..m
+                        });
+                    }
+                }
+            }
+            if spirit_pattern == 0 {
+                for to_pos in BitIter(shift_bitmap(pattern, spirit) & !kings) {
+                    moves.push(Move {
+                        from: spirit as u8,
+                        to: to_pos as u8,
+                        used_left_card,
+                        spirit: None,
+                    });
+                }
+            }
+        }
+        if moves.is_empty() {
+            self.push_passes(&mut moves);
+        }
+        moves
+    }
+
+    // take_turn trusts its move, so anything from a player, a file or the network
+    // is checked first, the legal moves are exactly the ones from gen_moves
+    pub fn validate_move(&self, m: &Move) -> Result<(), IllegalMove> {
+        if !self.in_progress {
+            return Err(IllegalMove::GameOver);
+        }
+        for &pos in [m.from, m.to].iter().chain(m.spirit.iter()) {
+            if pos >= 25 {
+                return Err(IllegalMove::OffBoard(pos));
+            }
+        }
+        let card = self.my.cards[if m.used_left_card { 0 } else { 1 }];
+        if self.spirit.test_bit(m.from) {
+            return self.validate_spirit_move(m, card);
+        }
+        if !self.my.pieces.test_bit(m.from) {
+            return Err(IllegalMove::NoPiece(m.from));
+        }
+        if m.from == m.to {
+            if m.spirit.is_some() {
+                return Err(IllegalMove::NoSpiritMove(card));
+            }
+            // real moves never end where they start
+            let can_move = self.gen_moves().iter().any(|other| other.from != other.to);
+            return if can_move {
+                Err(IllegalMove::PassWithMoves)
+            } else if m.from != self.my.king {
+                Err(IllegalMove::PassWithoutKing)
+            } else {
+                Ok(())
+            };
+        }
+        if self.my.pieces.test_bit(m.to) {
+            return Err(IllegalMove::OwnPiece(m.to));
+        }
+        if self.spirit.test_bit(m.to) {
+            return Err(IllegalMove::OntoSpirit(m.to));
+        }
+        if !shift_bitmap(card.get_move(self.colour), m.from as u32).test_bit(m.to) {
+            return Err(IllegalMove::NotOnCard {
+                card,
+                from: m.from,
+                to: m.to,
             });
-            moves.push(Move {
-                from: self.my.king,
-                to: self.my.king,
-                used_left_card: false,
+        }
+        // the spirit after the piece
+        let pattern = card.get_spirit_move(self.colour);
+        match m.spirit {
+            None if pattern == 0 || self.spirit == 0 => Ok(()),
+            Some(_) if self.spirit == 0 => Err(IllegalMove::NoSpirit),
+            Some(_) if pattern == 0 => Err(IllegalMove::NoSpiritMove(card)),
+            None if self.spirit_targets(pattern, m.from, m.to) == 0 => Ok(()),
+            None => Err(IllegalMove::SpiritMoveMissing(card)),
+            Some(to) => {
+                let from = self.spirit.trailing_zeros() as u8;
+                if !shift_bitmap(pattern, from as u32).test_bit(to) {
+                    Err(IllegalMove::NotOnCard { card, from, to })
+                } else if !self.spirit_targets(pattern, m.from, m.to).test_bit(to) {
+                    Err(IllegalMove::SpiritOntoKing(to))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    // the wind spirit moved with an ordinary card
+    fn validate_spirit_move(&self, m: &Move, card: Card) -> Result<(), IllegalMove> {
+        if card.is_dual() {
+            return Err(IllegalMove::PieceMoveMissing(card));
+        }
+        if m.spirit.is_some() {
+            return Err(IllegalMove::NoSpiritMove(card));
+        }
+        if !shift_bitmap(card.get_move(self.colour), m.from as u32).test_bit(m.to) {
+            return Err(IllegalMove::NotOnCard {
+                card,
+                from: m.from,
+                to: m.to,
             });
         }
+        if m.to == self.my.king || m.to == self.other.king {
+            return Err(IllegalMove::SpiritOntoKing(m.to));
+        }
+        Ok(())
+    }
 
-        moves
+    pub fn is_legal(&self, m: &Move) -> bool {
+        self.validate_move(m).is_ok()
     }
 
     pub fn count_moves(&self) -> usize {
+        if self.spirit != 0 {
+            return self.gen_wind_moves().len();
+        }
         let left = self.my.cards[0].get_move(self.colour);
         let right = self.my.cards[1].get_move(self.colour);
         let mut total = 0;
@@ -211,6 +535,8 @@ impl fmt::Display for Game {
                 } else {
                     board.push('♟');
                 }
+            } else if self.spirit.test_bit(i) {
+                board.push('◈');
             } else {
                 board.push('◻');
             }
@@ -230,13 +556,63 @@ impl fmt::Display for Game {
     }
 }
 
+// red's cards, blue's cards and the table card
+fn parse_cards(cards: &CardsObj) -> Result<([Card; 2], [Card; 2], Card), OnitamaError> {
+    let card = |text: &str| Card::from_text(text).map_err(OnitamaError::Parse);
+    Ok((
+        [card(&cards.red[0])?, card(&cards.red[1])?],
+        [card(&cards.blue[0])?, card(&cards.blue[1])?],
+        card(&cards.side)?,
+    ))
+}
+
 impl Game {
-    pub fn from_state_msg(state_msg: StateMsg) -> Game {
+    pub fn from_state_msg(state_msg: &StateMsg) -> Result<Game, OnitamaError> {
+        let colour = Colour::from(state_msg.current_turn.clone()).map_err(OnitamaError::Parse)?;
+        let (red_cards, blue_cards, table_card) = parse_cards(&state_msg.cards)?;
+        let in_progress = is_in_progress(&state_msg.game_state)?;
+        let board = &state_msg.board;
+        if board.len() != 25 || !board.chars().all(|c| ('0'..='4').contains(&c)) {
+            return Err(OnitamaError::Parse(format!("Invalid board: {}", board)));
+        }
+
+        Ok(Game::from_board(
+            board,
+            colour,
+            red_cards,
+            blue_cards,
+            table_card,
+            in_progress,
+        ))
+    }
+
+    // the position the match started from, None when the server doesn't send the starting cards
+    pub fn from_starting_cards(state_msg: &StateMsg) -> Result<Option<Game>, OnitamaError> {
+        if state_msg.starting_cards.side.is_empty() {
+            return Ok(None);
+        }
+        let (red, blue, side) = parse_cards(&state_msg.starting_cards)?;
+        // from_cards takes the cards from the back
+        Ok(Some(Game::from_cards(vec![
+            blue[1], blue[0], red[1], red[0], side,
+        ])))
+    }
+
+    // the board is in litama format: 25 digits, 0 empty, 1 blue, 2 blue king, 3 red, 4 red king
+    pub fn from_board(
+        board: &str,
+        colour: Colour,
+        red_cards: [Card; 2],
+        blue_cards: [Card; 2],
+        table_card: Card,
+        in_progress: bool,
+    ) -> Game {
         let mut red = 0u32;
         let mut blue = 0u32;
-        let mut red_king = 0;
-        let mut blue_king = 0;
-        for (i, character) in (0..25).zip(state_msg.board.chars()) {
+        // a king missing from the board was captured
+        let mut red_king = 25;
+        let mut blue_king = 25;
+        for (i, character) in (0..25).zip(board.chars()) {
             let row = i / 5;
             let col = 4 - i % 5;
             let pos = 5 * row + col;
@@ -260,18 +636,6 @@ impl Game {
             };
         }
 
-        let colour = Colour::from(state_msg.current_turn).unwrap();
-        let red_cards = [
-            Card::from_text(&state_msg.cards.red[0]).unwrap(),
-            Card::from_text(&state_msg.cards.red[1]).unwrap(),
-        ];
-        let blue_cards = [
-            Card::from_text(&state_msg.cards.blue[0]).unwrap(),
-            Card::from_text(&state_msg.cards.blue[1]).unwrap(),
-        ];
-        let table_card = Card::from_text(&state_msg.cards.side).unwrap();
-        let in_progress = is_in_progress(state_msg.game_state).unwrap();
-
         let red = Player {
             cards: red_cards,
             pieces: red,
@@ -286,14 +650,37 @@ impl Game {
             Colour::Red => (red, blue),
             Colour::Blue => (blue, red),
         };
+        // litama has no wind spirit
         Game {
             my,
             other,
             table_card,
             colour,
             in_progress,
+            spirit: 0,
         }
     }
+
+    // the inverse of from_board
+    pub fn to_board(&self) -> String {
+        let (red, blue) = self.get_red_blue();
+        (0..25)
+            .map(|i| {
+                let pos = 5 * (i / 5) + 4 - i % 5;
+                if pos == red.king as usize {
+                    '4'
+                } else if red.pieces.test_bit(pos) {
+                    '3'
+                } else if pos == blue.king as usize {
+                    '2'
+                } else if blue.pieces.test_bit(pos) {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -301,6 +688,287 @@ mod tests {
     use super::*;
     use test::Bencher;
 
+    #[test]
+    fn test_from_board() {
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        let from_board = Game::from_board(
+            "1121100000000000000033433",
+            Colour::Blue,
+            [Card::Elephant, Card::Horse],
+            [Card::Boar, Card::Ox],
+            Card::Crab,
+            true,
+        );
+        assert_eq!(game.hash(), from_board.hash());
+        assert_eq!(game.my.king, from_board.my.king);
+        assert_eq!(from_board.to_board(), "1121100000000000000033433");
+        // and after a few moves
+        let mut played = game;
+        for _ in 0..6 {
+            played = played.take_turn(&played.gen_moves()[0]);
+        }
+        let (red, blue) = played.get_red_blue();
+        let back = Game::from_board(
+            &played.to_board(),
+            played.colour,
+            red.cards,
+            blue.cards,
+            played.table_card,
+            played.in_progress,
+        );
+        assert_eq!(back.hash(), played.hash());
+    }
+
+    // every move validates exactly when gen_moves has it, in random positions
+    #[test]
+    fn test_validate_matches_gen_moves() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(15);
+        for _ in 0..100 {
+            let mut cards: Vec<Card> = Vec::new();
+            while cards.len() < 5 {
+                let card = Card::from_num(rng.gen_range(0, 16));
+                if !cards.contains(&card) {
+                    cards.push(card);
+                }
+            }
+            let mut game = Game::from_cards(cards);
+            while game.in_progress {
+                let moves = game.gen_moves();
+                for from in 0..25 {
+                    for to in 0..25 {
+                        for &used_left_card in [true, false].iter() {
+                            let m = Move {
+                                from,
+                                to,
+                                used_left_card,
+                                spirit: None,
+                            };
+                            assert_eq!(game.is_legal(&m), moves.contains(&m), "{:?}", m);
+                        }
+                    }
+                }
+                game = game.take_turn(&moves[rng.gen_range(0, moves.len())]);
+            }
+            let m = Move {
+                from: game.my.king,
+                to: game.my.king,
+                used_left_card: true,
+                spirit: None,
+            };
+            assert_eq!(game.validate_move(&m), Err(IllegalMove::GameOver));
+        }
+    }
+
+    #[test]
+    fn test_illegal_moves() {
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        // blue moves first with boar and ox, blue's king starts on c5
+        let m = |from, to, used_left_card| Move {
+            from,
+            to,
+            used_left_card,
+            spirit: None,
+        };
+        assert_eq!(
+            game.validate_move(&m(2, 25, true)),
+            Err(IllegalMove::OffBoard(25))
+        );
+        assert_eq!(
+            game.validate_move(&m(12, 7, true)),
+            Err(IllegalMove::NoPiece(12))
+        );
+        assert_eq!(
+            game.validate_move(&m(2, 3, true)),
+            Err(IllegalMove::OwnPiece(3))
+        );
+        assert_eq!(
+            game.validate_move(&m(2, 12, true)),
+            Err(IllegalMove::NotOnCard {
+                card: game.my.cards[0],
+                from: 2,
+                to: 12
+            })
+        );
+        assert_eq!(
+            game.validate_move(&m(2, 2, false)),
+            Err(IllegalMove::PassWithMoves)
+        );
+    }
+
+    #[test]
+    fn test_validate_pass() {
+        // find a king and a pawn that neither card can move
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        let game = (0..16 * 16 * 25 * 25)
+            .filter_map(|i| {
+                let (king, pawn) = ((i / 256 / 25) as u8, (i / 256 % 25) as u8);
+                if king == pawn {
+                    return None;
+                }
+                let mut game = game.clone();
+                game.my.cards = [Card::from_num(i % 16), Card::from_num(i / 16 % 16)];
+                game.my.king = king;
+                game.my.pieces = 1 << king | 1 << pawn;
+                game.other.pieces &= !game.my.pieces;
+                Some(game)
+            })
+            .find(|game| game.gen_moves().iter().all(|m| m.from == m.to))
+            .unwrap();
+        for m in game.gen_moves().iter() {
+            assert_eq!(game.validate_move(m), Ok(()));
+        }
+        let pawn = (game.my.pieces & !(1 << game.my.king)).trailing_zeros() as u8;
+        let m = Move {
+            from: pawn,
+            to: pawn,
+            used_left_card: true,
+            spirit: None,
+        };
+        assert_eq!(game.validate_move(&m), Err(IllegalMove::PassWithoutKing));
+    }
+
+    fn wind_game() -> Game {
+        // blue moves first with boar and ox, red has the bat which also moves the spirit
+        Game::from_cards_with(
+            vec![Card::Ox, Card::Boar, Card::Horse, Card::Bat, Card::Crab],
+            Ruleset::WayOfTheWind,
+        )
+    }
+
+    #[test]
+    fn test_wind_spirit() {
+        let mut game = wind_game();
+        assert_eq!(game.ruleset(), Ruleset::WayOfTheWind);
+        // boar and ox each move the spirit to 3 squares
+        let spirit_moves = game.gen_moves().iter().filter(|m| m.from == 12).count();
+        assert_eq!(spirit_moves, 6);
+        assert_eq!(game.count_moves(), game.gen_moves().len());
+
+        let m = |from, to, used_left_card, spirit| Move {
+            from,
+            to,
+            used_left_card,
+            spirit,
+        };
+        // for blue, ox moves forward, left and back
+        game.spirit = 1 << 7;
+        assert_eq!(
+            game.validate_move(&m(7, 2, false, None)),
+            Err(IllegalMove::SpiritOntoKing(2))
+        );
+        assert_eq!(
+            game.validate_move(&m(2, 7, false, None)),
+            Err(IllegalMove::OntoSpirit(7))
+        );
+        // the spirit swaps places with a pawn
+        game.spirit = 1 << 6;
+        let swap = m(6, 1, false, None);
+        assert_eq!(game.validate_move(&swap), Ok(()));
+        let next = game.take_turn(&swap);
+        assert_eq!(next.spirit, 1 << 1);
+        assert!(next.other.pieces.test_bit(6) && !next.other.pieces.test_bit(1));
+        assert_eq!(next.other.pieces.count_ones(), 5);
+
+        // the bat moves a piece and then the spirit
+        let game = wind_game();
+        let game = game.take_turn(&m(0, 5, false, None));
+        assert_eq!(game.colour, Colour::Red);
+        for m in game.gen_moves().iter() {
+            assert_eq!(m.spirit.is_some(), m.used_left_card, "{:?}", m);
+        }
+        assert_eq!(
+            game.validate_move(&m(21, 15, true, None)),
+            Err(IllegalMove::SpiritMoveMissing(Card::Bat))
+        );
+        assert_eq!(game.validate_move(&m(21, 15, true, Some(7))), Ok(()));
+        assert_eq!(
+            game.validate_move(&m(21, 16, false, Some(7))),
+            Err(IllegalMove::NoSpiritMove(Card::Horse))
+        );
+        assert_eq!(
+            game.validate_move(&m(12, 7, true, None)),
+            Err(IllegalMove::PieceMoveMissing(Card::Bat))
+        );
+        let next = game.take_turn(&m(21, 15, true, Some(7)));
+        assert_eq!(next.spirit, 1 << 7);
+        assert!(next.other.pieces.test_bit(15));
+        // the base game has no spirit to move
+        let base = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Bat,
+            Card::Crab,
+        ])
+        .take_turn(&m(0, 5, false, None));
+        assert_eq!(base.validate_move(&m(21, 15, true, None)), Ok(()));
+        assert_eq!(
+            base.validate_move(&m(21, 15, true, Some(7))),
+            Err(IllegalMove::NoSpirit)
+        );
+    }
+
+    // the same as test_validate_matches_gen_moves with the wind spirit
+    #[test]
+    fn test_validate_matches_gen_wind_moves() {
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::{Rng, SeedableRng};
+
+        // a dual card loaded by another test would change the deck
+        let _cards = crate::cards::fresh_cards();
+        let mut rng = StdRng::seed_from_u64(22);
+        let deck = Ruleset::WayOfTheWind.deck();
+        for _ in 0..5 {
+            let cards = deck.choose_multiple(&mut rng, 5).copied().collect();
+            let mut game = Game::from_cards_with(cards, Ruleset::WayOfTheWind);
+            while game.in_progress {
+                let moves = game.gen_moves();
+                let spirits = (0..25).map(Some).chain(std::iter::once(None));
+                for spirit in spirits {
+                    for from in 0..25 {
+                        for to in 0..25 {
+                            for &used_left_card in [true, false].iter() {
+                                let m = Move {
+                                    from,
+                                    to,
+                                    used_left_card,
+                                    spirit,
+                                };
+                                assert_eq!(game.is_legal(&m), moves.contains(&m), "{:?}", m);
+                            }
+                        }
+                    }
+                }
+                game = game.take_turn(&moves[rng.gen_range(0, moves.len())]);
+                // nothing is ever taken by the spirit
+                assert_eq!(game.spirit.count_ones(), 1);
+                assert_eq!(game.spirit & (game.my.pieces | game.other.pieces), 0);
+            }
+        }
+    }
+
     #[bench]
     fn bench_gen_moves(b: &mut Bencher) {
         let cards = vec![
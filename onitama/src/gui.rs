@@ -1,12 +1,16 @@
+use crate::analysis::{pv_texts, value_label};
+use crate::bot::MoveAnalysis;
+use crate::cards::Card;
 use crate::colour::Colour;
-use crate::game::Move;
-use crate::Transmission;
+use crate::game::{Game, IllegalMove, Move};
+use crate::messages::translate_pos;
+use crate::{Action, Transmission};
 
 use std::result::Result;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use sdl2::event::Event;
 use sdl2::image::LoadTexture;
@@ -20,8 +24,11 @@ use bitwise::TestBit;
 
 // sizes
 const FONT_SIZE: u16 = 28;
+const LIST_FONT_SIZE: u16 = 20;
 const BLOCK: u32 = 64;
-const WIN_WIDTH: u32 = 19 * BLOCK;
+const PLAY_WIDTH: u32 = 19 * BLOCK;
+const LIST_WIDTH: u32 = 4 * BLOCK;
+const WIN_WIDTH: u32 = PLAY_WIDTH + LIST_WIDTH;
 const WIN_HEIGHT: u32 = 12 * BLOCK;
 const BOARD_PAD: u32 = BLOCK;
 const BOARD_SQUARE: u32 = 2 * BLOCK;
@@ -29,6 +36,16 @@ const BOARD_SIZE: u32 = 5 * BOARD_SQUARE;
 const CARD_PAD: u32 = BLOCK;
 const CARD_SQUARE: u32 = BLOCK / 2;
 const CARD_SIZE: u32 = 5 * CARD_SQUARE;
+const LIST_PAD: u32 = BLOCK / 2;
+const LIST_LINE: u32 = 24;
+const LIST_LINES: usize = ((WIN_HEIGHT - 2 * LIST_PAD) / LIST_LINE) as usize;
+// the principal variation is written between the cards
+const PV_LINES: usize = 7;
+// the marks on the squares a piece can move to, in the corner of the card on screen
+const MARK_SIZE: u32 = BOARD_SQUARE / 4;
+const OUTLINE: u32 = 4;
+// how long a refused click is shown
+const REFUSAL_TIME: Duration = Duration::from_secs(2);
 
 // colour
 const BG_COLOUR: Color = Color::RGB(20, 20, 20);
@@ -37,6 +54,9 @@ const FONT_COLOUR: Color = Color::RGB(180, 180, 180);
 const W_SQUARE_COLOUR: Color = Color::RGB(239, 218, 182);
 const B_SQUARE_COLOUR: Color = Color::RGB(179, 137, 101);
 const SELECT_COLOUR: Color = Color::RGB(90, 150, 60);
+const SPIRIT_COLOUR: Color = Color::RGB(150, 200, 230);
+const LAST_MOVE_COLOUR: Color = Color::RGB(230, 200, 60);
+const REFUSED_COLOUR: Color = Color::RGB(200, 50, 50);
 
 macro_rules! rect {
     ($x:expr, $y:expr, $width:expr, $height:expr) => {
@@ -50,8 +70,146 @@ macro_rules! square {
     };
 }
 
+// a move being put together from clicks
+#[derive(Default)]
+struct Selection {
+    // the piece or the wind spirit
+    from: Option<u8>,
+    // where it goes, set before the move is played when both cards go there
+    // or the card also moves the wind spirit
+    to: Option<u8>,
+    // the card, true for the left one, known before the wind spirit is moved
+    card: Option<bool>,
+}
+
+enum Click {
+    Square(u8),
+    // one of the cards of the player to move, true for the left one
+    Card(bool),
+    OtherCard,
+}
+
+impl Selection {
+    // where the selected piece can go with the left and the right card
+    fn destinations(&self, g: &Game) -> [u32; 2] {
+        let mut targets = [0; 2];
+        if let (Some(from), None) = (self.from, self.to) {
+            for m in g.gen_moves().iter().filter(|m| m.from == from) {
+                targets[!m.used_left_card as usize] |= 1 << m.to;
+            }
+        }
+        targets
+    }
+
+    // where the wind spirit can go after the piece
+    fn spirit_destinations(&self, g: &Game) -> u32 {
+        match (self.from, self.to, self.card) {
+            (Some(from), Some(to), Some(left)) => g
+                .gen_moves()
+                .iter()
+                .filter(|m| m.from == from && m.to == to && m.used_left_card == left)
+                .filter_map(|m| m.spirit)
+                .fold(0, |targets, pos| targets | 1 << pos),
+            _ => 0,
+        }
+    }
+
+    // the move once it is complete, or why the click can't be played
+    fn click(&mut self, g: &Game, click: Click) -> Result<Option<Move>, String> {
+        let moves = g.gen_moves();
+        let pos = |pos: u8| translate_pos(pos as usize);
+        match click {
+            Click::OtherCard => Err("You can only play your own cards".to_string()),
+            Click::Card(left) => {
+                let (from, to) = match (self.from, self.to, self.card) {
+                    (Some(from), Some(to), None) => (from, to),
+                    _ => return Err("Choose a card when both cards make the move".to_string()),
+                };
+                let with_card: Vec<Move> = moves
+                    .into_iter()
+                    .filter(|m| m.from == from && m.to == to && m.used_left_card == left)
+                    .collect();
+                if let Some(&m) = with_card.iter().find(|m| m.spirit.is_none()) {
+                    return Ok(Some(m));
+                }
+                if with_card.is_empty() {
+                    let card = g.my.cards[!left as usize];
+                    return Err(IllegalMove::NotOnCard { card, from, to }.to_string());
+                }
+                self.card = Some(left);
+                Ok(None)
+            }
+            Click::Square(square) => {
+                if let (Some(from), Some(to), Some(left)) = (self.from, self.to, self.card) {
+                    let m = moves.iter().copied().find(|m| {
+                        m.from == from
+                            && m.to == to
+                            && m.used_left_card == left
+                            && m.spirit == Some(square)
+                    });
+                    if m.is_some() {
+                        return Ok(m);
+                    }
+                    // any other square cancels the move, a piece is selected instead
+                    *self = Selection::default();
+                    if !g.my.pieces.test_bit(square) && !g.spirit.test_bit(square) {
+                        return Ok(None);
+                    }
+                }
+                // passes go from the king to its own square
+                if let Some(from) = self.from {
+                    let candidates: Vec<Move> = moves
+                        .iter()
+                        .filter(|m| m.from == from && m.to == square)
+                        .copied()
+                        .collect();
+                    if let Some(first) = candidates.first() {
+                        self.to = Some(square);
+                        if candidates
+                            .iter()
+                            .any(|m| m.used_left_card != first.used_left_card)
+                        {
+                            // wait for a click on one of the cards
+                            self.card = None;
+                            return Ok(None);
+                        }
+                        self.card = Some(first.used_left_card);
+                        return Ok(candidates.iter().find(|m| m.spirit.is_none()).copied());
+                    }
+                    if from == square {
+                        *self = Selection::default();
+                        return Ok(None);
+                    }
+                }
+                if g.my.pieces.test_bit(square) || g.spirit.test_bit(square) {
+                    if !moves.iter().any(|m| m.from == square) {
+                        return Err(if g.spirit.test_bit(square) {
+                            "The wind spirit can't move".to_string()
+                        } else {
+                            format!("The piece on {} can't move", pos(square))
+                        });
+                    }
+                    *self = Selection {
+                        from: Some(square),
+                        ..Selection::default()
+                    };
+                    return Ok(None);
+                }
+                match self.from {
+                    Some(from) => Err(format!(
+                        "Neither card moves {} to {}",
+                        pos(from),
+                        pos(square)
+                    )),
+                    None => Err(IllegalMove::NoPiece(square).to_string()),
+                }
+            }
+        }
+    }
+}
+
 pub fn run(
-    tx: Sender<Move>,
+    tx: Sender<Action>,
     rx: Receiver<Transmission>,
     should_end: &Arc<AtomicBool>,
 ) -> Result<(), String> {
@@ -83,15 +241,29 @@ pub fn run(
     highlight.set_color_mod(SELECT_COLOUR.r, SELECT_COLOUR.g, SELECT_COLOUR.b);
     // load font
     let font = ttf_context.load_font("./fonts/Typographica-Blp5.ttf", FONT_SIZE)?;
+    let list_font = ttf_context.load_font("./fonts/Typographica-Blp5.ttf", LIST_FONT_SIZE)?;
 
     let mut event_pump = sdl_context.event_pump()?;
 
     let mut game = None;
     let mut want_move = false;
+    let mut want_step = false;
+    // spectators and replays are never asked for a move
+    let mut seated = false;
+    let mut selection = Selection::default();
+    let mut last_move: Option<Move> = None;
+    // the message and square of the last refused click
+    let mut refusal: Option<(Texture, Option<u8>, Instant)> = None;
+    let mut move_list: Option<Vec<String>> = None;
+    let mut ply = 0;
     let mut highlighted_squares = 0u32;
     let mut flipped = false;
     let mut red_username = None;
     let mut blue_username = None;
+    let mut analysis: Option<Vec<MoveAnalysis>> = None;
+    let mut winner = None;
+    // the last message about the match, shown until the next one
+    let mut status = None;
     'main_loop: loop {
         // early exit
         if should_end.load(Ordering::Relaxed) {
@@ -99,6 +271,7 @@ pub fn run(
         }
 
         let mut clicked_square = None;
+        let mut clicked_card = None;
 
         // event loop
         for event in event_pump.poll_iter() {
@@ -117,6 +290,17 @@ pub fn run(
                     ..
                 } => {
                     clicked_square = get_pos_from_click(x as u32, y as u32);
+                    clicked_card = get_card_from_click(x as u32, y as u32);
+                    // jump to a ply in the move list
+                    if let Some(ref moves) = move_list {
+                        let i = get_ply_from_click(x as u32, y as u32, ply, moves.len());
+                        if let (Some(i), true) = (i, want_move || want_step) {
+                            tx.send(Action::Goto(i)).map_err(|e| e.to_string())?;
+                            want_move = false;
+                            want_step = false;
+                            selection = Selection::default();
+                        }
+                    }
                 }
                 Event::MouseButtonDown {
                     mouse_btn: MouseButton::Right,
@@ -135,6 +319,35 @@ pub fn run(
                     flipped = !flipped;
                     highlighted_squares = highlighted_squares.reverse_bits() >> (32 - 25);
                 }
+                // undo and redo, or step through a replay
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if want_move || want_step => {
+                    let action = match key {
+                        Keycode::Left | Keycode::Backspace | Keycode::U => Action::Back,
+                        Keycode::Right | Keycode::R => Action::Forward,
+                        Keycode::A => Action::Analyse,
+                        _ => continue,
+                    };
+                    tx.send(action).map_err(|e| e.to_string())?;
+                    want_move = false;
+                    want_step = false;
+                    selection = Selection::default();
+                }
+                // the analysis is only searched when the game waits for the player
+                Event::KeyDown {
+                    keycode: Some(Keycode::A),
+                    ..
+                } if game.is_some() => {
+                    let surface = font
+                        .render("Analysis is only available on your move")
+                        .blended(FONT_COLOUR)
+                        .map_err(|e| e.to_string())?;
+                    let texture = texture_creator
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())?;
+                    refusal = Some((texture, None, Instant::now()));
+                }
                 _ => {}
             }
         }
@@ -142,8 +355,58 @@ pub fn run(
         // see if game can be updated
         if let Ok(trans) = rx.try_recv() {
             match trans {
-                Transmission::Display(g) => game = Some(g),
-                Transmission::RequestMove => want_move = true,
+                Transmission::Display(g) => {
+                    // the analysis stays up until the position changes
+                    if game.as_ref().map(Game::hash) != Some(g.hash()) {
+                        analysis = None;
+                        last_move = None;
+                        // a finished game can be undone, its result is sent again at the end
+                        winner = None;
+                        selection = Selection::default();
+                    }
+                    game = Some(g);
+                }
+                Transmission::LastMove(m) => last_move = m,
+                Transmission::Analysis(moves) => analysis = Some(moves),
+                Transmission::Winner(text) => {
+                    let surface = font
+                        .render(&text)
+                        .blended(FONT_COLOUR)
+                        .map_err(|e| e.to_string())?;
+                    let texture = texture_creator
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())?;
+                    winner = Some(texture);
+                }
+                Transmission::Refused(reason) => {
+                    let surface = font
+                        .render(&reason)
+                        .blended(FONT_COLOUR)
+                        .map_err(|e| e.to_string())?;
+                    let texture = texture_creator
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())?;
+                    refusal = Some((texture, None, Instant::now()));
+                }
+                Transmission::Status(text) => {
+                    let surface = font
+                        .render(&text)
+                        .blended(FONT_COLOUR)
+                        .map_err(|e| e.to_string())?;
+                    let texture = texture_creator
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())?;
+                    status = Some(texture);
+                }
+                Transmission::RequestMove => {
+                    want_move = true;
+                    seated = true;
+                }
+                Transmission::RequestStep => want_step = true,
+                Transmission::MoveList(moves, current) => {
+                    move_list = Some(moves);
+                    ply = current;
+                }
                 Transmission::Usernames(red, blue) => {
                     // create username textures
                     let surface = font
@@ -166,6 +429,54 @@ pub fn run(
             }
         }
 
+        // play a move by clicking on a piece or the wind spirit and then on where it should go,
+        // on a card when both cards go there and last on where the wind spirit goes
+        // when the card also moves it. Clicks that can't be played are shown and refused
+        if let Some(ref actual_game) = game {
+            let click = if let Some(pos) = clicked_square {
+                Some(Click::Square(if flipped { 24 - pos } else { pos } as u8))
+            } else if let Some((bottom, left)) = clicked_card {
+                // red's cards are at the bottom unless the board is flipped
+                let mine = bottom == ((actual_game.colour == Colour::Red) != flipped);
+                Some(if mine {
+                    Click::Card(left)
+                } else {
+                    Click::OtherCard
+                })
+            } else {
+                None
+            };
+            let result = match click {
+                Some(click) if want_move => selection.click(actual_game, click),
+                Some(_) if seated && actual_game.in_progress => {
+                    Err("It is not your turn".to_string())
+                }
+                _ => Ok(None),
+            };
+            match result {
+                Ok(Some(m)) => {
+                    tx.send(Action::Move(m)).map_err(|e| e.to_string())?;
+                    want_move = false;
+                    selection = Selection::default();
+                }
+                Ok(None) => {}
+                Err(message) => {
+                    let surface = font
+                        .render(&message)
+                        .blended(FONT_COLOUR)
+                        .map_err(|e| e.to_string())?;
+                    let texture = texture_creator
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())?;
+                    let square = clicked_square.map(|pos| pos as u8);
+                    refusal = Some((texture, square, Instant::now()));
+                }
+            }
+        }
+        if matches!(refusal, Some((_, _, at)) if at.elapsed() > REFUSAL_TIME) {
+            refusal = None;
+        }
+
         // clear everything
         canvas.set_draw_color(BG_COLOUR);
         canvas.clear();
@@ -174,14 +485,17 @@ pub fn run(
         match game {
             Some(ref actual_game) => {
                 let (red, blue) = actual_game.get_red_blue();
-                for mut pos in 0..25u8 {
+                let destinations = selection.destinations(actual_game);
+                let spirit_destinations = selection.spirit_destinations(actual_game);
+                for pos in 0..25u8 {
                     let row = pos as u32 / 5;
                     let col = pos as u32 % 5;
                     let x = BOARD_PAD + BOARD_SQUARE * col;
                     let y = BOARD_PAD + BOARD_SQUARE * row;
                     let square = square!(x, y, BOARD_SQUARE);
+                    let p = if flipped { 24 - pos } else { pos };
                     canvas.set_draw_color(
-                        if clicked_square.is_some() && clicked_square.unwrap() == pos as u32 {
+                        if selection.from == Some(p) || selection.to == Some(p) {
                             SELECT_COLOUR
                         } else if pos % 2 == 0 {
                             B_SQUARE_COLOUR
@@ -191,7 +505,6 @@ pub fn run(
                     );
                     canvas.fill_rect(square)?;
                     // add image (such as pieces or temple)
-                    let p = if flipped { 24 - pos } else { pos };
                     if red.pieces.test_bit(p) {
                         if red.king == p {
                             canvas.copy(&red_king, None, Some(square))?;
@@ -204,6 +517,14 @@ pub fn run(
                         } else {
                             canvas.copy(&blue_pawn, None, Some(square))?;
                         }
+                    } else if actual_game.spirit.test_bit(p) {
+                        let inset = BOARD_SQUARE / 4;
+                        canvas.set_draw_color(SPIRIT_COLOUR);
+                        canvas.fill_rect(square!(
+                            x + inset,
+                            y + inset,
+                            BOARD_SQUARE - 2 * inset
+                        ))?;
                     } else if p == 2 || p == 22 {
                         canvas.copy(&temple, None, Some(square))?;
                     }
@@ -211,6 +532,53 @@ pub fn run(
                     if highlighted_squares.test_bit(pos) {
                         canvas.copy(&highlight, None, Some(square))?;
                     }
+                    // mark where the selected piece can go, in the corner of each card that goes there
+                    canvas.set_draw_color(SELECT_COLOUR);
+                    for (card, targets) in destinations.iter().enumerate() {
+                        if targets.test_bit(p) {
+                            let mark_x = x + card as u32 * (BOARD_SQUARE - MARK_SIZE);
+                            let mark_y = y + BOARD_SQUARE - MARK_SIZE;
+                            canvas.fill_rect(square!(mark_x, mark_y, MARK_SIZE))?;
+                            canvas.set_draw_color(FONT_COLOUR);
+                            canvas.draw_rect(square!(mark_x, mark_y, MARK_SIZE))?;
+                            canvas.set_draw_color(SELECT_COLOUR);
+                        }
+                    }
+                    if spirit_destinations.test_bit(p) {
+                        let inset = BOARD_SQUARE / 4;
+                        canvas.set_draw_color(SPIRIT_COLOUR);
+                        draw_outline(&mut canvas, square!(x + inset, y + inset, 2 * inset))?;
+                    }
+                    // mark the squares of the last move
+                    if matches!(last_move, Some(m) if m.from == p || m.to == p || m.spirit == Some(p))
+                    {
+                        canvas.set_draw_color(LAST_MOVE_COLOUR);
+                        draw_outline(&mut canvas, square)?;
+                    }
+                    if matches!(refusal, Some((_, Some(refused), _)) if refused == pos) {
+                        canvas.set_draw_color(REFUSED_COLOUR);
+                        draw_outline(&mut canvas, square)?;
+                    }
+                    // label the square with the best move to it, from the selected piece if any
+                    let best = analysis.as_ref().and_then(|moves| {
+                        moves.iter().find(|a| {
+                            a.m.to == p && selection.from.is_none_or(|from| a.m.from == from)
+                        })
+                    });
+                    if let Some(a) = best {
+                        let surface = list_font
+                            .render(&value_label(a.value))
+                            .blended(FONT_COLOUR)
+                            .map_err(|e| e.to_string())?;
+                        let texture = texture_creator
+                            .create_texture_from_surface(&surface)
+                            .map_err(|e| e.to_string())?;
+                        let TextureQuery { width, height, .. } = texture.query();
+                        let text_rect = rect!(x + 4, y + 4, width, height);
+                        canvas.set_draw_color(CARD_BG_COLOUR);
+                        canvas.fill_rect(rect!(x, y, width + 8, height + 8))?;
+                        canvas.copy(&texture, None, Some(text_rect))?;
+                    }
                 }
             }
             None => {
@@ -245,6 +613,12 @@ pub fn run(
         if let Some(ref actual_game) = game {
             let (red, blue) = actual_game.get_red_blue();
             let (bottom, top) = if flipped { (blue, red) } else { (red, blue) };
+            // the cards to choose from, or the card chosen to move the wind spirit
+            let choosable = |card: &Card| match (selection.to, selection.card) {
+                (Some(_), None) => actual_game.my.cards.contains(card),
+                (Some(_), Some(left)) => actual_game.my.cards[!left as usize] == *card,
+                _ => false,
+            };
             for (card, colour, start_x, start_y, text_flipped) in vec![
                 (
                     &bottom.cards[0],
@@ -256,7 +630,7 @@ pub fn run(
                 (
                     &bottom.cards[1],
                     if flipped { Colour::Blue } else { Colour::Red },
-                    WIN_WIDTH - CARD_PAD - CARD_SIZE,
+                    PLAY_WIDTH - CARD_PAD - CARD_SIZE,
                     WIN_HEIGHT - CARD_PAD - CARD_SIZE,
                     false,
                 ),
@@ -270,14 +644,14 @@ pub fn run(
                 (
                     &top.cards[1],
                     if flipped { Colour::Red } else { Colour::Blue },
-                    WIN_WIDTH - CARD_PAD - CARD_SIZE,
+                    PLAY_WIDTH - CARD_PAD - CARD_SIZE,
                     CARD_PAD,
                     true,
                 ),
                 (
                     &actual_game.table_card,
                     actual_game.colour,
-                    WIN_WIDTH - CARD_PAD - CARD_SIZE,
+                    PLAY_WIDTH - CARD_PAD - CARD_SIZE,
                     (WIN_HEIGHT - CARD_SIZE) / 2,
                     matches!(actual_game.colour, Colour::Blue) ^ flipped,
                 ),
@@ -285,6 +659,7 @@ pub fn run(
             .into_iter()
             {
                 let board = card.get_move(colour);
+                let spirit = card.get_spirit_move(colour);
                 for pos in 0..25 {
                     let row = pos / 5;
                     let col = pos % 5;
@@ -298,6 +673,11 @@ pub fn run(
                         CARD_BG_COLOUR
                     });
                     canvas.fill_rect(square)?;
+                    if spirit.test_bit(p) {
+                        let inset = CARD_SQUARE / 4;
+                        canvas.set_draw_color(SPIRIT_COLOUR);
+                        canvas.fill_rect(square!(x + inset, y + inset, CARD_SQUARE - 2 * inset))?;
+                    }
                     canvas.set_draw_color(FONT_COLOUR);
                     canvas.draw_rect(square)?;
                     if pos == 12 {
@@ -308,6 +688,17 @@ pub fn run(
                         canvas.copy(pawn, None, Some(square))?;
                     }
                 }
+                if choosable(card) && card != &actual_game.table_card {
+                    canvas.set_draw_color(SELECT_COLOUR);
+                    draw_outline(
+                        &mut canvas,
+                        square!(
+                            start_x - OUTLINE,
+                            start_y - OUTLINE,
+                            CARD_SIZE + 2 * OUTLINE
+                        ),
+                    )?;
+                }
 
                 let surface = font
                     .render(card.get_name())
@@ -349,6 +740,69 @@ pub fn run(
             canvas.copy(blue, None, Some(text_rect))?;
         }
 
+        // write who won above the analysis, why a click was refused above that
+        // and the status of the match on top
+        let mut y = (WIN_HEIGHT - CARD_SIZE) / 2;
+        for text in winner
+            .iter()
+            .chain(refusal.as_ref().map(|(text, _, _)| text))
+            .chain(status.iter())
+        {
+            let TextureQuery { width, height, .. } = text.query();
+            let x = BOARD_PAD + BOARD_SIZE + CARD_PAD;
+            y -= height;
+            canvas.copy(text, None, Some(rect!(x, y, width, height)))?;
+        }
+
+        // write the best line of the analysis
+        if let (Some(moves), Some(actual_game)) = (&analysis, &game) {
+            if let Some(best) = moves.first() {
+                let lines = std::iter::once(format!("best: {}", best.value))
+                    .chain(pv_texts(actual_game, &best.pv))
+                    .take(PV_LINES);
+                for (line, text) in lines.enumerate() {
+                    let surface = list_font
+                        .render(&text)
+                        .blended(FONT_COLOUR)
+                        .map_err(|e| e.to_string())?;
+                    let texture = texture_creator
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())?;
+                    let TextureQuery { width, height, .. } = texture.query();
+                    let x = BOARD_PAD + BOARD_SIZE + CARD_PAD;
+                    let y = (WIN_HEIGHT - CARD_SIZE) / 2 + LIST_LINE * line as u32;
+                    canvas.copy(&texture, None, Some(rect!(x, y, width, height)))?;
+                }
+            }
+        }
+
+        // write the move list, the first line is the starting position
+        if let Some(ref moves) = move_list {
+            let first = first_listed(ply, moves.len());
+            for (line, i) in (first..=moves.len()).take(LIST_LINES).enumerate() {
+                let y = LIST_PAD + LIST_LINE * line as u32;
+                if i == ply {
+                    canvas.set_draw_color(SELECT_COLOUR);
+                    canvas.fill_rect(rect!(PLAY_WIDTH, y, LIST_WIDTH - LIST_PAD, LIST_LINE))?;
+                }
+                let text = if i == 0 {
+                    "start".to_string()
+                } else {
+                    format!("{}. {}", i, moves[i - 1])
+                };
+                let surface = list_font
+                    .render(&text)
+                    .blended(FONT_COLOUR)
+                    .map_err(|e| e.to_string())?;
+                let texture = texture_creator
+                    .create_texture_from_surface(&surface)
+                    .map_err(|e| e.to_string())?;
+                let TextureQuery { width, height, .. } = texture.query();
+                let text_rect = rect!(PLAY_WIDTH + LIST_PAD / 4, y, width, height);
+                canvas.copy(&texture, None, Some(text_rect))?;
+            }
+        }
+
         canvas.present();
         std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
     }
@@ -357,9 +811,8 @@ pub fn run(
 }
 
 fn get_pos_from_click(x: u32, y: u32) -> Option<u32> {
-    if (BOARD_PAD <= x && x < BOARD_PAD + BOARD_SIZE)
-        && (BOARD_PAD <= y && y < BOARD_PAD + BOARD_SIZE)
-    {
+    let board = BOARD_PAD..BOARD_PAD + BOARD_SIZE;
+    if board.contains(&x) && board.contains(&y) {
         let col = (x - BOARD_PAD) / BOARD_SQUARE;
         let row = (y - BOARD_PAD) / BOARD_SQUARE;
         Some(row * 5 + col)
@@ -367,3 +820,55 @@ fn get_pos_from_click(x: u32, y: u32) -> Option<u32> {
         None
     }
 }
+
+// which hand card is under a click, as whether it is at the bottom and whether it is on the left
+fn get_card_from_click(x: u32, y: u32) -> Option<(bool, bool)> {
+    let inside = |start: u32, v: u32| start <= v && v < start + CARD_SIZE;
+    let left = if inside(BOARD_PAD + BOARD_SIZE + CARD_PAD, x) {
+        true
+    } else if inside(PLAY_WIDTH - CARD_PAD - CARD_SIZE, x) {
+        false
+    } else {
+        return None;
+    };
+    if inside(WIN_HEIGHT - CARD_PAD - CARD_SIZE, y) {
+        Some((true, left))
+    } else if inside(CARD_PAD, y) {
+        Some((false, left))
+    } else {
+        None
+    }
+}
+
+// a frame OUTLINE thick inside the rect
+fn draw_outline(canvas: &mut WindowCanvas, rect: Rect) -> Result<(), String> {
+    for i in 0..OUTLINE {
+        canvas.draw_rect(rect!(
+            rect.x() as u32 + i,
+            rect.y() as u32 + i,
+            rect.width() - 2 * i,
+            rect.height() - 2 * i
+        ))?;
+    }
+    Ok(())
+}
+
+// the move list scrolls to keep the current ply in view
+fn first_listed(ply: usize, len: usize) -> usize {
+    (len + 1)
+        .saturating_sub(LIST_LINES)
+        .min(ply.saturating_sub(LIST_LINES / 2))
+}
+
+fn get_ply_from_click(x: u32, y: u32, ply: usize, len: usize) -> Option<usize> {
+    if (PLAY_WIDTH..WIN_WIDTH).contains(&x) && (LIST_PAD..WIN_HEIGHT - LIST_PAD).contains(&y) {
+        let i = first_listed(ply, len) + ((y - LIST_PAD) / LIST_LINE) as usize;
+        if i <= len {
+            Some(i)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
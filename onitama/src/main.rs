@@ -2,6 +2,7 @@
 extern crate arrayvec;
 extern crate bitwise;
 extern crate rand;
+#[cfg(feature = "gui")]
 extern crate sdl2;
 extern crate test;
 extern crate typenum;
@@ -10,17 +11,36 @@ extern crate websocket;
 extern crate serde_derive;
 #[macro_use]
 mod macros;
+mod analysis;
+mod arena;
+mod book;
 mod bot;
 mod cards;
 mod cli;
 mod colour;
+mod config;
 mod connection;
+mod engine;
+mod error;
+mod eval;
+mod external;
 mod game;
+#[cfg(feature = "gui")]
 mod gui;
 mod messages;
+mod nnue;
+mod notation;
 mod perft;
+mod record;
+mod server;
+mod symmetry;
+mod tablebase;
+mod terminal;
+mod tournament;
+mod training;
+mod transposition;
+mod zobrist;
 
-const SERVER: &str = "wss://litama.herokuapp.com";
 const HELP: &str = "Onitama Interface
 
 Commands:
@@ -30,8 +50,58 @@ Commands:
 - online create [username]          :   create an online game
 - online join [match id] [username] :   join an online game
 - online spectate [match id]        :   spectate an online game
+- engine                            :   talk to the engine over stdin/stdout
+- replay [file]                     :   step through a saved game
+- arena [config] [config]           :   play two engine configs against each other
+- tournament [configs]              :   rank several engine configs
+- export [file]                     :   write self-play positions for training
+- tablebase [file] [cards]          :   solve the endgames of a set of cards
+- book [file]                       :   search the openings for an opening book
+- analyse [position]                :   score every move of a position
+- server                            :   host online games on this computer
+- perft [position] [depth]          :   count the positions some moves ahead
 
 Add the `-h` flag at the end if you want to play instead of the bot
+Add `-t [seconds]` at the end to set how long the bot thinks per move (default 5)
+Add `-j [threads]` at the end to let the bot search with multiple threads
+Add `-c [config]` at the end to play and analyse with an engine config
+Add `--no-gui` at the end to play in the terminal instead of a window
+Add `--save [directory]` at the end to save every finished game there
+Add `--book [file]` at the end to let the bot play from an opening book
+Add `--server [url]` at the end to play online on another server (default wss://litama.herokuapp.com)
+Add `--rules wind` at the end of a local game to play with the wind spirit of the Way of the Wind
+Add `--cards [file]` to any command to use the cards in a card file, see cards.rs for the format.
+The cards of the Sensei's Path and Way of the Wind expansions are built in
+
+In the arena, `-n [games]` sets the number of games (default 100), `-j [threads]` how many
+are played at once and `--sprt [elo0] [elo1]` stops early once one of the bounds is accepted.
+A tournament plays every config against every other, or the first against the rest with
+`--gauntlet`, on `-n [openings]` random deals (default 10) with `-j [threads]` and
+writes the games to `-o [file]`. `--seed [number]` deals the same openings again
+A config can be `default` for the built in engine, see config.rs for the file format,
+and can run another program that speaks the engine protocol
+
+Export takes `-n [games]` (default 1000), `-j [threads]` and `-c [config]` for the engine
+
+The tablebase covers every position with at most `-k [pieces]` pieces per side (default 2,
+kings included, 3 needs over 2GB of memory) and takes `-j [threads]`. Engines use it through
+their config or `setoption`
+
+The book searches every position less than `-p [plies]` moves into `-n [deals]` random deals
+(default 100 deals and 2 plies, `-n all` for every deal) with `-c [config]` on `-j [threads]`.
+An existing book file is added to
+
+Analyse takes a position in the notation of notation.rs, `-d [depth]`, `-t [seconds]` and
+`-c [config]`. While playing or replaying, press A in the window or enter `a` in the terminal
+on your move to analyse
+
+Perft takes a position in the same notation and a depth, `--divide` prints the count after
+every move and `--bulk` counts the last moves without playing them. Depths of 8 and more are
+split over `-j [threads]`. `perft --suite [file]` checks the known counts of a suite file like
+perft.epd instead, up to `-d [depth]`
+
+The server speaks the same protocol as litama and listens on `-p [port]` (default 8080),
+play on it with `--server ws://localhost:8080`
 
 When using preset cards they be separated by spaces and in this order:
     [red1] [red2] [blue1] [blue2] [side]";
@@ -48,27 +118,94 @@ fn main() {
     }
 }
 
+use std::cell::RefCell;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
 
-use crate::bot::get_move;
-use crate::cli::Args;
+use crate::analysis::{analyse, REVIEW_TIME};
+use crate::book::Book;
+use crate::bot::{get_move, MoveAnalysis};
+use crate::cli::{Args, Command};
 use crate::cli::{GameHost, Playing};
 use crate::colour::Colour;
+use crate::config::EngineConfig;
 use crate::connection::{Connection, Participant};
+use crate::error::OnitamaError;
 use crate::game::{Game, Move};
+use crate::messages::{move_to_text, StateMsg};
+use crate::record::{find_line, find_move, GameRecord};
+
+// the most moves looked for between two states of an online game, after a reconnect
+const MISSED_PLIES: usize = 4;
 
+// from the game to the gui
 pub enum Transmission {
     Display(Game),
+    // the move that led to the position on display
+    LastMove(Option<Move>),
     Usernames(String, String),
     RequestMove,
+    RequestStep,
+    // the moves played so far and the ply on display
+    MoveList(Vec<String>, usize),
+    // every move of the position on display, best first
+    Analysis(Vec<MoveAnalysis>),
+    // the game is over, e.g. `bob (blue) won`
+    Winner(String),
+    // why the move from the front end wasn't played
+    Refused(String),
+    // what happened to the match or the saved game, e.g. `match id: ...`
+    Status(String),
+}
+
+// from the gui to the game
+pub enum Action {
+    Move(Move),
+    Forward,
+    Back,
+    Goto(usize),
+    // analyse the position on display
+    Analyse,
 }
 
 fn run() -> Result<(), String> {
-    let args = cli::parse_args()?;
+    match cli::parse_args()? {
+        Command::Play(args) => {
+            let use_gui = args.gui;
+            run_with_front_end(use_gui, move |tx, rx, should_end| {
+                run_game(tx, rx, args, should_end).map_err(|e| e.to_string())
+            })
+        }
+        Command::Replay(path, use_gui, config) => {
+            let record = GameRecord::load(&path)?;
+            let config = match &config {
+                Some(path) => EngineConfig::load(path)?,
+                None => EngineConfig::default(),
+            };
+            run_with_front_end(use_gui, move |tx, rx, should_end| {
+                run_replay(tx, rx, record, &config, should_end)
+            })
+        }
+        Command::Arena(args) => arena::run(args),
+        Command::Tournament(args) => tournament::run(args),
+        Command::Export(args) => training::run(args),
+        Command::Tablebase(args) => tablebase::run(args),
+        Command::Book(args) => book::run(args),
+        Command::Analyse(args) => analysis::run(args),
+        Command::Perft(args) => perft::run(args),
+        Command::Server(port) => server::run(port),
+        Command::Engine => engine::run(),
+    }
+}
 
+fn run_with_front_end<F>(use_gui: bool, run_game: F) -> Result<(), String>
+where
+    F: FnOnce(Sender<Transmission>, Receiver<Action>, &Arc<AtomicBool>) -> Result<(), String>
+        + Send
+        + 'static,
+{
     // track whether the program should exit
     let should_end = Arc::new(AtomicBool::new(false));
 
@@ -76,17 +213,16 @@ fn run() -> Result<(), String> {
     let (tx_gui, rx_game) = channel();
     let (tx_game, rx_gui) = channel();
 
-    // TODO no-gui option
     let gui_should_end = Arc::clone(&should_end);
     let gui_thread = thread::spawn(move || {
-        let res = gui::run(tx_gui, rx_gui, &gui_should_end);
+        let res = run_front_end(use_gui, tx_gui, rx_gui, &gui_should_end);
         gui_should_end.store(true, Ordering::Relaxed);
         res
     });
 
     let game_should_end = Arc::clone(&should_end);
     let game_thread = thread::spawn(move || {
-        let res = run_game(tx_game, rx_game, args, &game_should_end);
+        let res = run_game(tx_game, rx_game, &game_should_end);
         if res.is_err() {
             game_should_end.store(true, Ordering::Relaxed);
         }
@@ -108,58 +244,252 @@ fn run() -> Result<(), String> {
     }
 }
 
+#[cfg(feature = "gui")]
+fn run_front_end(
+    use_gui: bool,
+    tx: Sender<Action>,
+    rx: Receiver<Transmission>,
+    should_end: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    if use_gui {
+        gui::run(tx, rx, should_end)
+    } else {
+        terminal::run(tx, rx, should_end)
+    }
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_front_end(
+    _use_gui: bool,
+    tx: Sender<Action>,
+    rx: Receiver<Transmission>,
+    should_end: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    terminal::run(tx, rx, should_end)
+}
+
 fn run_game(
     tx_game: Sender<Transmission>,
-    rx_game: Receiver<Move>,
+    rx_game: Receiver<Action>,
     args: Args,
     should_end: &Arc<AtomicBool>,
-) -> Result<(), String> {
+) -> Result<(), OnitamaError> {
+    // the bot and the analysis search with the same engine
+    let mut config = match &args.config {
+        Some(path) => EngineConfig::load(path)?,
+        None => EngineConfig::default(),
+    };
+    if let Some(threads) = args.threads {
+        config.threads = threads;
+    }
+    let time_limit = args.time_limit;
+    // one searcher for the whole game, like a player in the arena,
+    // the analysis shares its table
+    let searcher = RefCell::new(config.searcher());
+    searcher.borrow_mut().stop = Arc::clone(should_end);
+
     // helper closures
-    let display = |game: &Game| {
+    let display = |game: &Game, last_move: Option<Move>| {
         // println!("{}", game);
         tx_game
             .send(Transmission::Display(game.clone()))
+            .and_then(|()| tx_game.send(Transmission::LastMove(last_move)))
             .map_err(|e| e.to_string())
     };
-    let get_move_from_gui = || {
+    let get_action_from_gui = || {
         tx_game
             .send(Transmission::RequestMove)
             .map_err(|e| e.to_string())?;
         rx_game.recv().map_err(|e| e.to_string())
     };
+    let get_step_from_gui = || {
+        tx_game
+            .send(Transmission::RequestStep)
+            .map_err(|e| e.to_string())?;
+        rx_game.recv().map_err(|e| e.to_string())
+    };
+    // the front end only offers legal moves, but it is checked before it gets near the board,
+    // a front end that is gone can't be told and the next request notices it
+    let checked = |game: &Game, m: &Move| match game.validate_move(m) {
+        Ok(()) => true,
+        Err(err) => {
+            let _ = tx_game.send(Transmission::Refused(err.to_string()));
+            false
+        }
+    };
+    let get_move_from_gui = |game: &Game| -> Result<Move, String> {
+        loop {
+            match get_action_from_gui()? {
+                Action::Move(m) if checked(game, &m) => break Ok(m),
+                Action::Analyse => tx_game
+                    .send(Transmission::Analysis(analyse(
+                        &mut searcher.borrow_mut(),
+                        game,
+                        time_limit,
+                    )))
+                    .map_err(|e| e.to_string())?,
+                // there is nothing to undo online
+                _ => {}
+            }
+        }
+    };
+    let send_move_list = |history: &[Game], moves: &[Move], ply: usize| {
+        let texts = moves
+            .iter()
+            .zip(history)
+            .map(|(m, game)| move_to_text(m, game))
+            .collect();
+        tx_game
+            .send(Transmission::MoveList(texts, ply))
+            .map_err(|e| e.to_string())
+    };
+    let send_winner = |winner: Colour, red: &str, blue: &str| {
+        let name = match winner {
+            Colour::Red => red,
+            Colour::Blue => blue,
+        };
+        tx_game
+            .send(Transmission::Winner(format!(
+                "{} ({}) won",
+                name,
+                winner.get_name()
+            )))
+            .map_err(|e| e.to_string())
+    };
+    // the terminal front end prints the board on stdout, so nothing else is printed there
+    let status = |text: String| {
+        tx_game
+            .send(Transmission::Status(text))
+            .map_err(|e| e.to_string())
+    };
     let send_usernames = |red: &str, blue: &str| {
         tx_game
             .send(Transmission::Usernames(red.to_string(), blue.to_string()))
             .map_err(|e| e.to_string())
     };
 
-    let (playing, host) = args;
+    let Args {
+        playing,
+        host,
+        save_dir,
+        book,
+        server,
+        ..
+    } = args;
+    let book = book.map(|path| Book::load(&path)).transpose()?;
+    // what the bot thought goes to stderr, stdout is for the board in the terminal
+    let bot_move = |game: &Game| {
+        let (m, report) = get_move(game, &mut searcher.borrow_mut(), time_limit, book.as_ref());
+        match report {
+            Some(r) => eprintln!("depth {}: {}", r.depth, r.value),
+            None => eprintln!("book move"),
+        }
+        m
+    };
+    let save = |mut record: GameRecord, game: &Game| -> Result<(), String> {
+        if let (Some(dir), false) = (&save_dir, game.in_progress) {
+            record.finish(game);
+            status(format!("saved game to {}", record.save(dir)?.display()))?;
+        }
+        Ok(())
+    };
     match host {
-        GameHost::Local(mut game) => {
+        GameHost::Local(game) => {
+            config.evaluator.check_cards(&game)?;
             let my_colour = Colour::Red; // TODO pick randomly?
-            while game.in_progress {
+            let red = match playing {
+                Playing::Human => "human",
+                _ => "bot",
+            };
+            let mut record = GameRecord::new(&game, red, "bot");
+            // undone moves are kept for redo until a different move is played
+            let mut history = vec![game];
+            let mut moves: Vec<Move> = Vec::new();
+            let mut ply = 0;
+            // a finished game stays up so that its moves can still be undone,
+            // until the front end is closed
+            loop {
                 if should_end.load(Ordering::Relaxed) {
                     break;
                 }
-                display(&game)?;
-                let the_move = if my_colour == game.colour {
-                    // my turn
-                    match playing {
-                        Playing::Human => get_move_from_gui()?,
-                        Playing::Bot => get_move(&game),
-                        Playing::No => unreachable!(),
+                let game = history[ply].clone();
+                display(&game, ply.checked_sub(1).map(|i| moves[i]))?;
+                send_move_list(&history, &moves, ply)?;
+                if !game.in_progress {
+                    send_winner(game.colour.next(), red, "bot")?;
+                }
+                let human_turn = game.in_progress
+                    && my_colour == game.colour
+                    && matches!(playing, Playing::Human);
+                // the bot waits while an earlier position is on display so that the moves after it
+                // can still be redone, the line only branches when the human plays a different move
+                let browsing = ply + 1 < history.len();
+                let the_move = if human_turn || browsing || !game.in_progress {
+                    let action = if human_turn {
+                        get_action_from_gui()?
+                    } else if game.in_progress {
+                        get_step_from_gui()?
+                    } else {
+                        match get_step_from_gui() {
+                            Ok(action) => action,
+                            // the front end was closed after the game ended
+                            Err(_) => break,
+                        }
+                    };
+                    match action {
+                        Action::Move(m) if human_turn && checked(&game, &m) => m,
+                        Action::Move(_) => continue,
+                        // undo and redo skip over the bot's moves
+                        Action::Back => {
+                            ply = (0..ply)
+                                .rev()
+                                .find(|&i| history[i].colour == my_colour)
+                                .unwrap_or(ply);
+                            continue;
+                        }
+                        Action::Forward => {
+                            ply = (ply + 1..history.len())
+                                .find(|&i| {
+                                    history[i].colour == my_colour || !history[i].in_progress
+                                })
+                                .unwrap_or(ply);
+                            continue;
+                        }
+                        Action::Goto(i) => {
+                            ply = i.min(history.len() - 1);
+                            continue;
+                        }
+                        Action::Analyse => {
+                            tx_game
+                                .send(Transmission::Analysis(analyse(
+                                    &mut searcher.borrow_mut(),
+                                    &game,
+                                    time_limit,
+                                )))
+                                .map_err(|e| e.to_string())?;
+                            continue;
+                        }
                     }
                 } else {
-                    // otherwise bot plays
-                    get_move(&game)
+                    bot_move(&game)
                 };
-                game = game.take_turn(&the_move);
+                if moves.get(ply) != Some(&the_move) {
+                    history.truncate(ply + 1);
+                    moves.truncate(ply);
+                    history.push(game.take_turn(&the_move));
+                    moves.push(the_move);
+                }
+                ply += 1;
             }
-            display(&game)?;
+            // the whole line is saved once it ended, whichever ply was on display
+            for (m, game) in moves.iter().zip(&history) {
+                record.push(m, game);
+            }
+            save(record, &history[history.len() - 1])?;
         }
 
         GameHost::Online(maybe_match_id, username) => {
-            let mut conn = Connection::new(SERVER)?;
+            let mut conn = Connection::new(&server)?;
 
             let (match_id, p) = match maybe_match_id {
                 Some(match_id) => {
@@ -170,43 +500,153 @@ fn run_game(
                             index: 0,
                         }
                     } else {
-                        conn.join_match(&match_id, &username)
+                        conn.join_match(&match_id, &username)?
                     };
                     (match_id, p)
                 }
-                None => conn.create_match(&username),
+                None => conn.create_match(&username)?,
             };
-            println!("match id: {}", match_id);
+            status(format!("match id: {}", match_id))?;
             // println!("join: https://git.io/onitama#{}", match_id);
             // println!("spectate: https://git.io/onitama#spectate-{}", match_id);
 
-            let mut state_msg = conn.spectate(&match_id);
+            let mut state_msg = conn.spectate(&match_id)?;
             let colour = if p.index == state_msg.indices.red {
                 Colour::Red
             } else {
                 Colour::Blue
             };
-            send_usernames(&state_msg.usernames.red, &state_msg.usernames.blue)?;
-            let mut game = Game::from_state_msg(state_msg);
+            let usernames = state_msg.usernames.clone();
+            send_usernames(&usernames.red, &usernames.blue)?;
+            let mut game = Game::from_state_msg(&state_msg)?;
+            config.evaluator.check_cards(&game)?;
+            let mut record = GameRecord::new(&game, &usernames.red, &usernames.blue);
+            // take the moves from the server, a desync is reported and the server's board is kept
+            let sync = |record: &mut GameRecord, state_msg: &StateMsg| {
+                let synced = match record.sync(state_msg) {
+                    Ok(synced) => synced,
+                    Err(err) => {
+                        status(err.to_string())?;
+                        false
+                    }
+                };
+                tx_game
+                    .send(Transmission::MoveList(
+                        record.moves.clone(),
+                        record.moves.len(),
+                    ))
+                    .map_err(|e| e.to_string())?;
+                Ok::<bool, String>(synced)
+            };
+            sync(&mut record, &state_msg)?;
+            let mut last_move = None;
             while game.in_progress {
                 if should_end.load(Ordering::Relaxed) {
                     break;
                 }
-                display(&game)?;
+                display(&game, last_move)?;
                 if colour == game.colour && !matches!(playing, Playing::No) {
                     let my_move = match playing {
-                        Playing::Human => get_move_from_gui()?,
-                        Playing::Bot => get_move(&game),
+                        Playing::Human => get_move_from_gui(&game)?,
+                        Playing::Bot => bot_move(&game),
                         Playing::No => unreachable!(),
                     };
-                    state_msg = conn.make_move(&my_move, &match_id, &p.token, &game);
+                    state_msg = conn.make_move(&my_move, &match_id, &p.token, &game)?;
                 } else {
-                    state_msg = conn.recv_state();
+                    state_msg = conn.recv_state()?;
+                }
+                // a state that can't be read is skipped for the one the match is in now
+                let next = match Game::from_state_msg(&state_msg) {
+                    Ok(next) => next,
+                    Err(err) => {
+                        status(format!("{}, asking the server again", err))?;
+                        state_msg = conn.spectate(&match_id)?;
+                        Game::from_state_msg(&state_msg)?
+                    }
+                };
+                // after a reconnect the match may not have moved on
+                if next.hash() == game.hash() && next.in_progress {
+                    continue;
                 }
-                game = Game::from_state_msg(state_msg);
+                // more than one move may have been played while reconnecting
+                let line = find_line(&game, &next, MISSED_PLIES);
+                last_move = line.as_ref().and_then(|line| line.last().copied());
+                // servers without a move list only show the board
+                if !sync(&mut record, &state_msg)? {
+                    match line {
+                        Some(line) => {
+                            let mut before = game.clone();
+                            for m in line {
+                                record.push(&m, &before);
+                                before = before.take_turn(&m);
+                            }
+                        }
+                        None => {
+                            status(
+                                "Could not work out the moves, the game is saved from here"
+                                    .to_string(),
+                            )?;
+                            record = GameRecord::new(&next, &usernames.red, &usernames.blue);
+                        }
+                    }
+                }
+                game = next;
             }
-            display(&game)?;
+            display(&game, last_move)?;
+            if !game.in_progress {
+                match Colour::from(state_msg.winner.clone()) {
+                    Ok(winner) => {
+                        if winner != game.colour.next() {
+                            status(format!("Desync: the server says {} won", winner.get_name()))?;
+                        }
+                        send_winner(winner, &usernames.red, &usernames.blue)?;
+                    }
+                    Err(_) => status("The server didn't say who won".to_string())?,
+                }
+            }
+            save(record, &game)?;
         }
     };
     Ok(())
 }
+
+fn run_replay(
+    tx_game: Sender<Transmission>,
+    rx_game: Receiver<Action>,
+    record: GameRecord,
+    config: &EngineConfig,
+    should_end: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let positions = record.positions()?;
+    // one table for every position of the replay
+    let mut searcher = config.searcher();
+    let send = |trans| tx_game.send(trans).map_err(|e| e.to_string());
+    send(Transmission::Usernames(record.red, record.blue))?;
+    let mut index = 0;
+    while !should_end.load(Ordering::Relaxed) {
+        send(Transmission::Display(positions[index].clone()))?;
+        let last_move = index
+            .checked_sub(1)
+            .and_then(|i| find_move(&positions[i], &positions[index]));
+        send(Transmission::LastMove(last_move))?;
+        send(Transmission::MoveList(record.moves.clone(), index))?;
+        send(Transmission::RequestStep)?;
+        match rx_game.recv() {
+            Ok(Action::Forward) => index = (index + 1).min(positions.len() - 1),
+            Ok(Action::Back) => index = index.saturating_sub(1),
+            Ok(Action::Goto(i)) => index = i.min(positions.len() - 1),
+            Ok(Action::Analyse) => match config.evaluator.check_cards(&positions[index]) {
+                Ok(()) => send(Transmission::Analysis(analyse(
+                    &mut searcher,
+                    &positions[index],
+                    REVIEW_TIME,
+                )))?,
+                Err(err) => send(Transmission::Status(err))?,
+            },
+            Ok(Action::Move(_)) => {}
+            // the gui was closed
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
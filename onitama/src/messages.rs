@@ -1,7 +1,11 @@
+use bitwise::TestBit;
+
+use crate::cards::Card;
+use crate::error::OnitamaError;
 use crate::game::{Game, Move};
 use std::result::Result;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "messageType")]
 pub enum LitamaMessage {
     #[serde(rename = "create")]
@@ -16,9 +20,12 @@ pub enum LitamaMessage {
     Spectate(SpectateMsg),
     #[serde(rename = "error")]
     Error(ErrorMsg),
+    // a message type added to the server after this was written
+    #[serde(other)]
+    Unknown,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateMsg {
     pub match_id: String,
@@ -26,7 +33,7 @@ pub struct CreateMsg {
     pub index: u8,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JoinMsg {
     pub match_id: String,
@@ -34,7 +41,7 @@ pub struct JoinMsg {
     pub index: u8,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StateMsg {
     pub usernames: UsernamesObj,
@@ -45,30 +52,34 @@ pub struct StateMsg {
     pub current_turn: String,
     #[serde(default)]
     pub cards: CardsObj,
-    // pub starting_cards: CardsObj,
-    // pub moves: Vec<String>,
+    // the cards dealt at the start and every move since, written like `tiger c1c3`
+    #[serde(default)]
+    pub starting_cards: CardsObj,
+    #[serde(default)]
+    pub moves: Vec<String>,
     #[serde(default)]
     pub board: String,
     pub game_state: String,
+    // red, blue or none
     #[serde(default)]
     pub winner: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct UsernamesObj {
     pub red: String,
     pub blue: String,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct IndicesObj {
     pub red: u8,
     pub blue: u8,
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct CardsObj {
     pub red: [String; 2],
@@ -76,19 +87,19 @@ pub struct CardsObj {
     pub side: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MoveMsg {
     pub match_id: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SpectateMsg {
     pub match_id: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ErrorMsg {
     pub match_id: String,
@@ -96,12 +107,15 @@ pub struct ErrorMsg {
     pub query: String,
 }
 
-pub fn is_in_progress(game_state: String) -> Result<bool, String> {
-    match game_state.as_ref() {
+pub fn is_in_progress(game_state: &str) -> Result<bool, OnitamaError> {
+    match game_state {
         "waiting for player" => Ok(true),
         "in progress" => Ok(true),
         "ended" => Ok(false),
-        _ => Err(format!("Unknown game state: {}", game_state)),
+        _ => Err(OnitamaError::Protocol(format!(
+            "Unknown game state: {}",
+            game_state
+        ))),
     }
 }
 
@@ -129,16 +143,130 @@ pub fn parse_pos(pos: &str) -> Result<usize, String> {
     Ok(row * 5 + col)
 }
 
-pub fn move_to_command(my_move: &Move, match_id: &str, token: &str, game: &Game) -> String {
-    let pos = format!(
+// the inverse of parse_move, e.g. `tiger c1c3`,
+// the wind spirit moving after the piece is added like `tiger c1c3 c3d3`
+pub fn move_to_text(my_move: &Move, game: &Game) -> String {
+    let mut pos = format!(
         "{}{}",
         translate_pos(my_move.from as usize),
         translate_pos(my_move.to as usize)
     );
+    if let Some(spirit) = my_move.spirit {
+        pos.push_str(&format!(
+            " {}{}",
+            translate_pos(game.spirit.trailing_zeros() as usize),
+            translate_pos(spirit as usize)
+        ));
+    }
     let card = if my_move.used_left_card {
         &game.my.cards[0]
     } else {
         &game.my.cards[1]
     };
-    format!("move {} {} {} {}", match_id, token, card.get_name(), pos)
+    format!("{} {}", card.get_name(), pos)
+}
+
+pub fn move_to_command(my_move: &Move, match_id: &str, token: &str, game: &Game) -> String {
+    format!(
+        "move {} {} {}",
+        match_id,
+        token,
+        move_to_text(my_move, game)
+    )
+}
+
+// parse a move written as a card and two positions, e.g. `tiger c1c3`
+pub fn parse_move(text: &str, game: &Game) -> Result<Move, String> {
+    let mut words = text.split_whitespace();
+    let card = Card::from_text(&words.next().ok_or("No card given")?.to_lowercase())?;
+    let used_left_card = if card == game.my.cards[0] {
+        true
+    } else if card == game.my.cards[1] {
+        false
+    } else {
+        return Err(format!("You don't have the {} card", card.get_name()));
+    };
+    let positions = words.next().ok_or("No positions given")?.to_lowercase();
+    let spirit_positions = words.next().map(str::to_lowercase);
+    let valid = |positions: &str| positions.len() == 4 && positions.is_ascii();
+    if !valid(&positions)
+        || !spirit_positions.as_deref().is_none_or(valid)
+        || words.next().is_some()
+    {
+        return Err(format!(
+            "Expected a move like `tiger c1c3`, got `{}`",
+            text.trim()
+        ));
+    }
+    let from = parse_pos(&positions[..2])?;
+    let to = parse_pos(&positions[2..])?;
+    let spirit = match spirit_positions {
+        Some(positions) => {
+            let from = parse_pos(&positions[..2])?;
+            if !game.spirit.test_bit(from) {
+                return Err(format!("The wind spirit isn't on {}", &positions[..2]));
+            }
+            Some(parse_pos(&positions[2..])? as u8)
+        }
+        None => None,
+    };
+    Ok(Move {
+        from: from as u8,
+        to: to as u8,
+        used_left_card,
+        spirit,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_move() {
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        for m in game.gen_moves() {
+            let text = move_to_text(&m, &game);
+            assert_eq!(parse_move(&text, &game), Ok(m));
+        }
+        assert!(parse_move("crab c1c2", &game).is_err());
+        assert!(parse_move("ox c1", &game).is_err());
+        assert!(parse_move("ox c1c2 c3", &game).is_err());
+        assert!(parse_move("ox f1c2", &game).is_err());
+    }
+
+    #[test]
+    fn test_unexpected_messages() {
+        let msg: LitamaMessage = serde_json::from_str(r#"{"messageType": "chat"}"#).unwrap();
+        assert!(matches!(msg, LitamaMessage::Unknown));
+
+        let state = |side: &str, game_state: &str| {
+            format!(
+                r#"{{"messageType": "state", "usernames": {{"red": "a", "blue": "b"}},
+                "indices": {{"red": 0, "blue": 1}}, "matchId": "m", "currentTurn": "blue",
+                "cards": {{"red": ["ox", "boar"], "blue": ["horse", "elephant"], "side": "{}"}},
+                "board": "1121100000000000000033433", "gameState": "{}", "winner": "none"}}"#,
+                side, game_state
+            )
+        };
+        let parse = |json: String| match serde_json::from_str(&json).unwrap() {
+            LitamaMessage::State(msg) => Game::from_state_msg(&msg),
+            msg => panic!("Expected a state message, got {:?}", msg),
+        };
+        assert!(parse(state("crab", "in progress")).is_ok());
+        assert!(matches!(
+            parse(state("unicorn", "in progress")),
+            Err(OnitamaError::Parse(_))
+        ));
+        assert!(matches!(
+            parse(state("crab", "paused")),
+            Err(OnitamaError::Protocol(_))
+        ));
+    }
 }
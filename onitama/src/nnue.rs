@@ -0,0 +1,389 @@
+use arrayvec::ArrayVec;
+
+use crate::cards::{BitIter, Card, BASE_CARDS};
+use crate::colour::Colour;
+use crate::eval::Evaluator;
+use crate::game::Game;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::result::Result;
+use std::sync::Arc;
+
+// A small neural network evaluator in the style of NNUE.
+//
+// Each colour has its own view of the board, rotated so that its pieces start at the bottom.
+// An input feature is a piece on a square paired with one of the two cards in that colour's hand:
+//
+//     card * 100 + kind * 25 + square
+//
+// where kind is 0 for own pawns, 1 for the own king, 2 for other pawns and 3 for the other king.
+// Each view is summed into an accumulator of `hidden` values, which only changes by
+// the few features that differ after a move, so it is updated incrementally during the search:
+// only the features of the squares that changed and of the card that was exchanged.
+// The side to move's accumulator followed by the other's are clamped to [0, 127]
+// and multiplied with the output weights. The result is divided by OUTPUT_SCALE.
+//
+// The weights are integers, saved as JSON:
+//
+// {
+//   "hidden": 32,
+//   "input_weights": [...],    1600 * hidden values, all the weights of feature 0 first
+//   "input_bias": [...],       hidden values
+//   "output_weights": [...],   2 * hidden values
+//   "output_bias": 0
+// }
+
+// the network only knows the base cards, games with others are refused by check_cards
+const NUM_CARDS: usize = BASE_CARDS;
+pub const NUM_FEATURES: usize = NUM_CARDS * 4 * 25;
+const ACTIVATION_MAX: i32 = 127;
+const OUTPUT_SCALE: i64 = 1024;
+
+#[derive(Deserialize)]
+pub struct Network {
+    hidden: usize,
+    input_weights: Vec<i32>,
+    input_bias: Vec<i32>,
+    output_weights: Vec<i32>,
+    output_bias: i32,
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Network {{ hidden: {} }}", self.hidden)
+    }
+}
+
+impl Network {
+    pub fn load(path: &Path) -> Result<Network, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let network: Network =
+            serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+        network
+            .check()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(network)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let expect = |name: &str, len: usize, expected: usize| {
+            if len == expected {
+                Ok(())
+            } else {
+                Err(format!("Expected {} {}, got {}", expected, name, len))
+            }
+        };
+        expect(
+            "input weights",
+            self.input_weights.len(),
+            NUM_FEATURES * self.hidden,
+        )?;
+        expect("input biases", self.input_bias.len(), self.hidden)?;
+        expect("output weights", self.output_weights.len(), 2 * self.hidden)
+    }
+
+    fn add_feature(&self, values: &mut [i32], feature: u16) {
+        let start = feature as usize * self.hidden;
+        let weights = &self.input_weights[start..start + self.hidden];
+        for (value, weight) in values.iter_mut().zip(weights) {
+            *value += weight;
+        }
+    }
+
+    fn remove_feature(&self, values: &mut [i32], feature: u16) {
+        let start = feature as usize * self.hidden;
+        let weights = &self.input_weights[start..start + self.hidden];
+        for (value, weight) in values.iter_mut().zip(weights) {
+            *value -= weight;
+        }
+    }
+
+    fn output(&self, us: &[i32], them: &[i32]) -> i64 {
+        let activations = us.iter().chain(them.iter());
+        let sum: i64 = activations
+            .zip(self.output_weights.iter())
+            .map(|(&a, &w)| a.clamp(0, ACTIVATION_MAX) as i64 * w as i64)
+            .sum();
+        (self.output_bias as i64 + sum) / OUTPUT_SCALE
+    }
+}
+
+// at most the ten pieces for each of the two cards
+type Features = ArrayVec<[u16; 20]>;
+
+const ALL_SQUARES: u32 = (1 << 25) - 1;
+
+// the features of a colour with one of its cards, only for the pieces on the squares in `mask`
+fn card_features(features: &mut Features, g: &Game, colour: Colour, card: Card, mask: u32) {
+    // an unknown card would index past the weights, it adds no features instead
+    if card.index() >= NUM_CARDS {
+        return;
+    }
+    let (red, blue) = g.get_red_blue();
+    let (own, other) = match colour {
+        Colour::Red => (red, blue),
+        Colour::Blue => (blue, red),
+    };
+    let view = |pos: u32| match colour {
+        Colour::Red => pos as u16,
+        Colour::Blue => 24 - pos as u16,
+    };
+    let base = card.index() as u16 * 100;
+    for (player, kind) in [(own, 0), (other, 2)].iter() {
+        for pos in BitIter(player.pieces & mask) {
+            let kind = if pos == player.king as u32 {
+                kind + 1
+            } else {
+                *kind
+            };
+            features.push(base + kind * 25 + view(pos));
+        }
+    }
+}
+
+pub fn features(g: &Game, colour: Colour) -> Features {
+    let mut features = Features::new();
+    for &card in own_cards(g, colour).iter() {
+        card_features(&mut features, g, colour, card, ALL_SQUARES);
+    }
+    features
+}
+
+fn own_cards(g: &Game, colour: Colour) -> [Card; 2] {
+    let (red, blue) = g.get_red_blue();
+    match colour {
+        Colour::Red => red.cards,
+        Colour::Blue => blue.cards,
+    }
+}
+
+// the squares whose pieces changed, which include the squares of a king that moved
+fn changed_squares(before: &Game, after: &Game) -> u32 {
+    let (red_before, blue_before) = before.get_red_blue();
+    let (red_after, blue_after) = after.get_red_blue();
+    (red_before.pieces ^ red_after.pieces) | (blue_before.pieces ^ blue_after.pieces)
+}
+
+#[derive(Clone, Debug)]
+struct Accumulator {
+    hash: u64,
+    // indexed by colour, red first
+    values: [Vec<i32>; 2],
+}
+
+fn colour_index(colour: Colour) -> usize {
+    match colour {
+        Colour::Red => 0,
+        Colour::Blue => 1,
+    }
+}
+
+// the accumulators of the positions on the current search path
+#[derive(Clone, Debug)]
+pub struct Nnue {
+    network: Arc<Network>,
+    stack: Vec<Accumulator>,
+    len: usize,
+}
+
+impl Nnue {
+    pub fn new(network: Arc<Network>) -> Nnue {
+        Nnue {
+            network,
+            stack: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Nnue, String> {
+        Ok(Nnue::new(Arc::new(Network::load(path)?)))
+    }
+
+    // the features only have room for the cards of the base game
+    pub fn check_cards(&self, g: &Game) -> Result<(), String> {
+        let mut cards =
+            g.my.cards
+                .iter()
+                .chain(&g.other.cards)
+                .chain([&g.table_card]);
+        match cards.find(|card| card.index() >= NUM_CARDS) {
+            Some(card) => Err(format!(
+                "The network only knows the cards of the base game, not {}",
+                card.get_name()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn refresh(&self, g: &Game, hash: u64) -> Accumulator {
+        let mut acc = Accumulator {
+            hash,
+            values: [
+                self.network.input_bias.clone(),
+                self.network.input_bias.clone(),
+            ],
+        };
+        for &colour in [Colour::Red, Colour::Blue].iter() {
+            for feature in features(g, colour) {
+                self.network
+                    .add_feature(&mut acc.values[colour_index(colour)], feature);
+            }
+        }
+        acc
+    }
+
+    fn output(&self, acc: &Accumulator, colour: Colour) -> i64 {
+        let us = colour_index(colour);
+        self.network.output(&acc.values[us], &acc.values[1 - us])
+    }
+}
+
+impl Evaluator for Nnue {
+    fn evaluate(&self, g: &Game) -> i64 {
+        self.evaluate_hashed(g, g.hash())
+    }
+
+    fn evaluate_hashed(&self, g: &Game, hash: u64) -> i64 {
+        match self.stack[..self.len].last() {
+            Some(acc) if acc.hash == hash => self.output(acc, g.colour),
+            _ => self.output(&self.refresh(g, hash), g.colour),
+        }
+    }
+
+    fn make_move(&mut self, g: &Game, hash: u64, next: &Game, next_hash: u64) {
+        if self.stack[..self.len].last().map(|acc| acc.hash) != Some(hash) {
+            // a new search
+            let acc = self.refresh(g, hash);
+            if self.stack.is_empty() {
+                self.stack.push(acc);
+            } else {
+                self.stack[0] = acc;
+            }
+            self.len = 1;
+        }
+        // accumulators above the top are left over from earlier moves, reuse their memory
+        if self.len == self.stack.len() {
+            let acc = self.stack[self.len - 1].clone();
+            self.stack.push(acc);
+        }
+        let (below, above) = self.stack.split_at_mut(self.len);
+        let (prev, acc) = (&below[self.len - 1], &mut above[0]);
+        acc.hash = next_hash;
+        // only the moved pieces, a captured piece and the exchanged card change the features
+        let changed = changed_squares(g, next);
+        for &colour in [Colour::Red, Colour::Blue].iter() {
+            let (old_cards, new_cards) = (own_cards(g, colour), own_cards(next, colour));
+            let mut removed = Features::new();
+            let mut added = Features::new();
+            for &card in old_cards.iter() {
+                let mask = if new_cards.contains(&card) {
+                    changed
+                } else {
+                    ALL_SQUARES
+                };
+                card_features(&mut removed, g, colour, card, mask);
+            }
+            for &card in new_cards.iter() {
+                let mask = if old_cards.contains(&card) {
+                    changed
+                } else {
+                    ALL_SQUARES
+                };
+                card_features(&mut added, next, colour, card, mask);
+            }
+            let i = colour_index(colour);
+            acc.values[i].copy_from_slice(&prev.values[i]);
+            for &feature in removed.iter() {
+                self.network.remove_feature(&mut acc.values[i], feature);
+            }
+            for &feature in added.iter() {
+                self.network.add_feature(&mut acc.values[i], feature);
+            }
+        }
+        self.len += 1;
+    }
+
+    fn unmake_move(&mut self) {
+        self.len = self.len.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // arbitrary but fixed weights
+    fn test_network() -> Network {
+        let hidden = 8;
+        let weight = |i: usize, m: usize| ((i * 7919 + 13) % m) as i32 - (m / 2) as i32;
+        Network {
+            hidden,
+            input_weights: (0..NUM_FEATURES * hidden).map(|i| weight(i, 61)).collect(),
+            input_bias: (0..hidden).map(|i| weight(i, 41)).collect(),
+            output_weights: (0..2 * hidden).map(|i| weight(i, 2003)).collect(),
+            output_bias: 17,
+        }
+    }
+
+    #[test]
+    fn test_incremental_matches_refresh() {
+        let network = test_network();
+        network.check().unwrap();
+        let mut nnue = Nnue::new(Arc::new(network));
+        let mut game = Game::from_cards(vec![
+            Card::Tiger,
+            Card::Dragon,
+            Card::Frog,
+            Card::Rabbit,
+            Card::Cobra,
+        ]);
+        let mut ply = 0;
+        while game.in_progress && ply < 100 {
+            let moves = game.gen_moves();
+            let m = moves[(ply * 5) % moves.len()];
+            let next = game.take_turn(&m);
+            nnue.make_move(&game, game.hash(), &next, next.hash());
+            let fresh = Nnue::new(Arc::clone(&nnue.network));
+            assert_eq!(nnue.evaluate(&next), fresh.evaluate(&next));
+            assert_eq!(nnue.len, ply + 2);
+            game = next;
+            ply += 1;
+        }
+        // back to the start
+        for _ in 0..ply {
+            nnue.unmake_move();
+        }
+        assert_eq!(nnue.len, 1);
+        // the expansions have no features
+        assert_eq!(nnue.check_cards(&game), Ok(()));
+        let expansion = Game::from_cards(vec![
+            Card::Tiger,
+            Card::Dragon,
+            Card::Frog,
+            Card::Rabbit,
+            Card::Bat,
+        ]);
+        assert!(nnue.check_cards(&expansion).is_err());
+    }
+
+    #[test]
+    fn test_features_symmetric() {
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        // both sides see the same start apart from their cards
+        let squares = |colour| {
+            let mut squares: Vec<u16> = features(&game, colour).iter().map(|f| f % 100).collect();
+            squares.sort_unstable();
+            squares
+        };
+        assert_eq!(squares(Colour::Red).len(), 20);
+        assert_eq!(squares(Colour::Red), squares(Colour::Blue));
+    }
+}
@@ -0,0 +1,269 @@
+use bitwise::{SetBit, TestBit};
+
+use crate::cards::Card;
+use crate::colour::Colour;
+use crate::game::{Game, Player};
+use std::result::Result;
+
+// A single line position notation, similar to FEN in chess:
+//
+//     bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab
+//
+// - the board from the top row to the bottom row, separated by `/`,
+//   `r` is a red pawn, `R` the red king, `b` a blue pawn, `B` the blue king,
+//   `w` the wind spirit of the Way of the Wind and digits count empty squares
+// - the colour to move, `r` or `b`
+// - red's cards, blue's cards and the side card
+// The order of the cards in a hand is kept.
+
+impl Game {
+    pub fn to_notation(&self) -> String {
+        let (red, blue) = self.get_red_blue();
+        let mut board = String::new();
+        for row in 0..5u8 {
+            let mut empty = 0;
+            for col in 0..5u8 {
+                let pos = 5 * row + col;
+                let piece = if red.pieces.test_bit(pos) {
+                    Some(if red.king == pos { 'R' } else { 'r' })
+                } else if blue.pieces.test_bit(pos) {
+                    Some(if blue.king == pos { 'B' } else { 'b' })
+                } else if self.spirit.test_bit(pos) {
+                    Some('w')
+                } else {
+                    None
+                };
+                match piece {
+                    Some(piece) => {
+                        if empty > 0 {
+                            board.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        board.push(piece);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                board.push_str(&empty.to_string());
+            }
+            if row < 4 {
+                board.push('/');
+            }
+        }
+        let colour = match self.colour {
+            Colour::Red => 'r',
+            Colour::Blue => 'b',
+        };
+        format!(
+            "{} {} {},{} {},{} {}",
+            board,
+            colour,
+            red.cards[0].get_name(),
+            red.cards[1].get_name(),
+            blue.cards[0].get_name(),
+            blue.cards[1].get_name(),
+            self.table_card.get_name()
+        )
+    }
+
+    pub fn from_notation(notation: &str) -> Result<Game, String> {
+        let fields: Vec<&str> = notation.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 fields, got {}", fields.len()));
+        }
+
+        // board
+        let mut red = Player {
+            cards: [Card::Boar; 2],
+            pieces: 0,
+            king: 25,
+        };
+        let mut blue = Player {
+            cards: [Card::Boar; 2],
+            pieces: 0,
+            king: 25,
+        };
+        let mut spirit = 0u32;
+        let rows: Vec<&str> = fields[0].split('/').collect();
+        if rows.len() != 5 {
+            return Err(format!("Expected 5 rows, got {}", rows.len()));
+        }
+        for (row, text) in rows.iter().enumerate() {
+            let mut col = 0;
+            // runs of empty squares are written as a single digit from 1 to 5
+            let mut after_run = false;
+            for character in text.chars() {
+                if let Some(empty) = character.to_digit(10) {
+                    if empty == 0 || after_run {
+                        return Err(format!(
+                            "Row {} has an invalid run of empty squares",
+                            row + 1
+                        ));
+                    }
+                    col += empty as usize;
+                    if col > 5 {
+                        return Err(format!("Row {} has too many squares", row + 1));
+                    }
+                    after_run = true;
+                    continue;
+                }
+                after_run = false;
+                if col >= 5 {
+                    return Err(format!("Row {} has too many squares", row + 1));
+                }
+                let pos = (5 * row + col) as u8;
+                if character == 'w' {
+                    if spirit != 0 {
+                        return Err("There can only be one wind spirit".to_string());
+                    }
+                    spirit = spirit.set_bit(pos);
+                    col += 1;
+                    continue;
+                }
+                let (player, king) = match character {
+                    'r' => (&mut red, false),
+                    'R' => (&mut red, true),
+                    'b' => (&mut blue, false),
+                    'B' => (&mut blue, true),
+                    _ => return Err(format!("Unknown piece `{}`", character)),
+                };
+                if king {
+                    if player.king != 25 {
+                        return Err("There can only be one king per colour".to_string());
+                    }
+                    player.king = pos;
+                }
+                player.pieces = player.pieces.set_bit(pos);
+                col += 1;
+            }
+            if col != 5 {
+                return Err(format!("Row {} does not have 5 squares", row + 1));
+            }
+        }
+
+        // colour to move
+        let colour = match fields[1] {
+            "r" => Colour::Red,
+            "b" => Colour::Blue,
+            _ => return Err(format!("Unknown colour `{}`", fields[1])),
+        };
+
+        // cards
+        let hand = |text: &str| -> Result<[Card; 2], String> {
+            let names: Vec<&str> = text.split(',').collect();
+            if names.len() != 2 {
+                return Err(format!("Expected 2 cards in `{}`", text));
+            }
+            Ok([Card::from_text(names[0])?, Card::from_text(names[1])?])
+        };
+        red.cards = hand(fields[2])?;
+        blue.cards = hand(fields[3])?;
+        let table_card = Card::from_text(fields[4])?;
+        let mut cards = vec![
+            red.cards[0],
+            red.cards[1],
+            blue.cards[0],
+            blue.cards[1],
+            table_card,
+        ];
+        cards.sort_by_key(|&card| card.index());
+        cards.dedup();
+        if cards.len() != 5 {
+            return Err("The same card cannot be used twice".to_string());
+        }
+
+        // the game is over if a king was captured or reached the other temple
+        let in_progress = red.king != 25 && blue.king != 25 && red.king != 2 && blue.king != 22;
+        let (my, other) = match colour {
+            Colour::Red => (red, blue),
+            Colour::Blue => (blue, red),
+        };
+        Ok(Game {
+            my,
+            other,
+            table_card,
+            colour,
+            in_progress,
+            spirit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab";
+
+    #[test]
+    fn test_start_notation() {
+        let game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        assert_eq!(game.to_notation(), START);
+        let parsed = Game::from_notation(START).unwrap();
+        assert_eq!(parsed.hash(), game.hash());
+        assert_eq!(parsed.my.cards, game.my.cards);
+        assert!(parsed.in_progress);
+    }
+
+    #[test]
+    fn test_notation_round_trip() {
+        let mut game = Game::from_cards(vec![
+            Card::Tiger,
+            Card::Dragon,
+            Card::Frog,
+            Card::Rabbit,
+            Card::Cobra,
+        ]);
+        // walk down a deterministic line until the game ends
+        let mut ply = 0;
+        while game.in_progress && ply < 200 {
+            let moves = game.gen_moves();
+            let m = moves[(ply * 7) % moves.len()];
+            game = game.take_turn(&m);
+            ply += 1;
+
+            let notation = game.to_notation();
+            let parsed = Game::from_notation(&notation).unwrap();
+            assert_eq!(parsed.to_notation(), notation);
+            assert_eq!(parsed.hash(), game.hash());
+            assert_eq!(parsed.in_progress, game.in_progress);
+            assert_eq!(parsed.my.cards, game.my.cards);
+            assert_eq!(parsed.other.cards, game.other.cards);
+            if game.in_progress {
+                assert_eq!(parsed.gen_moves(), game.gen_moves());
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_notation() {
+        for notation in [
+            "bbBbb/5/5/5/rrRrr b elephant,horse boar,ox",
+            "bbBbb/5/5/rrRrr b elephant,horse boar,ox crab",
+            "bbBbb/6/5/5/rrRrr b elephant,horse boar,ox crab",
+            "bbBbb/4/5/5/rrRrr b elephant,horse boar,ox crab",
+            "bbBbb/05/5/5/rrRrr b elephant,horse boar,ox crab",
+            "bbBbb/23/5/5/rrRrr b elephant,horse boar,ox crab",
+            "bbBbb/5/5/5/r0rRrr b elephant,horse boar,ox crab",
+            "bbBbb/5/5/2r3/rrRrr b elephant,horse boar,ox crab",
+            "bbBbbb/5/5/5/rrRrr b elephant,horse boar,ox crab",
+            "bbBbx/5/5/5/rrRrr b elephant,horse boar,ox crab",
+            "bbBbB/5/5/5/rrRrr b elephant,horse boar,ox crab",
+            "bbBbb/5/5/5/rrRrr g elephant,horse boar,ox crab",
+            "bbBbb/5/5/5/rrRrr b elephant boar,ox crab",
+            "bbBbb/5/5/5/rrRrr b elephant,horse boar,ox owl",
+            "bbBbb/5/5/5/rrRrr b elephant,horse boar,ox ox",
+        ]
+        .iter()
+        {
+            assert!(Game::from_notation(notation).is_err(), "{}", notation);
+        }
+    }
+}
@@ -1,4 +1,28 @@
-use crate::game::Game;
+use crate::cli::{PerftArgs, PerftPositions};
+use crate::game::{Game, Move};
+use crate::messages::move_to_text;
+
+use std::fs;
+use std::path::Path;
+use std::result::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+// Perft counts the positions a number of moves ahead, to check the move generation.
+// `perft` plays every move, `perft_cheat` counts the moves of the last ply without playing them.
+//
+// A suite file has a position in the notation of notation.rs on every line,
+// followed by its known counts, similar to EPD files in chess:
+//
+//     bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab ;D1 10 ;D2 130 ;D3 1989
+//
+// Empty lines and lines starting with `#` are skipped. perft.epd is checked by the tests.
+
+// shallower counts are over too quickly to be worth splitting over threads
+pub const PARALLEL_DEPTH: u8 = 8;
 
 pub fn perft(g: &Game, depth: u8) -> usize {
     if !g.in_progress || depth == 0 {
@@ -32,10 +56,207 @@ pub fn perft_cheat(g: &Game, depth: u8) -> usize {
     }
 }
 
+// with `bulk` the last ply is counted with perft_cheat
+fn count(g: &Game, depth: u8, bulk: bool) -> usize {
+    if depth == 0 {
+        1
+    } else if bulk {
+        perft_cheat(g, depth)
+    } else {
+        perft(g, depth)
+    }
+}
+
+// the count after every legal move in the order of gen_moves,
+// the moves are shared between the threads
+pub fn divide(
+    g: &Game,
+    depth: u8,
+    bulk: bool,
+    threads: usize,
+) -> Result<Vec<(Move, usize)>, String> {
+    if !g.in_progress || depth == 0 {
+        return Ok(Vec::new());
+    }
+    let moves: Vec<Move> = g.gen_moves().into_iter().collect();
+    if threads <= 1 {
+        return Ok(moves
+            .iter()
+            .map(|m| (*m, count(&g.take_turn(m), depth - 1, bulk)))
+            .collect());
+    }
+
+    let game = Arc::new(g.clone());
+    let moves = Arc::new(moves);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = channel();
+    let handles: Vec<_> = (0..threads.min(moves.len()))
+        .map(|_| {
+            let (game, moves, next) = (Arc::clone(&game), Arc::clone(&moves), Arc::clone(&next));
+            let tx = tx.clone();
+            thread::spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= moves.len() {
+                    break;
+                }
+                let nodes = count(&game.take_turn(&moves[i]), depth - 1, bulk);
+                if tx.send((i, nodes)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+    let mut counts = vec![0; moves.len()];
+    for (i, nodes) in rx {
+        counts[i] = nodes;
+    }
+    for handle in handles {
+        handle
+            .join()
+            .map_err(|_| "A perft thread panicked".to_string())?;
+    }
+    Ok(moves.iter().copied().zip(counts).collect())
+}
+
+// the same as perft, threads are only used from PARALLEL_DEPTH
+pub fn total(g: &Game, depth: u8, bulk: bool, threads: usize) -> Result<usize, String> {
+    if !g.in_progress || depth == 0 {
+        return Ok(1);
+    }
+    let threads = if depth >= PARALLEL_DEPTH { threads } else { 1 };
+    Ok(divide(g, depth, bulk, threads)?
+        .iter()
+        .map(|(_, nodes)| nodes)
+        .sum())
+}
+
+pub struct SuiteEntry {
+    pub position: Game,
+    // the known count at each depth
+    pub counts: Vec<(u8, usize)>,
+}
+
+fn parse_suite_line(line: &str) -> Result<SuiteEntry, String> {
+    let mut fields = line.split(';');
+    let position = Game::from_notation(fields.next().unwrap_or(""))?;
+    let counts = fields
+        .map(
+            |field| match field.split_whitespace().collect::<Vec<_>>()[..] {
+                [depth, nodes] if depth.starts_with('D') => {
+                    let depth = depth[1..]
+                        .parse()
+                        .ok()
+                        .filter(|&d| d > 0)
+                        .ok_or_else(|| format!("Invalid depth: {}", depth))?;
+                    let nodes = nodes
+                        .parse()
+                        .map_err(|_| format!("Invalid count: {}", nodes))?;
+                    Ok((depth, nodes))
+                }
+                _ => Err(format!(
+                    "Expected a depth and a count like `D1 10`, got `{}`",
+                    field.trim()
+                )),
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+    if counts.is_empty() {
+        return Err("The position has no counts".to_string());
+    }
+    Ok(SuiteEntry { position, counts })
+}
+
+pub fn parse_suite(text: &str) -> Result<Vec<SuiteEntry>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+        .map(|(i, line)| parse_suite_line(line).map_err(|e| format!("Line {}: {}", i + 1, e)))
+        .collect()
+}
+
+pub fn load_suite(path: &Path) -> Result<Vec<SuiteEntry>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_suite(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn run_position(g: &Game, depth: u8, args: &PerftArgs) -> Result<(), String> {
+    if !g.in_progress {
+        return Err("The game is already over".to_string());
+    }
+    println!("{}", g);
+    let start = Instant::now();
+    let threads = if depth >= PARALLEL_DEPTH {
+        args.threads
+    } else {
+        1
+    };
+    let counts = divide(g, depth, args.bulk, threads)?;
+    if args.divide {
+        for (m, nodes) in counts.iter() {
+            println!("{:<16} {}", move_to_text(m, g), nodes);
+        }
+        println!();
+    }
+    let nodes: usize = counts.iter().map(|(_, nodes)| nodes).sum();
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "depth {}, {} nodes in {} ms ({:.0} nodes/s)",
+        depth,
+        nodes,
+        (seconds * 1000.) as u64,
+        nodes as f64 / seconds.max(1e-9)
+    );
+    Ok(())
+}
+
+fn run_suite(path: &Path, max_depth: Option<u8>, args: &PerftArgs) -> Result<(), String> {
+    let entries = load_suite(path)?;
+    let (mut checked, mut failed) = (0, 0);
+    let start = Instant::now();
+    for entry in entries.iter() {
+        let notation = entry.position.to_notation();
+        for &(depth, expected) in entry.counts.iter() {
+            if depth > max_depth.unwrap_or(u8::MAX) {
+                continue;
+            }
+            let nodes = total(&entry.position, depth, args.bulk, args.threads)?;
+            checked += 1;
+            if nodes == expected {
+                println!("{} D{} {} ok", notation, depth, nodes);
+            } else {
+                failed += 1;
+                println!(
+                    "{} D{} {} FAILED, expected {}",
+                    notation, depth, nodes, expected
+                );
+            }
+        }
+    }
+    println!(
+        "{} counts checked in {} ms",
+        checked,
+        start.elapsed().as_millis()
+    );
+    if failed > 0 {
+        return Err(format!("{} of {} counts did not match", failed, checked));
+    }
+    Ok(())
+}
+
+pub fn run(args: PerftArgs) -> Result<(), String> {
+    match &args.positions {
+        PerftPositions::One(game, depth) => run_position(game, *depth, &args),
+        PerftPositions::Suite(path, max_depth) => run_suite(path, *max_depth, &args),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cards::Card;
+    use crate::cards::{add_card, fresh_cards, Card};
+    use crate::colour::Colour;
+    use crate::game::Ruleset;
     use test::Bencher;
 
     const CARDS: [Card; 5] = [
@@ -59,6 +280,101 @@ mod tests {
         // assert_eq!(perft_cheat(&game, 8), 2353802670);
     }
 
+    // with cards from Sensei's Path and Way of the Wind
+    const EXPANSION_CARDS: [Card; 5] = [
+        Card::Kirin,
+        Card::Fox,
+        Card::Tiger,
+        Card::Phoenix,
+        Card::Eagle,
+    ];
+
+    #[test]
+    fn test_perft_expansions() {
+        let game = Game::from_cards(Vec::from(EXPANSION_CARDS));
+        assert_eq!(perft(&game, 4), 26649);
+        assert_eq!(perft_cheat(&game, 1), 12);
+        assert_eq!(perft_cheat(&game, 2), 156);
+        assert_eq!(perft_cheat(&game, 3), 1966);
+        assert_eq!(perft_cheat(&game, 4), 26649);
+        assert_eq!(perft_cheat(&game, 5), 376613);
+        assert_eq!(perft_cheat(&game, 6), 5016427);
+    }
+
+    #[test]
+    fn test_perft_wind() {
+        // the same cards with the wind spirit in the middle
+        let game = Game::from_cards_with(Vec::from(EXPANSION_CARDS), Ruleset::WayOfTheWind);
+        assert_eq!(perft(&game, 4), 69341);
+        assert_eq!(perft_cheat(&game, 1), 15);
+        assert_eq!(perft_cheat(&game, 2), 259);
+        assert_eq!(perft_cheat(&game, 3), 4355);
+        assert_eq!(perft_cheat(&game, 4), 69341);
+        assert_eq!(perft_cheat(&game, 5), 1269309);
+    }
+
+    #[test]
+    fn test_perft_custom() {
+        // a loaded card plays like the built in card it copies
+        let _cards = fresh_cards();
+        let copy = add_card(
+            "phoenix-copy",
+            Card::Phoenix.get_move(Colour::Red),
+            0,
+            Card::Phoenix.get_colour(),
+        )
+        .unwrap();
+        let mut cards = Vec::from(EXPANSION_CARDS);
+        cards[3] = copy;
+        let game = Game::from_cards(cards);
+        assert_eq!(perft_cheat(&game, 5), 376613);
+    }
+
+    #[test]
+    fn test_suite() {
+        let entries = parse_suite(include_str!("../perft.epd")).unwrap();
+        assert_eq!(entries.len(), 14);
+        for entry in entries.iter() {
+            for &(depth, nodes) in entry.counts.iter() {
+                assert_eq!(total(&entry.position, depth, true, 1), Ok(nodes));
+                if depth <= 3 {
+                    assert_eq!(total(&entry.position, depth, false, 1), Ok(nodes));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_symmetric_perft() {
+        // the positions that are turned around or mirrored have the same counts
+        let entries = parse_suite(include_str!("../perft.epd")).unwrap();
+        for entry in entries.iter() {
+            assert_eq!(entry.position.symmetries().len(), 4);
+            for &s in entry.position.symmetries() {
+                let image = entry.position.transform(s);
+                for &(depth, nodes) in entry.counts.iter().filter(|&&(depth, _)| depth <= 4) {
+                    assert_eq!(perft_cheat(&image, depth), nodes, "{}", image.to_notation());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_divide() {
+        let game = Game::from_cards_with(Vec::from(EXPANSION_CARDS), Ruleset::WayOfTheWind);
+        let counts = divide(&game, 4, true, 1).unwrap();
+        assert_eq!(counts.len(), 15);
+        assert_eq!(counts.iter().map(|(_, n)| n).sum::<usize>(), 69341);
+        // the threads give the same counts in the same order
+        assert_eq!(divide(&game, 4, false, 3), Ok(counts));
+
+        assert!(parse_suite("bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab").is_err());
+        let error =
+            parse_suite("# comment\n\nbbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab ;D0 1");
+        assert_eq!(error.err(), Some("Line 3: Invalid depth: D0".to_string()));
+        assert!(parse_suite("bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab ;D1").is_err());
+    }
+
     #[bench]
     fn bench_perft_3(b: &mut Bencher) {
         let game = test::black_box(Game::from_cards(Vec::from(CARDS)));
@@ -0,0 +1,345 @@
+use crate::colour::Colour;
+use crate::error::OnitamaError;
+use crate::game::{Game, Move};
+use crate::messages::{move_to_text, parse_move, StateMsg};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A finished or unfinished game, saved as JSON:
+//
+// {
+//   "red": "bot",
+//   "blue": "human",
+//   "start": "bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab",
+//   "moves": ["ox b1b2", "horse e5e4"],
+//   "winner": "red",
+//   "started": 1605964800,
+//   "finished": 1605965100
+// }
+//
+// `start` is the first position in the position notation, so it includes the starting cards.
+// `moves` are written like litama moves, with the card that was used.
+// `winner` is null if the game did not finish, the times are unix timestamps in seconds.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub red: String,
+    pub blue: String,
+    pub start: String,
+    pub moves: Vec<String>,
+    pub winner: Option<Colour>,
+    pub started: u64,
+    pub finished: Option<u64>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl GameRecord {
+    pub fn new(start: &Game, red: &str, blue: &str) -> GameRecord {
+        GameRecord {
+            red: red.to_string(),
+            blue: blue.to_string(),
+            start: start.to_notation(),
+            moves: Vec::new(),
+            winner: None,
+            started: now(),
+            finished: None,
+        }
+    }
+
+    // the game is the position before the move was played
+    pub fn push(&mut self, m: &Move, game: &Game) {
+        self.moves.push(move_to_text(m, game));
+    }
+
+    pub fn finish(&mut self, game: &Game) {
+        if !game.in_progress {
+            // the loser is the one left to move
+            self.winner = Some(game.colour.next());
+        }
+        self.finished = Some(now());
+    }
+
+    // the other player wins, e.g. when an engine crashes or plays an illegal move
+    pub fn forfeit(&mut self, loser: Colour) {
+        self.winner = Some(loser.next());
+        self.finished = Some(now());
+    }
+
+    // the result from red's point of view, a finished game without a winner is a draw
+    pub fn result(&self) -> &'static str {
+        match (self.winner, self.finished) {
+            (Some(Colour::Red), _) => "1-0",
+            (Some(Colour::Blue), _) => "0-1",
+            (None, Some(_)) => "1/2-1/2",
+            (None, None) => "*",
+        }
+    }
+
+    // the game in a format like PGN, after any extra tags:
+    //
+    // [Red "bot"]
+    // [Blue "human"]
+    // [Start "bbBbb/5/5/5/rrRrr b elephant,horse boar,ox crab"]
+    // [Result "1-0"]
+    //
+    // 1. ox b1b2 horse e5e4 2. boar b2b3 1-0
+    pub fn to_pgn(&self, tags: &[(&str, String)]) -> String {
+        let mut text = String::new();
+        let own = [
+            ("Red", self.red.clone()),
+            ("Blue", self.blue.clone()),
+            ("Start", self.start.clone()),
+            ("Result", self.result().to_string()),
+        ];
+        for (tag, value) in tags.iter().chain(own.iter()) {
+            text.push_str(&format!("[{} \"{}\"]\n", tag, value.replace('"', "'")));
+        }
+        text.push('\n');
+        let mut words = Vec::new();
+        for (i, m) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                words.push(format!("{}.", i / 2 + 1));
+            }
+            words.push(m.clone());
+        }
+        words.push(self.result().to_string());
+        text.push_str(&words.join(" "));
+        text.push('\n');
+        text
+    }
+
+    // every position of the game, starting with the first
+    pub fn positions(&self) -> Result<Vec<Game>, String> {
+        let mut game = Game::from_notation(&self.start)?;
+        let mut positions = Vec::new();
+        for (i, text) in self.moves.iter().enumerate() {
+            let m = parse_move(text, &game)?;
+            game.validate_move(&m)
+                .map_err(|e| format!("Move {} `{}` is illegal: {}", i + 1, text, e))?;
+            let next = game.take_turn(&m);
+            positions.push(game);
+            game = next;
+        }
+        positions.push(game);
+        Ok(positions)
+    }
+
+    // take the moves from a litama state, replayed from its starting cards,
+    // false if the server doesn't send them and a desync if they don't lead to its board
+    pub fn sync(&mut self, state_msg: &StateMsg) -> Result<bool, OnitamaError> {
+        let start = match Game::from_starting_cards(state_msg)? {
+            Some(start) => start,
+            None => return Ok(false),
+        };
+        let replayed = GameRecord {
+            start: start.to_notation(),
+            moves: state_msg.moves.clone(),
+            ..GameRecord::new(&start, &self.red, &self.blue)
+        };
+        let positions = replayed.positions().map_err(OnitamaError::Protocol)?;
+        let last = &positions[positions.len() - 1];
+        let board = Game::from_state_msg(state_msg)?;
+        if last.hash() != board.hash() || last.in_progress != board.in_progress {
+            return Err(OnitamaError::Protocol(format!(
+                "Desync after {} moves, they lead to\n{}but the server's board is\n{}",
+                replayed.moves.len(),
+                last,
+                board
+            )));
+        }
+        self.start = replayed.start;
+        self.moves = replayed.moves;
+        Ok(true)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let clean = |name: &str| -> String {
+            name.chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect()
+        };
+        let path = dir.join(format!(
+            "{}-{}-vs-{}.json",
+            self.started,
+            clean(&self.red),
+            clean(&self.blue)
+        ));
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> Result<GameRecord, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// work out which move was played from the positions before and after,
+// used when the other player's move is only seen as a new state
+pub fn find_move(before: &Game, after: &Game) -> Option<Move> {
+    let hash = after.hash();
+    before
+        .gen_moves()
+        .into_iter()
+        .find(|m| before.take_turn(m).hash() == hash)
+}
+
+// the moves from one position to another up to `plies` moves later, shortest first,
+// used to catch up on the moves played while the connection was down
+pub fn find_line(before: &Game, after: &Game, plies: usize) -> Option<Vec<Move>> {
+    (1..=plies).find_map(|n| line_of_length(before, after, n))
+}
+
+fn line_of_length(before: &Game, after: &Game, plies: usize) -> Option<Vec<Move>> {
+    if plies == 1 {
+        return find_move(before, after).map(|m| vec![m]);
+    }
+    if !before.in_progress {
+        return None;
+    }
+    before.gen_moves().into_iter().find_map(|m| {
+        let mut line = line_of_length(&before.take_turn(&m), after, plies - 1)?;
+        line.insert(0, m);
+        Some(line)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::Searcher;
+    use crate::cards::Card;
+    use crate::messages::{CardsObj, IndicesObj, UsernamesObj};
+
+    #[test]
+    fn test_record_round_trip() {
+        let mut game = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        let mut record = GameRecord::new(&game, "red", "blue");
+        let mut positions = vec![game.clone()];
+        while game.in_progress && record.moves.len() < 200 {
+            let m = Searcher::new().search(&game, 2, None).1;
+            let next = game.take_turn(&m);
+            assert_eq!(find_move(&game, &next), Some(m));
+            record.push(&m, &game);
+            game = next;
+            positions.push(game.clone());
+        }
+        record.finish(&game);
+        assert_eq!(record.winner.is_some(), !game.in_progress);
+
+        let json = serde_json::to_string(&record).unwrap();
+        let loaded: GameRecord = serde_json::from_str(&json).unwrap();
+        let replayed = loaded.positions().unwrap();
+        assert_eq!(replayed.len(), positions.len());
+        for (a, b) in replayed.iter().zip(positions.iter()) {
+            assert_eq!(a.to_notation(), b.to_notation());
+        }
+
+        let pgn = record.to_pgn(&[("Round", "1".to_string())]);
+        let mut lines = pgn.lines();
+        assert_eq!(lines.next(), Some("[Round \"1\"]"));
+        assert_eq!(lines.next(), Some("[Red \"red\"]"));
+        let moves = pgn.lines().last().unwrap();
+        assert!(moves.starts_with(&format!("1. {} {} 2.", record.moves[0], record.moves[1])));
+        assert!(moves.ends_with(record.result()));
+        record.forfeit(Colour::Red);
+        assert_eq!(record.result(), "0-1");
+    }
+
+    #[test]
+    fn test_sync() {
+        let start = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        let mut game = start.clone();
+        let mut moves = Vec::new();
+        for _ in 0..4 {
+            let m = game.gen_moves()[0];
+            moves.push(move_to_text(&m, &game));
+            game = game.take_turn(&m);
+        }
+        let cards = |g: &Game| {
+            let (red, blue) = g.get_red_blue();
+            let names = |cards: [Card; 2]| {
+                [
+                    cards[0].get_name().to_string(),
+                    cards[1].get_name().to_string(),
+                ]
+            };
+            CardsObj {
+                red: names(red.cards),
+                blue: names(blue.cards),
+                side: g.table_card.get_name().to_string(),
+            }
+        };
+        let mut state_msg = StateMsg {
+            usernames: UsernamesObj {
+                red: "a".to_string(),
+                blue: "b".to_string(),
+            },
+            indices: IndicesObj::default(),
+            match_id: "m".to_string(),
+            current_turn: game.colour.get_name().to_string(),
+            cards: cards(&game),
+            starting_cards: cards(&start),
+            moves,
+            board: game.to_board(),
+            game_state: "in progress".to_string(),
+            winner: "none".to_string(),
+        };
+        let mut record = GameRecord::new(&game, "a", "b");
+        assert_eq!(record.sync(&state_msg), Ok(true));
+        assert_eq!(record.start, start.to_notation());
+        assert_eq!(record.moves, state_msg.moves);
+        // a move missing from the list
+        state_msg.moves.pop();
+        assert!(matches!(
+            record.sync(&state_msg),
+            Err(OnitamaError::Protocol(_))
+        ));
+        assert_eq!(record.moves.len(), 4);
+        // a server without the starting cards
+        state_msg.starting_cards = CardsObj::default();
+        assert_eq!(record.sync(&state_msg), Ok(false));
+    }
+
+    #[test]
+    fn test_find_line() {
+        let start = Game::from_cards(vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ]);
+        let mut game = start.clone();
+        for _ in 0..3 {
+            game = game.take_turn(&game.gen_moves()[0]);
+        }
+        let line = find_line(&start, &game, 4).unwrap();
+        assert_eq!(line.len(), 3);
+        let replayed = line.iter().fold(start.clone(), |g, m| g.take_turn(m));
+        assert_eq!(replayed.hash(), game.hash());
+        assert_eq!(find_line(&start, &game, 2), None);
+    }
+}
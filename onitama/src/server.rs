@@ -0,0 +1,383 @@
+use crate::cards::Card;
+use crate::colour::Colour;
+use crate::game::Game;
+use crate::messages::*;
+
+use rand::Rng;
+use std::collections::HashMap;
+use std::result::Result;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use websocket::server::NoTlsAcceptor;
+use websocket::sync::Server;
+use websocket::{Message, OwnedMessage};
+
+// A local server speaking the litama protocol (see messages.rs),
+// so matches can be played without the internet.
+// Commands are plain text and every answer is JSON with a messageType:
+//
+// create [username]                        -> create
+// join [match id] [username]               -> join, the spectators get the state
+// spectate [match id]                      -> spectate and the state
+// move [match id] [token] [card] [move]    -> move, the spectators get the state
+//                                             the move is written like `c1c3`
+// anything else or anything illegal        -> error
+//
+// The player who created the match has index 0 and a random colour.
+
+struct Match {
+    // by index
+    usernames: [String; 2],
+    tokens: [String; 2],
+    red_index: u8,
+    start: Game,
+    // written like `tiger c1c3`
+    moves: Vec<String>,
+    game: Game,
+    spectators: Vec<Sender<OwnedMessage>>,
+}
+
+type Matches = Arc<Mutex<HashMap<String, Match>>>;
+
+pub fn run(port: u16) -> Result<(), String> {
+    let server = Server::bind(("0.0.0.0", port)).map_err(|e| e.to_string())?;
+    println!("listening on ws://localhost:{}", port);
+    serve(server);
+    Ok(())
+}
+
+// serve every client on its own thread
+fn serve(server: Server<NoTlsAcceptor>) {
+    let matches = Matches::default();
+    for upgrade in server.filter_map(Result::ok) {
+        let client = match upgrade.accept() {
+            Ok(client) => client,
+            Err(_) => continue,
+        };
+        let (mut reader, mut writer) = match client.split() {
+            Ok(halves) => halves,
+            Err(_) => continue,
+        };
+        // the writer has its own thread so that other clients can send states to spectators
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for message in rx {
+                if writer.send_message(&message).is_err() {
+                    break;
+                }
+            }
+        });
+        let matches = Arc::clone(&matches);
+        thread::spawn(move || {
+            for message in reader.incoming_messages() {
+                match message {
+                    Ok(OwnedMessage::Text(text)) => handle(&text, &tx, &matches),
+                    Ok(OwnedMessage::Ping(data)) => {
+                        let _ = tx.send(OwnedMessage::Pong(data));
+                    }
+                    Ok(OwnedMessage::Close(_)) => {
+                        let _ = tx.send(OwnedMessage::Close(None));
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+        });
+    }
+}
+
+fn send(tx: &Sender<OwnedMessage>, msg: LitamaMessage) {
+    // the messages are plain structs, they always serialise
+    let json = serde_json::to_string(&msg).unwrap();
+    // a client that left doesn't need an answer
+    let _ = tx.send(Message::text(json).into());
+}
+
+fn random_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..24)
+        .map(|_| format!("{:x}", rng.gen_range(0, 16)))
+        .collect()
+}
+
+// answer a command, or answer with an error
+fn handle(text: &str, tx: &Sender<OwnedMessage>, matches: &Mutex<HashMap<String, Match>>) {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut matches = matches.lock().unwrap();
+    if let Err(error) = respond(&words, tx, &mut matches) {
+        let match_id = match words.first() {
+            Some(&"create") | None => "",
+            Some(_) => words.get(1).copied().unwrap_or(""),
+        };
+        send(
+            tx,
+            LitamaMessage::Error(ErrorMsg {
+                match_id: match_id.to_string(),
+                error,
+                query: text.to_string(),
+            }),
+        );
+    }
+}
+
+fn respond(
+    words: &[&str],
+    tx: &Sender<OwnedMessage>,
+    matches: &mut HashMap<String, Match>,
+) -> Result<(), String> {
+    match words {
+        ["create", username] => {
+            let match_id = random_id();
+            let token = random_id();
+            let game = Game::new();
+            let m = Match {
+                usernames: [username.to_string(), String::new()],
+                tokens: [token.clone(), String::new()],
+                red_index: rand::thread_rng().gen_range(0, 2),
+                start: game.clone(),
+                moves: Vec::new(),
+                game,
+                spectators: Vec::new(),
+            };
+            matches.insert(match_id.clone(), m);
+            send(
+                tx,
+                LitamaMessage::Create(CreateMsg {
+                    match_id,
+                    token,
+                    index: 0,
+                }),
+            );
+        }
+        ["join", match_id, username] => {
+            let m = find(matches, match_id)?;
+            if !m.tokens[1].is_empty() {
+                return Err("Match already has two players".to_string());
+            }
+            let token = random_id();
+            m.usernames[1] = username.to_string();
+            m.tokens[1] = token.clone();
+            send(
+                tx,
+                LitamaMessage::Join(JoinMsg {
+                    match_id: match_id.to_string(),
+                    token,
+                    index: 1,
+                }),
+            );
+            m.broadcast(match_id);
+        }
+        ["spectate", match_id] => {
+            let m = find(matches, match_id)?;
+            send(
+                tx,
+                LitamaMessage::Spectate(SpectateMsg {
+                    match_id: match_id.to_string(),
+                }),
+            );
+            send(tx, LitamaMessage::State(Box::new(m.state_msg(match_id))));
+            m.spectators.push(tx.clone());
+        }
+        ["move", match_id, token, card, positions] => {
+            let m = find(matches, match_id)?;
+            let index = m
+                .tokens
+                .iter()
+                .position(|t| !t.is_empty() && t == token)
+                .ok_or("Token is not valid for this match")?;
+            if m.tokens[1].is_empty() {
+                return Err("Waiting for the other player to join".to_string());
+            }
+            if !m.game.in_progress {
+                return Err("Game has ended".to_string());
+            }
+            if m.colour(index as u8) != m.game.colour {
+                return Err("It is not your turn".to_string());
+            }
+            let my_move = parse_move(&format!("{} {}", card, positions), &m.game)?;
+            m.game.validate_move(&my_move).map_err(|e| e.to_string())?;
+            m.moves.push(move_to_text(&my_move, &m.game));
+            m.game = m.game.take_turn(&my_move);
+            send(
+                tx,
+                LitamaMessage::Move(MoveMsg {
+                    match_id: match_id.to_string(),
+                }),
+            );
+            m.broadcast(match_id);
+        }
+        _ => return Err("Unrecognised command".to_string()),
+    }
+    Ok(())
+}
+
+fn find<'a>(
+    matches: &'a mut HashMap<String, Match>,
+    match_id: &str,
+) -> Result<&'a mut Match, String> {
+    matches
+        .get_mut(match_id)
+        .ok_or_else(|| format!("Match {} doesn't exist", match_id))
+}
+
+impl Match {
+    fn colour(&self, index: u8) -> Colour {
+        if index == self.red_index {
+            Colour::Red
+        } else {
+            Colour::Blue
+        }
+    }
+
+    fn state_msg(&self, match_id: &str) -> StateMsg {
+        let red_index = self.red_index as usize;
+        let cards = |game: &Game| {
+            let (red, blue) = game.get_red_blue();
+            let names = |cards: [Card; 2]| {
+                [
+                    cards[0].get_name().to_string(),
+                    cards[1].get_name().to_string(),
+                ]
+            };
+            CardsObj {
+                red: names(red.cards),
+                blue: names(blue.cards),
+                side: game.table_card.get_name().to_string(),
+            }
+        };
+        let (game_state, winner) = if self.tokens[1].is_empty() {
+            ("waiting for player", "none")
+        } else if self.game.in_progress {
+            ("in progress", "none")
+        } else {
+            // the side to move has lost
+            ("ended", self.game.colour.next().get_name())
+        };
+        StateMsg {
+            usernames: UsernamesObj {
+                red: self.usernames[red_index].clone(),
+                blue: self.usernames[1 - red_index].clone(),
+            },
+            indices: IndicesObj {
+                red: self.red_index,
+                blue: 1 - self.red_index,
+            },
+            match_id: match_id.to_string(),
+            current_turn: self.game.colour.get_name().to_string(),
+            cards: cards(&self.game),
+            starting_cards: cards(&self.start),
+            moves: self.moves.clone(),
+            board: self.game.to_board(),
+            game_state: game_state.to_string(),
+            winner: winner.to_string(),
+        }
+    }
+
+    // send the state to every spectator that is still there
+    fn broadcast(&mut self, match_id: &str) {
+        let json = serde_json::to_string(&LitamaMessage::State(Box::new(self.state_msg(match_id))))
+            .unwrap();
+        self.spectators
+            .retain(|tx| tx.send(Message::text(json.clone()).into()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+    use crate::record::GameRecord;
+    use std::sync::mpsc::Receiver;
+
+    fn start() -> String {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let address = format!("ws://{}", server.local_addr().unwrap());
+        thread::spawn(move || serve(server));
+        address
+    }
+
+    #[test]
+    fn test_play_match() {
+        let address = start();
+        let mut creator = Connection::new(&address).unwrap();
+        let mut joiner = Connection::new(&address).unwrap();
+        let (match_id, first) = creator.create_match("first").unwrap();
+        let second = joiner.join_match(&match_id, "second").unwrap();
+        let state_msg = creator.spectate(&match_id).unwrap();
+        joiner.spectate(&match_id).unwrap();
+        let red_index = state_msg.indices.red;
+        let red = if red_index == 0 { "first" } else { "second" };
+        assert_eq!(state_msg.usernames.red, red);
+        let mut game = Game::from_state_msg(&state_msg).unwrap();
+        let mut players = [(first, creator), (second, joiner)];
+        for _ in 0..30 {
+            if !game.in_progress {
+                break;
+            }
+            let i = players
+                .iter()
+                .position(|(p, _)| (p.index == red_index) == (game.colour == Colour::Red))
+                .unwrap();
+            let m = game.gen_moves()[0];
+            let (p, conn) = &mut players[i];
+            let state_msg = conn.make_move(&m, &match_id, &p.token, &game).unwrap();
+            game = game.take_turn(&m);
+            // both players see the same state
+            let other = players[1 - i].1.recv_state().unwrap();
+            assert_eq!(state_msg.board, other.board);
+            if !game.in_progress {
+                assert_eq!(state_msg.winner, game.colour.next().get_name());
+            }
+            assert_eq!(
+                Game::from_state_msg(&state_msg).unwrap().hash(),
+                game.hash()
+            );
+            // the move list replays to the same board
+            let mut record = GameRecord::new(&game, "first", "second");
+            assert_eq!(record.sync(&state_msg), Ok(true));
+            assert_eq!(
+                record.positions().unwrap().last().unwrap().hash(),
+                game.hash()
+            );
+        }
+    }
+
+    fn recv(rx: &Receiver<OwnedMessage>) -> LitamaMessage {
+        match rx.recv().unwrap() {
+            OwnedMessage::Text(text) => serde_json::from_str(&text).unwrap(),
+            msg => panic!("Expected text, got {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let matches = Mutex::new(HashMap::new());
+        let (tx, rx) = channel();
+        handle("create first", &tx, &matches);
+        let (match_id, token) = match recv(&rx) {
+            LitamaMessage::Create(msg) => (msg.match_id, msg.token),
+            msg => panic!("Expected create, got {:?}", msg),
+        };
+        let error = |text: &str| {
+            handle(text, &tx, &matches);
+            match recv(&rx) {
+                LitamaMessage::Error(msg) => msg.error,
+                msg => panic!("Expected an error for `{}`, got {:?}", text, msg),
+            }
+        };
+        assert_eq!(error("dance"), "Unrecognised command");
+        assert_eq!(error("join nope second"), "Match nope doesn't exist");
+        let mv = format!("move {} {} ox c1c2", match_id, token);
+        assert_eq!(error(&mv), "Waiting for the other player to join");
+        handle(&format!("join {} second", match_id), &tx, &matches);
+        assert!(matches!(recv(&rx), LitamaMessage::Join(_)));
+        assert_eq!(
+            error(&format!("join {} third", match_id)),
+            "Match already has two players"
+        );
+        let mv = format!("move {} wrong ox c1c2", match_id);
+        assert_eq!(error(&mv), "Token is not valid for this match");
+    }
+}
@@ -0,0 +1,204 @@
+use crate::cards::{mirror_bitmap, Card};
+use crate::game::{Game, Player};
+use crate::zobrist::SymmetricHashes;
+
+// Positions that play the same way, so that the tables can share their entries.
+//
+// Turning the board around and swapping the colours is always a symmetry, a card moves
+// blue's pieces like red's pieces turned around. Mirroring the board left to right is one
+// when every card of the game has a mirrored card (see Card::mirror), the cards are then
+// swapped for their mirrors. Every symmetry is its own inverse.
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Symmetry {
+    // turned around with the colours swapped
+    pub rotate: bool,
+    // mirrored left to right
+    pub mirror: bool,
+}
+
+// the first two are symmetries of every game
+pub const SYMMETRIES: [Symmetry; 4] = [
+    Symmetry {
+        rotate: false,
+        mirror: false,
+    },
+    Symmetry {
+        rotate: true,
+        mirror: false,
+    },
+    Symmetry {
+        rotate: false,
+        mirror: true,
+    },
+    Symmetry {
+        rotate: true,
+        mirror: true,
+    },
+];
+
+impl Symmetry {
+    // captured kings stay off the board
+    pub fn pos(self, pos: u8) -> u8 {
+        if pos >= 25 {
+            return pos;
+        }
+        let pos = if self.mirror {
+            pos - pos % 5 + 4 - pos % 5
+        } else {
+            pos
+        };
+        if self.rotate {
+            24 - pos
+        } else {
+            pos
+        }
+    }
+
+    pub fn bitmap(self, board: u32) -> u32 {
+        let board = if self.mirror {
+            mirror_bitmap(board)
+        } else {
+            board
+        };
+        if self.rotate {
+            board.reverse_bits() >> (32 - 25)
+        } else {
+            board
+        }
+    }
+
+    pub fn card(self, card: Card) -> Card {
+        if self.mirror {
+            card.mirror().unwrap_or(card)
+        } else {
+            card
+        }
+    }
+}
+
+impl Game {
+    pub fn can_mirror(&self) -> bool {
+        self.my
+            .cards
+            .iter()
+            .chain(self.other.cards.iter())
+            .chain(std::iter::once(&self.table_card))
+            .all(|card| card.mirror().is_some())
+    }
+
+    pub fn symmetries(&self) -> &'static [Symmetry] {
+        if self.can_mirror() {
+            &SYMMETRIES
+        } else {
+            &SYMMETRIES[..2]
+        }
+    }
+
+    // the player to move stays the player to move, with the other colour when rotated
+    pub fn transform(&self, s: Symmetry) -> Game {
+        let player = |p: &Player| Player {
+            cards: [s.card(p.cards[0]), s.card(p.cards[1])],
+            pieces: s.bitmap(p.pieces),
+            king: s.pos(p.king),
+        };
+        Game {
+            my: player(&self.my),
+            other: player(&self.other),
+            table_card: s.card(self.table_card),
+            colour: if s.rotate {
+                self.colour.next()
+            } else {
+                self.colour
+            },
+            in_progress: self.in_progress,
+            spirit: s.bitmap(self.spirit),
+        }
+    }
+
+    // the same for every symmetric position, with the symmetry that turns this position
+    // into the one that was hashed
+    pub fn canonical_hash(&self) -> (u64, Symmetry) {
+        SymmetricHashes::new(self, true).canonical()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cards::{add_card, fresh_cards};
+    use crate::colour::Colour;
+    use crate::game::Move;
+    use crate::messages::move_to_text;
+    use crate::transposition::StoredMove;
+
+    fn transform_move(m: &Move, s: Symmetry) -> Move {
+        Move {
+            from: s.pos(m.from),
+            to: s.pos(m.to),
+            used_left_card: m.used_left_card,
+            spirit: m.spirit.map(|pos| s.pos(pos)),
+        }
+    }
+
+    #[test]
+    fn test_transform() {
+        let game = Game::from_notation("1bB1b/2b2/1r1w1/R4/3rr r frog,ox eel,bat tiger").unwrap();
+        assert!(game.can_mirror());
+        assert_eq!(
+            game.transform(SYMMETRIES[1]).to_notation(),
+            "bb3/4B/1w1b1/2r2/r1Rr1 b eel,bat frog,ox tiger"
+        );
+        assert_eq!(
+            game.transform(SYMMETRIES[2]).to_notation(),
+            "b1Bb1/2b2/1w1r1/4R/rr3 r rabbit,horse cobra,bat tiger"
+        );
+        for &s in game.symmetries() {
+            let image = game.transform(s);
+            assert_eq!(image.transform(s).to_notation(), game.to_notation());
+            assert_eq!(image.canonical_hash().0, game.canonical_hash().0);
+            // the moves of the image are the moves of the position transformed
+            let moves: Vec<String> = game
+                .gen_moves()
+                .iter()
+                .map(|m| move_to_text(&transform_move(m, s), &image))
+                .collect();
+            let image_moves: Vec<String> = image
+                .gen_moves()
+                .iter()
+                .map(|m| move_to_text(m, &image))
+                .collect();
+            assert_eq!(moves.len(), image_moves.len());
+            assert!(moves.iter().all(|m| image_moves.contains(m)));
+            // and stored moves find their way back
+            for m in game.gen_moves() {
+                let stored = StoredMove::new(&m, &game).transform(s);
+                assert!(stored.matches(&transform_move(&m, s), &image));
+                assert!(stored.transform(s).matches(&m, &game));
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_mirror() {
+        // no card moves to the other side
+        let _cards = fresh_cards();
+        let card = add_card("onesided", 1 << 6 | 1 << 14, 0, Colour::Red).unwrap();
+        let mut cards = vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ];
+        let game = Game::from_cards(cards.clone());
+        assert_eq!(game.symmetries().len(), 4);
+        cards[0] = card;
+        let game = Game::from_cards(cards);
+        assert!(!game.can_mirror());
+        assert_eq!(game.symmetries().len(), 2);
+        let (hash, s) = game.canonical_hash();
+        assert!(!s.mirror);
+        assert_eq!(game.transform(s).hash(), hash);
+    }
+}
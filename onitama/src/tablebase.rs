@@ -0,0 +1,523 @@
+use crate::bot::Value;
+use crate::cards::{BitIter, Card, MAX_CARDS};
+use crate::cli::TablebaseArgs;
+use crate::colour::Colour;
+use crate::game::{Game, Player};
+use crate::symmetry::Symmetry;
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::result::Result;
+use std::sync::Arc;
+use std::thread;
+
+// An endgame tablebase for one set of five cards, covering every position
+// with at most `max_pieces` pieces per side, kings included.
+//
+// It is built by retrograde analysis: first every position that is won in one ply,
+// then every position where all moves lead to one of those, and so on.
+// Whatever is left when a pass finds nothing new can't be forced either way and is a draw.
+//
+// Each position takes one byte: n is a win in n plies when odd and a loss in n plies
+// when even, 0 is a draw and 255 is a position that can't happen. Only the positions with
+// red to move are stored, the others are turned around with the colours swapped
+// (see symmetry.rs). They are indexed by
+//
+//     (((deal * 25 + red king) * 25 + blue king) * sets + red pawns) * sets + blue pawns
+//
+// where deal is one of the 30 ways to split the cards between red, blue and the table
+// and a set of pawns is numbered by its size and then its combinatorial number.
+// The values are kept in memory, one byte per index and another bit while generating:
+// about 20KB for 1 piece per side, 13MB for 2 and 2.2GB for 3.
+//
+// The cards are kept in the order of their names, which unlike their numbers
+// doesn't depend on the order card files were loaded in. The file format is
+//
+//     ONITAMATB3       magic bytes, the tablebases with both sides to move had no number
+//                      and the ones with card numbers 2
+//     [max pieces]     one byte
+//     [cards]          the names of the five cards in alphabetical order, each ended by a newline
+//     [runs]           the values of the possible positions in the order of their index,
+//                      run length encoded as pairs of bytes [length] [value]
+
+const MAGIC: &[u8] = b"ONITAMATB3";
+// the index grows with the square of the number of pawn sets, see the memory above
+pub const MAX_PIECES: usize = 3;
+pub const DEFAULT_PIECES: usize = 2;
+const DRAW: u8 = 0;
+const INVALID: u8 = 255;
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+pub struct Tablebase {
+    // in order of their name
+    cards: [Card; 5],
+    max_pieces: usize,
+    // the position of each card in `cards`, by card number
+    slots: [Option<usize>; MAX_CARDS],
+    // the slots of the red cards, blue cards and table card
+    deals: Vec<([usize; 2], [usize; 2], usize)>,
+    // the deal by the masks of the red and blue slots
+    deal_index: Vec<Option<usize>>,
+    // the first index of the pawn sets of each size, the last is the number of sets
+    pawn_offsets: Vec<usize>,
+    values: Vec<u8>,
+}
+
+impl fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Tablebase {{ cards: {:?}, max_pieces: {} }}",
+            self.cards, self.max_pieces
+        )
+    }
+}
+
+impl Tablebase {
+    // the indexing without any values
+    fn layout(mut cards: [Card; 5], max_pieces: usize) -> Result<Tablebase, String> {
+        if max_pieces == 0 || max_pieces > MAX_PIECES {
+            return Err(format!(
+                "A tablebase has 1 to {} pieces per side, not {}",
+                MAX_PIECES, max_pieces
+            ));
+        }
+        cards.sort_by_key(|&card| card.get_name());
+        if cards.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err("The cards of a tablebase must be different".to_string());
+        }
+        let mut slots = [None; MAX_CARDS];
+        for (slot, &card) in cards.iter().enumerate() {
+            slots[card.index()] = Some(slot);
+        }
+        let mut deals = Vec::new();
+        let mut deal_index = vec![None; 1 << 10];
+        for red in 0..1usize << 5 {
+            for blue in 0..1usize << 5 {
+                if red.count_ones() != 2 || blue.count_ones() != 2 || red & blue != 0 {
+                    continue;
+                }
+                let slots_of = |mask: usize| {
+                    let mut slots = (0..5).filter(|&slot| mask & 1 << slot != 0);
+                    [slots.next().unwrap(), slots.next().unwrap()]
+                };
+                let table = (0..5).find(|&slot| (red | blue) & 1 << slot == 0).unwrap();
+                deal_index[red << 5 | blue] = Some(deals.len());
+                deals.push((slots_of(red), slots_of(blue), table));
+            }
+        }
+        let mut pawn_offsets = vec![0];
+        for pawns in 0..max_pieces {
+            pawn_offsets.push(pawn_offsets[pawns] + binomial(25, pawns));
+        }
+        Ok(Tablebase {
+            cards,
+            max_pieces,
+            slots,
+            deals,
+            deal_index,
+            pawn_offsets,
+            values: Vec::new(),
+        })
+    }
+
+    // every possible position is a draw until it is proven otherwise
+    fn empty(cards: [Card; 5], max_pieces: usize) -> Result<Tablebase, String> {
+        let mut tablebase = Tablebase::layout(cards, max_pieces)?;
+        tablebase.values = (0..tablebase.len())
+            .map(|i| match tablebase.position(i) {
+                Some(_) => DRAW,
+                None => INVALID,
+            })
+            .collect();
+        Ok(tablebase)
+    }
+
+    fn pawn_sets(&self) -> usize {
+        self.pawn_offsets[self.max_pieces]
+    }
+
+    fn len(&self) -> usize {
+        self.deals.len() * 25 * 25 * self.pawn_sets() * self.pawn_sets()
+    }
+
+    fn pawn_index(&self, pawns: u32) -> usize {
+        let mut rank = 0;
+        let mut pawns = pawns;
+        let mut i = 1;
+        while pawns != 0 {
+            rank += binomial(pawns.trailing_zeros() as usize, i);
+            pawns &= pawns - 1;
+            i += 1;
+        }
+        self.pawn_offsets[i - 1] + rank
+    }
+
+    fn pawns_from_index(&self, index: usize) -> u32 {
+        let size = self.pawn_offsets.iter().rposition(|&o| o <= index).unwrap();
+        let mut rank = index - self.pawn_offsets[size];
+        let mut pawns = 0;
+        for i in (1..=size).rev() {
+            let pos = (0..25).rev().find(|&pos| binomial(pos, i) <= rank).unwrap();
+            rank -= binomial(pos, i);
+            pawns |= 1 << pos;
+        }
+        pawns
+    }
+
+    fn index(&self, g: &Game) -> Option<usize> {
+        // only the base game is solved
+        if g.spirit != 0 {
+            return None;
+        }
+        let rotated;
+        let g = match g.colour {
+            Colour::Red => g,
+            Colour::Blue => {
+                rotated = g.transform(Symmetry {
+                    rotate: true,
+                    mirror: false,
+                });
+                &rotated
+            }
+        };
+        let (red, blue) = g.get_red_blue();
+        let mut masks = [0; 2];
+        for (mask, player) in masks.iter_mut().zip([red, blue].iter()) {
+            if player.king >= 25 || player.pieces.count_ones() as usize > self.max_pieces {
+                return None;
+            }
+            for &card in player.cards.iter() {
+                *mask |= 1 << self.slots[card.index()]?;
+            }
+        }
+        let deal = self.deal_index[masks[0] << 5 | masks[1]]?;
+        let sets = self.pawn_sets();
+        let red_pawns = self.pawn_index(red.pieces & !(1 << red.king));
+        let blue_pawns = self.pawn_index(blue.pieces & !(1 << blue.king));
+        Some(
+            (((deal * 25 + red.king as usize) * 25 + blue.king as usize) * sets + red_pawns) * sets
+                + blue_pawns,
+        )
+    }
+
+    // None for overlapping pieces and games that are already over
+    fn position(&self, index: usize) -> Option<Game> {
+        let sets = self.pawn_sets();
+        let (index, blue_pawns) = (index / sets, index % sets);
+        let (index, red_pawns) = (index / sets, index % sets);
+        let (index, blue_king) = (index / 25, (index % 25) as u8);
+        let (deal, red_king) = (index / 25, (index % 25) as u8);
+        let red_pawns = self.pawns_from_index(red_pawns);
+        let blue_pawns = self.pawns_from_index(blue_pawns);
+        let kings = 1 << red_king | 1 << blue_king;
+        if red_king == blue_king
+            || red_pawns & blue_pawns != 0
+            || (red_pawns | blue_pawns) & kings != 0
+        {
+            return None;
+        }
+        // a king on the other's home square has already won
+        if red_king == 2 || blue_king == 22 {
+            return None;
+        }
+        let (red_cards, blue_cards, table) = self.deals[deal];
+        let red = Player {
+            cards: [self.cards[red_cards[0]], self.cards[red_cards[1]]],
+            pieces: red_pawns | 1 << red_king,
+            king: red_king,
+        };
+        let blue = Player {
+            cards: [self.cards[blue_cards[0]], self.cards[blue_cards[1]]],
+            pieces: blue_pawns | 1 << blue_king,
+            king: blue_king,
+        };
+        Some(Game {
+            my: red,
+            other: blue,
+            table_card: self.cards[table],
+            colour: Colour::Red,
+            in_progress: true,
+            spirit: 0,
+        })
+    }
+
+    // the exact value for the player to move,
+    // None if the position isn't in the tablebase
+    pub fn probe(&self, g: &Game) -> Option<Value> {
+        if !g.in_progress {
+            return None;
+        }
+        match self.values[self.index(g)?] {
+            INVALID => None,
+            DRAW => Some(Value::Eval(0)),
+            n if n % 2 == 1 => Some(Value::Win(n)),
+            n => Some(Value::Loss(n)),
+        }
+    }
+
+    // whether an undecided position is won or lost in n plies
+    fn resolve(&self, g: &Game, n: u8) -> bool {
+        let mut children = g.gen_moves().into_iter().map(|m| g.take_turn(&m));
+        if n == 1 {
+            // capture the king or reach the goal
+            return children.any(|child| !child.in_progress);
+        }
+        let value = |child: &Game| self.values[self.index(child).unwrap()];
+        if n % 2 == 1 {
+            children.any(|child| value(&child) == n - 1)
+        } else {
+            // every child is a win found in an earlier pass, so the longest is in n - 1
+            children.all(|child| child.in_progress && value(&child) % 2 == 1)
+        }
+    }
+
+    pub fn generate(
+        cards: [Card; 5],
+        max_pieces: usize,
+        threads: usize,
+        mut report: impl FnMut(u8, usize),
+    ) -> Result<Tablebase, String> {
+        let threads = threads.max(1);
+        let mut tablebase = Arc::new(Tablebase::empty(cards, max_pieces)?);
+        for n in 1..INVALID {
+            // every thread looks at part of the positions and marks the new ones with a bit,
+            // then the new values are written at once
+            let len = tablebase.values.len();
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let tablebase = Arc::clone(&tablebase);
+                    let start = t * len / threads;
+                    let end = (t + 1) * len / threads;
+                    thread::spawn(move || {
+                        let mut found = vec![0u32; (end - start).div_ceil(32)];
+                        for i in start..end {
+                            if tablebase.values[i] != DRAW {
+                                continue;
+                            }
+                            let g = tablebase.position(i).unwrap();
+                            if tablebase.resolve(&g, n) {
+                                found[(i - start) / 32] |= 1 << ((i - start) % 32);
+                            }
+                        }
+                        (start, found)
+                    })
+                })
+                .collect();
+            let mut parts = Vec::new();
+            for handle in handles {
+                parts.push(
+                    handle
+                        .join()
+                        .map_err(|_| "A tablebase thread panicked".to_string())?,
+                );
+            }
+            let count: usize = parts
+                .iter()
+                .flat_map(|(_, found)| found.iter())
+                .map(|bits| bits.count_ones() as usize)
+                .sum();
+            report(n, count);
+            // nothing new in this pass means nothing new in the next one either
+            if count == 0 {
+                return Ok(Arc::try_unwrap(tablebase).unwrap());
+            }
+            let values = &mut Arc::get_mut(&mut tablebase).unwrap().values;
+            for (start, found) in parts {
+                for (word, &bits) in found.iter().enumerate() {
+                    for bit in BitIter(bits) {
+                        values[start + 32 * word + bit as usize] = n;
+                    }
+                }
+            }
+        }
+        Err(format!(
+            "Some positions take longer than {} plies",
+            INVALID - 1
+        ))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(self.max_pieces as u8);
+        for card in self.cards.iter() {
+            bytes.extend(card.get_name().as_bytes());
+            bytes.push(b'\n');
+        }
+        // the impossible positions are left out, they are known from the index
+        let mut values = self.values.iter().filter(|&&v| v != INVALID).peekable();
+        while let Some(&value) = values.next() {
+            let mut length = 1u8;
+            while length < u8::MAX && values.peek() == Some(&&value) {
+                values.next();
+                length += 1;
+            }
+            bytes.push(length);
+            bytes.push(value);
+        }
+        fs::write(path, bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Tablebase, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let error = |message: &str| format!("{}: {}", path.display(), message);
+        if bytes.len() <= MAGIC.len() || !bytes.starts_with(MAGIC) {
+            return Err(error("not a tablebase"));
+        }
+        let max_pieces = bytes[MAGIC.len()] as usize;
+        let mut rest = &bytes[MAGIC.len() + 1..];
+        let mut cards = [Card::Boar; 5];
+        for card in cards.iter_mut() {
+            let end = rest
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or_else(|| error("truncated"))?;
+            let name = std::str::from_utf8(&rest[..end]).map_err(|_| error("invalid card name"))?;
+            // the cards of card files have to be loaded with --cards first
+            *card = Card::from_text(name).map_err(|e| error(&e))?;
+            rest = &rest[end + 1..];
+        }
+        let mut tablebase = Tablebase::empty(cards, max_pieces).map_err(|e| error(&e))?;
+        let runs = rest;
+        if runs.len() % 2 != 0 {
+            return Err(error("truncated"));
+        }
+        let mut values = runs
+            .chunks(2)
+            .flat_map(|run| std::iter::repeat_n(run[1], run[0] as usize));
+        for slot in tablebase.values.iter_mut().filter(|v| **v != INVALID) {
+            *slot = match values.next() {
+                Some(INVALID) => return Err(error("invalid value")),
+                Some(value) => value,
+                None => return Err(error("truncated")),
+            };
+        }
+        if values.next().is_some() {
+            return Err(error("too many values"));
+        }
+        Ok(tablebase)
+    }
+}
+
+pub fn run(args: TablebaseArgs) -> Result<(), String> {
+    let names: Vec<_> = args.cards.iter().map(|card| card.get_name()).collect();
+    let len = Tablebase::layout(args.cards, args.max_pieces)?.len();
+    println!(
+        "generating up to {} pieces per side for {}, this needs about {}MB of memory",
+        args.max_pieces,
+        names.join(" "),
+        (len + len / 8).div_ceil(1 << 20)
+    );
+    let tablebase = Tablebase::generate(args.cards, args.max_pieces, args.threads, |n, found| {
+        println!(
+            "{} in {}: {}",
+            if n % 2 == 1 { "win" } else { "loss" },
+            n,
+            found
+        )
+    })?;
+    tablebase.save(&args.path)?;
+    println!("saved to {}", args.path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::Searcher;
+
+    const CARDS: [Card; 5] = [Card::Tiger, Card::Crab, Card::Boar, Card::Ox, Card::Mantis];
+
+    #[test]
+    fn test_pawn_index() {
+        let tablebase = Tablebase::layout(CARDS, 3).unwrap();
+        assert_eq!(tablebase.pawn_sets(), 1 + 25 + 300);
+        for index in 0..tablebase.pawn_sets() {
+            let pawns = tablebase.pawns_from_index(index);
+            assert!(pawns.count_ones() < 3);
+            assert_eq!(tablebase.pawn_index(pawns), index);
+        }
+    }
+
+    #[test]
+    fn test_index() {
+        let tablebase = Tablebase::layout(CARDS, 2).unwrap();
+        for i in (0..tablebase.len()).step_by(997) {
+            if let Some(g) = tablebase.position(i) {
+                assert_eq!(tablebase.index(&g), Some(i));
+            }
+        }
+        // too many pieces
+        assert_eq!(tablebase.index(&Game::from_cards(CARDS.to_vec())), None);
+        // blue to move shares the index of red to move turned around
+        let g = (tablebase.len() / 3..)
+            .find_map(|i| tablebase.position(i))
+            .unwrap();
+        let rotated = g.transform(Symmetry {
+            rotate: true,
+            mirror: false,
+        });
+        assert_eq!(rotated.colour, Colour::Blue);
+        assert_eq!(tablebase.index(&rotated), tablebase.index(&g));
+    }
+
+    #[test]
+    fn test_kings_match_search() {
+        let tablebase = Tablebase::generate(CARDS, 1, 2, |_, _| {}).unwrap();
+        let mut decided = 0;
+        for i in (0..tablebase.len()).step_by(101) {
+            let g = match tablebase.position(i) {
+                Some(g) => g,
+                None => continue,
+            };
+            let value = tablebase.probe(&g).unwrap();
+            let searched = Searcher::new().search(&g, 5, None).0;
+            match value {
+                Value::Win(n) | Value::Loss(n) if n <= 5 => {
+                    assert_eq!(searched, value, "{}", g.to_notation());
+                    decided += 1;
+                }
+                _ => assert!(matches!(searched, Value::Eval(_)), "{}", g.to_notation()),
+            }
+        }
+        assert!(decided > 0);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let tablebase = Tablebase::generate(CARDS, 1, 1, |_, _| {}).unwrap();
+        let path = std::env::temp_dir().join(format!("onitama-test-{}.tb", std::process::id()));
+        tablebase.save(&path).unwrap();
+        let loaded = Tablebase::load(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        // the cards are saved by name
+        assert!(bytes[MAGIC.len() + 1..].starts_with(b"boar\ncrab\nmantis\nox\ntiger\n"));
+        assert_eq!(loaded.cards, tablebase.cards);
+        assert_eq!(loaded.max_pieces, 1);
+        assert!(loaded.values == tablebase.values);
+    }
+
+    #[test]
+    fn test_search_uses_probe() {
+        let tablebase = Arc::new(Tablebase::generate(CARDS, 1, 1, |_, _| {}).unwrap());
+        let (g, value) = (0..tablebase.len())
+            .filter_map(|i| tablebase.position(i))
+            .filter_map(|g| match tablebase.probe(&g) {
+                Some(Value::Win(n)) if n > 5 => Some((g, Value::Win(n))),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        let mut searcher = Searcher::new();
+        searcher.tablebase = Some(tablebase);
+        assert_eq!(searcher.search(&g, 2, None).0, value);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::analysis::describe;
+use crate::game::Game;
+use crate::messages::parse_move;
+use crate::{Action, Transmission};
+
+use std::io::{self, BufRead, Write};
+use std::result::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+// text front end for when there is no display, mirrors gui::run
+pub fn run(
+    tx: Sender<Action>,
+    rx: Receiver<Transmission>,
+    should_end: &Arc<AtomicBool>,
+) -> Result<(), String> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut game: Option<Game> = None;
+    loop {
+        // early exit
+        if should_end.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let trans = match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(trans) => trans,
+            Err(RecvTimeoutError::Timeout) => continue,
+            // game finished
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match trans {
+            Transmission::Display(g) => {
+                println!("\n{}", g);
+                game = Some(g);
+            }
+            Transmission::Usernames(red, blue) => println!("Red: {}\nBlue: {}", red, blue),
+            Transmission::Winner(text) => println!("{}", text),
+            Transmission::Refused(reason) => println!("Illegal move: {}", reason),
+            Transmission::Status(text) => println!("{}", text),
+            // every position is printed anyway
+            Transmission::MoveList(..) | Transmission::LastMove(_) => {}
+            Transmission::Analysis(analysis) => {
+                if let Some(current) = &game {
+                    for line in describe(current, &analysis) {
+                        println!("{}", line);
+                    }
+                }
+            }
+            Transmission::RequestMove => {
+                let current = game
+                    .as_ref()
+                    .ok_or("Move requested before the game started")?;
+                let action = loop {
+                    print!("Enter your move (e.g. tiger c1c3), undo, redo or a to analyse: ");
+                    io::stdout().flush().map_err(|e| e.to_string())?;
+                    let line = match lines.next() {
+                        Some(line) => line.map_err(|e| e.to_string())?,
+                        // stdin closed
+                        None => return Ok(()),
+                    };
+                    match line.trim() {
+                        "undo" => break Action::Back,
+                        "redo" => break Action::Forward,
+                        "a" => break Action::Analyse,
+                        _ => {}
+                    }
+                    match parse_move(&line, current) {
+                        Ok(m) => match current.validate_move(&m) {
+                            Ok(()) => break Action::Move(m),
+                            Err(err) => println!("{}", err),
+                        },
+                        Err(err) => println!("{}", err),
+                    }
+                };
+                tx.send(action).map_err(|e| e.to_string())?;
+            }
+            Transmission::RequestStep => {
+                print!("Enter = next, b = back, a = analyse, q = quit: ");
+                io::stdout().flush().map_err(|e| e.to_string())?;
+                let line = match lines.next() {
+                    Some(line) => line.map_err(|e| e.to_string())?,
+                    None => return Ok(()),
+                };
+                let action = match line.trim() {
+                    "b" => Action::Back,
+                    "a" => Action::Analyse,
+                    "q" => return Ok(()),
+                    _ => Action::Forward,
+                };
+                tx.send(action).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,341 @@
+use crate::arena::{expected_score, play_game, Outcome, Score};
+use crate::cards::draw_cards_with;
+use crate::cli::TournamentArgs;
+use crate::colour::Colour;
+use crate::config::EngineConfig;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use std::fs;
+use std::result::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+
+// Plays several engine configurations (see config.rs) against each other,
+// every engine against every other in a round robin or the first against the rest in a gauntlet.
+// The openings are random sets of cards dealt once at the start from a seed, every pairing
+// plays each of them twice so both engines play both sides of the same cards.
+// At the end it prints the standings with elo ratings and a crosstable of the points scored,
+// the games can be written to a file in the format of GameRecord::to_pgn.
+
+// ratings can't be fitted for an engine that won or lost every game, they stop here
+const MAX_RATING: f64 = 1000.;
+
+// the pairs of engines that play each other
+fn pairings(engines: usize, gauntlet: bool) -> Vec<(usize, usize)> {
+    if gauntlet {
+        (1..engines).map(|j| (0, j)).collect()
+    } else {
+        (0..engines)
+            .flat_map(|i| (i + 1..engines).map(move |j| (i, j)))
+            .collect()
+    }
+}
+
+fn points(score: &Score) -> f64 {
+    score.wins as f64 + score.draws as f64 / 2.
+}
+
+// elo ratings that fit the scores of every pair best, averaging 0,
+// scores[i][j] is the score of engine i against engine j
+fn ratings(scores: &[Vec<Score>]) -> Vec<f64> {
+    let n = scores.len();
+    let mut ratings = vec![0.; n];
+    // gradient ascent on the likelihood of the results
+    for _ in 0..10_000 {
+        let mut change = 0f64;
+        for i in 0..n {
+            let games: u32 = scores[i].iter().map(Score::games).sum();
+            if games == 0 {
+                continue;
+            }
+            let (mut actual, mut expected) = (0., 0.);
+            for j in 0..n {
+                actual += points(&scores[i][j]);
+                expected += scores[i][j].games() as f64 * expected_score(ratings[i] - ratings[j]);
+            }
+            let step = 400. * (actual - expected) / games as f64;
+            let rating = (ratings[i] + step).clamp(-MAX_RATING, MAX_RATING);
+            change = change.max((rating - ratings[i]).abs());
+            ratings[i] = rating;
+        }
+        if change < 1e-6 {
+            break;
+        }
+    }
+    let mean = ratings.iter().sum::<f64>() / n as f64;
+    ratings.iter().map(|r| r - mean).collect()
+}
+
+// the standings, best first, and a crosstable with the points each engine scored against each other
+fn summary(names: &[String], scores: &[Vec<Score>]) -> Vec<String> {
+    let ratings = ratings(scores);
+    let mut order: Vec<usize> = (0..names.len()).collect();
+    order.sort_by(|&a, &b| ratings[b].partial_cmp(&ratings[a]).unwrap());
+    let width = names.iter().map(String::len).max().unwrap_or(0).max(4);
+    // the crosstable columns are in the same order as the rows
+    let columns: Vec<String> = (1..=names.len()).map(|r| format!("{:>7}", r)).collect();
+    let mut lines = vec![format!(
+        "{:>2}  {:<width$}  {:>7}  {:>10}  {:>7}  {}",
+        "#",
+        "name",
+        "elo",
+        "points",
+        "score",
+        columns.join(" "),
+        width = width
+    )];
+    for (rank, &i) in order.iter().enumerate() {
+        let total = scores[i].iter().fold(Score::default(), |total, s| Score {
+            wins: total.wins + s.wins,
+            losses: total.losses + s.losses,
+            draws: total.draws + s.draws,
+        });
+        let table: Vec<String> = order
+            .iter()
+            .map(|&j| match scores[i][j].games() {
+                _ if i == j => format!("{:>7}", "-"),
+                0 => format!("{:>7}", ""),
+                games => format!("{:>7}", format!("{}/{}", points(&scores[i][j]), games)),
+            })
+            .collect();
+        let score = if total.games() == 0 {
+            0.
+        } else {
+            100. * points(&total) / total.games() as f64
+        };
+        lines.push(format!(
+            "{:>2}  {:<width$}  {:>+7.1}  {:>10}  {:>6.1}%  {}",
+            rank + 1,
+            names[i],
+            ratings[i],
+            format!("{}/{}", points(&total), total.games()),
+            score,
+            table.join(" "),
+            width = width
+        ));
+    }
+    lines
+}
+
+pub fn run(args: TournamentArgs) -> Result<(), String> {
+    let engines = args
+        .configs
+        .iter()
+        .map(|path| EngineConfig::load(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let names: Vec<String> = engines.iter().map(|e| e.name.clone()).collect();
+    // printed so that the same openings can be played again
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!("seed {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let openings: Vec<_> = (0..args.openings)
+        .map(|_| draw_cards_with(&mut rng))
+        .collect();
+    // red, blue and the opening of every game
+    let games: Vec<(usize, usize, usize)> = pairings(engines.len(), args.gauntlet)
+        .into_iter()
+        .flat_map(|(a, b)| (0..args.openings).flat_map(move |o| vec![(a, b, o), (b, a, o)]))
+        .collect();
+    println!(
+        "{} engines, {} games on {} threads",
+        engines.len(),
+        games.len(),
+        args.threads
+    );
+
+    let engines = Arc::new(engines);
+    let openings = Arc::new(openings);
+    let games = Arc::new(games);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = channel();
+    let handles: Vec<_> = (0..args.threads)
+        .map(|_| {
+            let (engines, openings, games, next) = (
+                Arc::clone(&engines),
+                Arc::clone(&openings),
+                Arc::clone(&games),
+                Arc::clone(&next),
+            );
+            let tx = tx.clone();
+            thread::spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= games.len() {
+                    break;
+                }
+                let (red, blue, opening) = games[i];
+                let record = play_game([&engines[red], &engines[blue]], openings[opening].clone());
+                if tx.send((i, record)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let n = engines.len();
+    let mut scores = vec![vec![Score::default(); n]; n];
+    let mut records = Vec::new();
+    for (done, (i, record)) in rx.iter().enumerate() {
+        let (red, blue, _) = games[i];
+        let outcome = match record.winner {
+            Some(Colour::Red) => Outcome::Win,
+            Some(Colour::Blue) => Outcome::Loss,
+            None => Outcome::Draw,
+        };
+        scores[red][blue].add(outcome);
+        scores[blue][red].add(match outcome {
+            Outcome::Win => Outcome::Loss,
+            Outcome::Loss => Outcome::Win,
+            Outcome::Draw => Outcome::Draw,
+        });
+        println!(
+            "game {}/{}: {} - {} {}",
+            done + 1,
+            games.len(),
+            names[red],
+            names[blue],
+            record.result()
+        );
+        records.push((i, record));
+    }
+    for handle in handles {
+        handle
+            .join()
+            .map_err(|_| "A tournament thread panicked".to_string())?;
+    }
+
+    println!();
+    for line in summary(&names, &scores) {
+        println!("{}", line);
+    }
+    if let Some(path) = &args.pgn {
+        records.sort_by_key(|(i, _)| *i);
+        let text: Vec<String> = records
+            .iter()
+            .map(|(i, record)| {
+                record.to_pgn(&[
+                    ("Event", "tournament".to_string()),
+                    ("Round", (i + 1).to_string()),
+                    ("Opening", (games[*i].2 + 1).to_string()),
+                ])
+            })
+            .collect();
+        fs::write(path, text.join("\n")).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!("saved the games to {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::elo_from_score;
+
+    #[test]
+    fn test_pairings() {
+        assert_eq!(pairings(3, false), vec![(0, 1), (0, 2), (1, 2)]);
+        assert_eq!(pairings(3, true), vec![(0, 1), (0, 2)]);
+        assert_eq!(pairings(5, false).len(), 10);
+    }
+
+    #[test]
+    fn test_ratings() {
+        let score = |wins, losses, draws| Score {
+            wins,
+            losses,
+            draws,
+        };
+        // two engines get the same difference as the arena gives
+        let scores = vec![
+            vec![Score::default(), score(75, 25, 0)],
+            vec![score(25, 75, 0), Score::default()],
+        ];
+        let r = ratings(&scores);
+        assert!((r[0] - r[1] - elo_from_score(0.75)).abs() < 0.01);
+        assert!((r[0] + r[1]).abs() < 1e-9);
+
+        // a beats b beats c, and a never played c
+        let scores = vec![
+            vec![Score::default(), score(6, 4, 0), Score::default()],
+            vec![score(4, 6, 0), Score::default(), score(6, 4, 0)],
+            vec![Score::default(), score(4, 6, 0), Score::default()],
+        ];
+        let r = ratings(&scores);
+        assert!(r[0] > r[1] && r[1] > r[2]);
+        let names = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let lines = summary(&names, &scores);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].contains(" a ") && lines[3].contains(" c "));
+        assert!(lines[2].contains("4/10") && lines[2].contains("6/10"));
+
+        // an engine that won everything stays finite
+        let scores = vec![
+            vec![Score::default(), score(10, 0, 0)],
+            vec![score(0, 10, 0), Score::default()],
+        ];
+        let r = ratings(&scores);
+        assert!(r[0].is_finite() && r[0] > 0.);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_forfeit() {
+        use crate::cards::Card;
+        // an external engine that answers every position with a card it doesn't have
+        let broken = EngineConfig {
+            name: "broken".to_string(),
+            command: Some(
+                [
+                    "sh",
+                    "-c",
+                    "while read line; do case $line in onitama) echo onitamaok;; \
+                     go*) echo bestmove dragon c1c2;; quit) exit;; esac; done",
+                ]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            ),
+            ..EngineConfig::default()
+        };
+        let fast = EngineConfig {
+            depth: Some(1),
+            ..EngineConfig::default()
+        };
+        let cards = vec![
+            Card::Ox,
+            Card::Boar,
+            Card::Horse,
+            Card::Elephant,
+            Card::Crab,
+        ];
+        // blue moves first with these cards
+        let record = play_game([&fast, &broken], cards.clone());
+        assert_eq!(record.winner, Some(Colour::Red));
+        assert!(record.moves.is_empty());
+        let record = play_game([&broken, &fast], cards.clone());
+        assert_eq!(record.winner, Some(Colour::Blue));
+        assert_eq!(record.moves.len(), 1);
+        // one that never answers runs out of time
+        let silent = EngineConfig {
+            name: "silent".to_string(),
+            command: Some(
+                [
+                    "sh",
+                    "-c",
+                    "while read line; do case $line in onitama) echo onitamaok;; esac; done",
+                ]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            ),
+            timeout: 0.2,
+            ..EngineConfig::default()
+        };
+        let record = play_game([&fast, &silent], cards);
+        assert_eq!(record.winner, Some(Colour::Red));
+    }
+}
@@ -0,0 +1,161 @@
+use rand::Rng;
+
+use crate::arena::MAX_PLIES;
+use crate::bot::Value;
+use crate::cards::draw_cards;
+use crate::cli::ExportArgs;
+use crate::config::EngineConfig;
+use crate::game::Game;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::result::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::thread;
+
+// Writes positions from self-play games for training a network offline, one per line:
+//
+//     bbBbb/5/1r3/5/r1Rrr b elephant,horse boar,ox crab | -12 | 1
+//
+// - the position in the position notation
+// - the score of the search, for the player to move,
+//   a win in n plies is WIN_SCORE - n and a loss in n plies is n - WIN_SCORE
+// - the result of the game for the player to move, 1 for a win, 0 for a draw and -1 for a loss
+// Each game starts with a few random moves so that the games are different.
+
+const RANDOM_PLIES: usize = 4;
+const WIN_SCORE: i64 = 10_000;
+
+fn score(value: Value) -> i64 {
+    match value {
+        Value::Win(n) => WIN_SCORE - n as i64,
+        Value::Loss(n) => n as i64 - WIN_SCORE,
+        Value::Eval(eval) => eval,
+    }
+}
+
+fn random_opening() -> Game {
+    let mut rng = rand::thread_rng();
+    loop {
+        let mut game = Game::from_cards(draw_cards());
+        for _ in 0..RANDOM_PLIES {
+            if !game.in_progress {
+                break;
+            }
+            let moves = game.gen_moves();
+            game = game.take_turn(&moves[rng.gen_range(0, moves.len())]);
+        }
+        if game.in_progress {
+            return game;
+        }
+    }
+}
+
+// the lines for every searched position of one game
+fn play_game(config: &EngineConfig) -> Vec<String> {
+    let mut searcher = config.searcher();
+    let mut game = random_opening();
+    let mut positions = Vec::new();
+    while game.in_progress && positions.len() < MAX_PLIES {
+        let (value, m) = config.search(&mut searcher, &game);
+        positions.push((game.to_notation(), game.colour, score(value)));
+        game = game.take_turn(&m);
+    }
+    // the loser is the one left to move
+    let winner = if game.in_progress {
+        None
+    } else {
+        Some(game.colour.next())
+    };
+    positions
+        .into_iter()
+        .map(|(notation, colour, score)| {
+            let result = match winner {
+                None => 0,
+                Some(winner) if winner == colour => 1,
+                Some(_) => -1,
+            };
+            format!("{} | {} | {}", notation, score, result)
+        })
+        .collect()
+}
+
+pub fn run(args: ExportArgs) -> Result<(), String> {
+    let config = match &args.config {
+        Some(path) => EngineConfig::load(path)?,
+        None => EngineConfig::default(),
+    };
+    let file = File::create(&args.path).map_err(|e| format!("{}: {}", args.path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+
+    let config = Arc::new(config);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = channel();
+    let handles: Vec<_> = (0..args.threads)
+        .map(|_| {
+            let config = Arc::clone(&config);
+            let next = Arc::clone(&next);
+            let tx = tx.clone();
+            let games = args.games;
+            thread::spawn(move || {
+                while next.fetch_add(1, Ordering::Relaxed) < games {
+                    if tx.send(play_game(&config)).is_err() {
+                        return;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let mut total = 0;
+    for (i, lines) in rx.iter().enumerate() {
+        for line in lines.iter() {
+            writeln!(writer, "{}", line).map_err(|e| e.to_string())?;
+        }
+        total += lines.len();
+        println!(
+            "game {}: {} positions, {} in total",
+            i + 1,
+            lines.len(),
+            total
+        );
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    for handle in handles {
+        handle
+            .join()
+            .map_err(|_| "An export thread panicked".to_string())?;
+    }
+    println!("wrote {} positions to {}", total, args.path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_play_game() {
+        let config = EngineConfig {
+            depth: Some(2),
+            ..EngineConfig::default()
+        };
+        let lines = play_game(&config);
+        assert!(!lines.is_empty());
+        for line in lines.iter() {
+            let fields: Vec<&str> = line.split(" | ").collect();
+            assert_eq!(fields.len(), 3);
+            assert!(Game::from_notation(fields[0]).unwrap().in_progress);
+            fields[1].parse::<i64>().unwrap();
+            assert!(["1", "0", "-1"].contains(&fields[2]));
+        }
+        // the results alternate with the player to move
+        let result = |line: &String| line.rsplit(" | ").next().unwrap().to_string();
+        if lines.len() > 1 && result(&lines[0]) != "0" {
+            assert_ne!(result(&lines[0]), result(&lines[1]));
+        }
+    }
+}
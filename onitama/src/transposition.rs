@@ -0,0 +1,124 @@
+use crate::bot::Value;
+use crate::cards::Card;
+use crate::game::{Game, Move};
+use crate::symmetry::Symmetry;
+
+use std::sync::Mutex;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+// the best move is stored by card rather than by hand index,
+// because the same position can be reached with the hand in either order
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StoredMove {
+    pub from: u8,
+    pub to: u8,
+    pub card: Card,
+    pub spirit: Option<u8>,
+}
+
+impl StoredMove {
+    pub fn new(m: &Move, g: &Game) -> StoredMove {
+        StoredMove {
+            from: m.from,
+            to: m.to,
+            card: g.my.cards[!m.used_left_card as usize],
+            spirit: m.spirit,
+        }
+    }
+
+    // the move in the transformed position, or back again
+    pub fn transform(self, s: Symmetry) -> StoredMove {
+        StoredMove {
+            from: s.pos(self.from),
+            to: s.pos(self.to),
+            card: s.card(self.card),
+            spirit: self.spirit.map(|pos| s.pos(pos)),
+        }
+    }
+
+    pub fn matches(&self, m: &Move, g: &Game) -> bool {
+        self.from == m.from
+            && self.to == m.to
+            && self.card == g.my.cards[!m.used_left_card as usize]
+            && self.spirit == m.spirit
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Entry {
+    pub hash: u64,
+    pub depth: u8,
+    pub bound: Bound,
+    pub value: Value,
+    pub best_move: Option<StoredMove>,
+}
+
+// shared by the threads of a search, every slot has its own lock so they rarely wait
+pub struct TranspositionTable {
+    entries: Vec<Mutex<Option<Entry>>>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    // size is rounded down to a power of two so that indexing is a mask
+    pub fn new(size: usize) -> TranspositionTable {
+        let size = if size.is_power_of_two() {
+            size
+        } else {
+            (size.next_power_of_two() / 2).max(1)
+        };
+        TranspositionTable {
+            entries: (0..size).map(|_| Mutex::new(None)).collect(),
+            mask: size - 1,
+        }
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<Entry> {
+        let slot = self.entries[hash as usize & self.mask].lock().unwrap();
+        slot.filter(|entry| entry.hash == hash)
+    }
+
+    // always replace, unless it would overwrite a deeper search of the same position
+    pub fn store(&self, entry: Entry) {
+        let mut slot = self.entries[entry.hash as usize & self.mask]
+            .lock()
+            .unwrap();
+        if let Some(old) = *slot {
+            if old.hash == entry.hash && old.depth > entry.depth {
+                return;
+            }
+        }
+        *slot = Some(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_probe() {
+        let tt = TranspositionTable::new(1000);
+        assert_eq!(tt.entries.len(), 512);
+        let entry = Entry {
+            hash: 0x1234_5678,
+            depth: 3,
+            bound: Bound::Exact,
+            value: Value::Eval(5),
+            best_move: None,
+        };
+        tt.store(entry);
+        assert_eq!(tt.probe(0x1234_5678).unwrap().value, Value::Eval(5));
+        // same slot, different position
+        assert!(tt.probe(0x1234_5678 + 512).is_none());
+        // shallower search of the same position is ignored
+        tt.store(Entry { depth: 1, ..entry });
+        assert_eq!(tt.probe(0x1234_5678).unwrap().depth, 3);
+    }
+}
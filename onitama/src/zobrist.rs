@@ -0,0 +1,247 @@
+use array_const_fn_init::array_const_fn_init;
+
+use crate::cards::{BitIter, Card, BASE_CARDS, MAX_CARDS};
+use crate::colour::Colour;
+use crate::game::Game;
+use crate::symmetry::{Symmetry, SYMMETRIES};
+
+// layout of the key table
+const RED_PIECES: usize = 0;
+const BLUE_PIECES: usize = 25;
+const RED_KING: usize = 50;
+const BLUE_KING: usize = 75;
+const RED_CARDS: usize = 100;
+const BLUE_CARDS: usize = 116;
+const TABLE_CARD: usize = 132;
+const BLUE_TO_MOVE: usize = 148;
+// the other cards came later, their red, blue and table keys are next to each other
+// so that the hashes of the base cards didn't change
+const MORE_CARDS: usize = 149;
+const SPIRIT: usize = MORE_CARDS + 3 * (MAX_CARDS - BASE_CARDS);
+const NUM_KEYS: usize = SPIRIT + 25;
+
+// splitmix64 so that the keys can be generated at compile time
+const fn zobrist_key(index: usize) -> u64 {
+    let mut z = (index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// the macro needs a literal length
+const KEYS: [u64; NUM_KEYS] = array_const_fn_init![zobrist_key; 318];
+const _: () = assert!(NUM_KEYS == 318);
+
+fn card_key(offset: usize, card: Card) -> u64 {
+    match card.index() {
+        index if index < BASE_CARDS => KEYS[offset + index],
+        index => KEYS[MORE_CARDS + 3 * (index - BASE_CARDS) + (offset - RED_CARDS) / BASE_CARDS],
+    }
+}
+
+impl Game {
+    // the order of cards in a hand does not matter, so they are hashed as a set
+    pub fn hash(&self) -> u64 {
+        let (red, blue) = self.get_red_blue();
+        let mut hash = 0;
+        for pos in BitIter(red.pieces) {
+            hash ^= KEYS[RED_PIECES + pos as usize];
+        }
+        for pos in BitIter(blue.pieces) {
+            hash ^= KEYS[BLUE_PIECES + pos as usize];
+        }
+        // captured kings are moved off the board
+        if red.king < 25 {
+            hash ^= KEYS[RED_KING + red.king as usize];
+        }
+        if blue.king < 25 {
+            hash ^= KEYS[BLUE_KING + blue.king as usize];
+        }
+        for &card in red.cards.iter() {
+            hash ^= card_key(RED_CARDS, card);
+        }
+        for &card in blue.cards.iter() {
+            hash ^= card_key(BLUE_CARDS, card);
+        }
+        hash ^= card_key(TABLE_CARD, self.table_card);
+        for pos in BitIter(self.spirit) {
+            hash ^= KEYS[SPIRIT + pos as usize];
+        }
+        if self.colour == Colour::Blue {
+            hash ^= KEYS[BLUE_TO_MOVE];
+        }
+        hash
+    }
+}
+
+// The hashes of a position seen through its symmetries (see symmetry.rs) without transforming it,
+// so that the search can share the entries of symmetric positions. A hash is the xor of
+// the keys of its parts, after a move only the keys of the parts that changed are xor-ed in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SymmetricHashes {
+    // hashes[i] is the hash of the position transformed by SYMMETRIES[i]
+    hashes: [u64; 4],
+    // only the first two when the mirrored positions aren't shared
+    count: usize,
+}
+
+// the keys seen through the symmetry: the squares move, the cards are mirrored
+// and red and blue swap when it is rotated
+fn piece_keys(s: Symmetry, red: bool, pieces: u32) -> u64 {
+    let offset = if red != s.rotate {
+        RED_PIECES
+    } else {
+        BLUE_PIECES
+    };
+    BitIter(pieces).fold(0, |hash, pos| {
+        hash ^ KEYS[offset + s.pos(pos as u8) as usize]
+    })
+}
+
+fn spirit_keys(s: Symmetry, spirit: u32) -> u64 {
+    BitIter(spirit).fold(0, |hash, pos| {
+        hash ^ KEYS[SPIRIT + s.pos(pos as u8) as usize]
+    })
+}
+
+// everything but the pieces and the spirit, which are xor-ed in as bitmaps
+fn other_keys(s: Symmetry, g: &Game) -> u64 {
+    let (red, blue) = g.get_red_blue();
+    let mut hash = card_key(TABLE_CARD, s.card(g.table_card));
+    for (player, is_red) in [(red, true), (blue, false)] {
+        let (king, cards) = if is_red != s.rotate {
+            (RED_KING, RED_CARDS)
+        } else {
+            (BLUE_KING, BLUE_CARDS)
+        };
+        if player.king < 25 {
+            hash ^= KEYS[king + s.pos(player.king) as usize];
+        }
+        hash ^= card_key(cards, s.card(player.cards[0])) ^ card_key(cards, s.card(player.cards[1]));
+    }
+    if (g.colour == Colour::Blue) != s.rotate {
+        hash ^= KEYS[BLUE_TO_MOVE];
+    }
+    hash
+}
+
+impl SymmetricHashes {
+    // with `mirror` the mirrored positions share a hash too when the cards allow it
+    pub fn new(g: &Game, mirror: bool) -> SymmetricHashes {
+        let count = if mirror && g.can_mirror() { 4 } else { 2 };
+        let (red, blue) = g.get_red_blue();
+        let mut hashes = [0; 4];
+        for (hash, &s) in hashes.iter_mut().zip(&SYMMETRIES[..count]) {
+            *hash = piece_keys(s, true, red.pieces)
+                ^ piece_keys(s, false, blue.pieces)
+                ^ spirit_keys(s, g.spirit)
+                ^ other_keys(s, g);
+        }
+        SymmetricHashes { hashes, count }
+    }
+
+    // the hashes of the position after a move from `before`, whose hashes these are
+    pub fn after(&self, before: &Game, after: &Game) -> SymmetricHashes {
+        let (red_before, blue_before) = before.get_red_blue();
+        let (red_after, blue_after) = after.get_red_blue();
+        let red = red_before.pieces ^ red_after.pieces;
+        let blue = blue_before.pieces ^ blue_after.pieces;
+        let spirit = before.spirit ^ after.spirit;
+        let mut hashes = self.hashes;
+        for (hash, &s) in hashes.iter_mut().zip(&SYMMETRIES[..self.count]) {
+            *hash ^= piece_keys(s, true, red)
+                ^ piece_keys(s, false, blue)
+                ^ spirit_keys(s, spirit)
+                ^ other_keys(s, before)
+                ^ other_keys(s, after);
+        }
+        SymmetricHashes {
+            hashes,
+            count: self.count,
+        }
+    }
+
+    // the hash of the position itself, the same as Game::hash
+    pub fn hash(&self) -> u64 {
+        self.hashes[0]
+    }
+
+    // the same for every symmetric position, with the symmetry that turns the position
+    // into the one that was hashed
+    pub fn canonical(&self) -> (u64, Symmetry) {
+        self.hashes[..self.count]
+            .iter()
+            .zip(SYMMETRIES.iter())
+            .map(|(&hash, &s)| (hash, s))
+            .min_by_key(|&(hash, _)| hash)
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Move;
+
+    const CARDS: [Card; 5] = [
+        Card::Ox,
+        Card::Boar,
+        Card::Horse,
+        Card::Elephant,
+        Card::Crab,
+    ];
+
+    #[test]
+    fn test_keys_unique() {
+        for (i, key) in KEYS.iter().enumerate() {
+            assert!(!KEYS[..i].contains(key));
+        }
+    }
+
+    #[test]
+    fn test_hash_side_to_move() {
+        let game = Game::from_cards(Vec::from(CARDS));
+        let mut other_side = game.clone();
+        std::mem::swap(&mut other_side.my, &mut other_side.other);
+        other_side.colour = game.colour.next();
+        assert_ne!(game.hash(), other_side.hash());
+    }
+
+    #[test]
+    fn test_hash_card_order() {
+        let game = Game::from_cards(Vec::from(CARDS));
+        let mut swapped = game.clone();
+        swapped.my.cards.swap(0, 1);
+        assert_eq!(game.hash(), swapped.hash());
+        let skip = Move {
+            from: game.my.king,
+            to: game.my.king,
+            used_left_card: true,
+            spirit: None,
+        };
+        assert_ne!(game.hash(), game.take_turn(&skip).hash());
+    }
+
+    #[test]
+    fn test_symmetric_hashes() {
+        let mut game =
+            Game::from_notation("1bB1b/2b2/1r1w1/R4/3rr r frog,ox eel,bat tiger").unwrap();
+        let mut hashes = SymmetricHashes::new(&game, true);
+        for ply in 0..20 {
+            for (i, &s) in SYMMETRIES.iter().enumerate() {
+                assert_eq!(hashes.hashes[i], game.transform(s).hash());
+            }
+            assert_eq!(hashes.hash(), game.hash());
+            if !game.in_progress {
+                break;
+            }
+            let moves = game.gen_moves();
+            let next = game.take_turn(&moves[ply * 7 % moves.len()]);
+            hashes = hashes.after(&game, &next);
+            game = next;
+        }
+        // without the mirror only the rotation is shared
+        let (_, s) = SymmetricHashes::new(&game, false).canonical();
+        assert!(!s.mirror);
+    }
+}